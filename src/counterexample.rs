@@ -0,0 +1,277 @@
+//! Counterexamples for the conflicts that the solver reports.
+//!
+//! A conflict only says which tables overlap, which is hard to relate to the inputs where it
+//! matters. For each conflict, this finds the shortest sentence of the grammar where the
+//! choice that the interpreter makes is the wrong one for at least one of its trees: the
+//! match of a shadowed first set or of a later follow set is used where the tokens that are
+//! tried first also start, or the wraps of more than one candidate are used. Sentences are
+//! parsed into a forest with the Earley parser, so when an input is ambiguous, its trees show
+//! the different ways that it could be read.
+
+use crate::{
+    differential::TokensDisplay,
+    earley::EarleyParser,
+    generate::sentences,
+    glr::forest::{Forest, NodeKind},
+    interpreter::{ITokenOrGroup, RuleValue},
+    matches::{Grammar, MatchId},
+    solver::{ConflictId, ConflictReport, FollowOverlapWith, TokenOrGroup},
+};
+
+/// Options for looking for counterexamples.
+#[derive(Debug, Clone)]
+pub struct CounterexampleOptions {
+    /// The most tokens in a counterexample, in the flattened token stream.
+    pub max_len: usize,
+    /// The most sentences of each length to look through, for each rule. A conflict that
+    /// only shows in sentences past the limit gets no counterexample.
+    pub limit: usize,
+}
+
+impl Default for CounterexampleOptions {
+    fn default() -> Self {
+        Self {
+            max_len: 8,
+            limit: 50,
+        }
+    }
+}
+
+/// An input that shows a conflict, with its trees.
+#[derive(Debug, Clone)]
+pub struct Counterexample {
+    pub conflict: ConflictId,
+    pub tokens: Vec<ITokenOrGroup>,
+    /// The trees of the input. There are two if the input is ambiguous, and the first of them
+    /// otherwise.
+    pub trees: Vec<RuleValue>,
+}
+
+impl Counterexample {
+    pub fn is_ambiguous(&self) -> bool {
+        self.trees.len() > 1
+    }
+
+    /// Display the conflict that this is a counterexample for, the input and its trees.
+    pub fn display<'a>(
+        &'a self,
+        grammar: &'a Grammar,
+        report: &'a ConflictReport,
+    ) -> CounterexampleDisplay<'a> {
+        CounterexampleDisplay {
+            counterexample: self,
+            grammar,
+            report,
+        }
+    }
+}
+
+/// Find a counterexample for every conflict of the report that has one within the length of
+/// the options. Among the shortest inputs that show a conflict, an ambiguous one is preferred.
+pub fn counterexamples(
+    grammar: &Grammar,
+    report: &ConflictReport,
+    options: &CounterexampleOptions,
+) -> Vec<Counterexample> {
+    if report.is_empty() {
+        return Vec::new();
+    }
+
+    let parser = EarleyParser::new(grammar.clone());
+    let parses: Vec<_> = sentences(grammar, options.max_len, options.limit)
+        .items
+        .into_iter()
+        .filter_map(|tokens| {
+            let parse = parser.parse_forest(tokens.clone(), None);
+            parse
+                .diagnostics
+                .is_empty()
+                .then_some((tokens, parse.forest))
+        })
+        .collect();
+
+    let mut counterexamples = Vec::new();
+    for id in report.ids() {
+        let mut found: Option<&(Vec<ITokenOrGroup>, Forest)> = None;
+        for parse in &parses {
+            let (tokens, forest) = parse;
+            if found.is_some_and(|(shortest, _)| flat_len(shortest) < flat_len(tokens)) {
+                break;
+            }
+
+            if !shows_conflict(grammar, report, id, tokens, forest) {
+                continue;
+            }
+
+            if found.is_none() || forest.is_ambiguous() {
+                found = Some(parse);
+            }
+            if forest.is_ambiguous() {
+                break;
+            }
+        }
+
+        if let Some((tokens, forest)) = found {
+            counterexamples.push(Counterexample {
+                conflict: id,
+                tokens: tokens.clone(),
+                trees: forest.trees(grammar, 2),
+            });
+        }
+    }
+
+    counterexamples
+}
+
+/// Whether the parse of the input uses the choice that the interpreter doesn't make.
+fn shows_conflict(
+    grammar: &Grammar,
+    report: &ConflictReport,
+    id: ConflictId,
+    tokens: &[ITokenOrGroup],
+    forest: &Forest,
+) -> bool {
+    let reachable = forest.reachable();
+    let families = || {
+        reachable.iter().flat_map(|&node_id| {
+            let node = forest.node(node_id);
+            node.families.iter().map(move |family| (node, family))
+        })
+    };
+    let uses = |id: MatchId| families().any(|(_, family)| family.match_id == id);
+
+    match id {
+        ConflictId::FirstSet(i) => {
+            // The rule starts where the tokens of the match that's tried first are, and the
+            // shadowed match starts there too
+            let overlap = &report.first_sets[i];
+            let starts = |position: usize| {
+                families().any(|(node, family)| {
+                    family.match_id == overlap.shadowed && node.tokens.start == position
+                })
+            };
+
+            families().any(|(node, _)| {
+                matches!(node.kind, NodeKind::Rule(rule) if rule == overlap.rule)
+                    && starts(node.tokens.start)
+                    && items_at(tokens, node.tokens.start)
+                        .iter()
+                        .any(|items| starts_with(items, &overlap.taken_tokens))
+            })
+        }
+        ConflictId::Competing(i) => {
+            // The wraps of the kept candidate and of another one are both used
+            let competing = &report.competing[i];
+            let uses_all = |wraps: &[MatchId]| wraps.iter().all(|&id| uses(id));
+            let (kept, _) = &competing.candidates[competing.kept];
+
+            competing.if_matches.is_none_or(|mi| uses(mi.id))
+                && uses_all(kept)
+                && competing
+                    .candidates
+                    .iter()
+                    .enumerate()
+                    .any(|(i, (wraps, _))| i != competing.kept && uses_all(wraps))
+        }
+        ConflictId::FollowSet(i) => {
+            // The later match index is reached where the tokens that are wrapped into first are
+            let overlap = &report.follow_sets[i];
+            let (FollowOverlapWith::Wrap(mi) | FollowOverlapWith::Insert(mi)) = overlap.other;
+            let len = grammar.get(mi.id).terms.len();
+
+            families().any(|(node, family)| {
+                if family.match_id != mi.id || mi.index > len {
+                    return false;
+                }
+
+                let position = match mi.index {
+                    0 => node.tokens.start,
+                    index => forest.node(family.children[index - 1]).tokens.end,
+                };
+                items_at(tokens, position)
+                    .iter()
+                    .any(|items| starts_with(items, &overlap.tokens))
+            })
+        }
+    }
+}
+
+fn flat_len(tokens: &[ITokenOrGroup]) -> usize {
+    tokens.iter().map(|item| item.flat_len()).sum()
+}
+
+/// The items that start at the position in the flattened token stream, at each level of
+/// groups that the position is in, outermost first.
+fn items_at(items: &[ITokenOrGroup], position: usize) -> Vec<&[ITokenOrGroup]> {
+    let mut levels = Vec::new();
+    let mut start = 0;
+
+    for (i, item) in items.iter().enumerate() {
+        if start > position {
+            return levels;
+        }
+
+        if start == position {
+            levels.push(&items[i..]);
+        }
+
+        let end = start + item.flat_len();
+        if let ITokenOrGroup::Group(inner) = item {
+            if (start..end).contains(&position) {
+                levels.extend(items_at(inner, position - start));
+            }
+        }
+        start = end;
+    }
+
+    if start == position {
+        levels.push(&items[items.len()..]);
+    }
+
+    levels
+}
+
+/// Whether the items start with the tokens, matching them the way the interpreter does.
+fn starts_with(items: &[ITokenOrGroup], tokens: &[TokenOrGroup]) -> bool {
+    items.len() >= tokens.len()
+        && items.iter().zip(tokens).all(|pair| match pair {
+            (ITokenOrGroup::Token(a), TokenOrGroup::Token(b)) => a == b,
+            (ITokenOrGroup::Group(_), TokenOrGroup::Group(_, _)) => true,
+            _ => false,
+        })
+}
+
+pub struct CounterexampleDisplay<'a> {
+    counterexample: &'a Counterexample,
+    grammar: &'a Grammar,
+    report: &'a ConflictReport,
+}
+
+impl std::fmt::Display for CounterexampleDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let counterexample = self.counterexample;
+        writeln!(
+            f,
+            "{}",
+            self.report
+                .display_conflict(counterexample.conflict, self.grammar)
+        )?;
+        writeln!(
+            f,
+            "counterexample: {}",
+            TokensDisplay(&counterexample.tokens)
+        )?;
+
+        if counterexample.is_ambiguous() {
+            for (i, tree) in counterexample.trees.iter().enumerate() {
+                write!(f, "tree {}: {}", i + 1, tree)?;
+            }
+        } else {
+            for tree in &counterexample.trees {
+                write!(f, "tree: {}", tree)?;
+            }
+        }
+
+        Ok(())
+    }
+}
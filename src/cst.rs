@@ -0,0 +1,11 @@
+//! A concrete syntax tree built from the output of a parse, in two layers. The green tree
+//! is immutable and shareable, and only stores kinds and widths. The red tree is a cursor
+//! over the green tree that knows its parent and its absolute offset in the input.
+//!
+//! Offsets are in bytes when the parse was given byte ranges, and in flattened token
+//! positions otherwise. Parts of the input that aren't covered by any value (e.g. whitespace
+//! between tokens, or tokens skipped during error recovery) become gap tokens, so that the
+//! tree covers the input without holes.
+
+pub mod green;
+pub mod red;
@@ -0,0 +1,204 @@
+use std::{ops::Range, sync::Arc};
+
+use crate::{
+    interpreter::{RuleValue, Value},
+    matches::{MatchId, Rule, Token},
+    span::Span,
+};
+
+/// The kind of a leaf in the tree.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum TokenKind {
+    Token(Token),
+    /// Input that was replaced by an error during recovery.
+    Error,
+    /// Input that isn't part of any value.
+    Gap,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct GreenToken(Arc<GreenTokenData>);
+
+#[derive(Debug, Hash, PartialEq, Eq)]
+struct GreenTokenData {
+    kind: TokenKind,
+    width: usize,
+}
+
+impl GreenToken {
+    pub fn new(kind: TokenKind, width: usize) -> Self {
+        Self(Arc::new(GreenTokenData { kind, width }))
+    }
+
+    pub fn kind(&self) -> TokenKind {
+        self.0.kind
+    }
+
+    pub fn width(&self) -> usize {
+        self.0.width
+    }
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct GreenNode(Arc<GreenNodeData>);
+
+#[derive(Debug, Hash, PartialEq, Eq)]
+struct GreenNodeData {
+    rule: Rule,
+    match_id: MatchId,
+    is_error: bool,
+    width: usize,
+    children: Vec<GreenChild>,
+}
+
+/// A child of a green node, with its offset relative to the start of the node.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct GreenChild {
+    pub offset: usize,
+    pub element: GreenElement,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub enum GreenElement {
+    Node(GreenNode),
+    Token(GreenToken),
+}
+
+impl GreenElement {
+    pub fn width(&self) -> usize {
+        match self {
+            GreenElement::Node(node) => node.width(),
+            GreenElement::Token(token) => token.width(),
+        }
+    }
+}
+
+impl GreenNode {
+    pub fn new(rule: Rule, match_id: MatchId, is_error: bool, elements: Vec<GreenElement>) -> Self {
+        let mut width = 0;
+        let children = elements
+            .into_iter()
+            .map(|element| {
+                let offset = width;
+                width += element.width();
+                GreenChild { offset, element }
+            })
+            .collect();
+
+        Self(Arc::new(GreenNodeData {
+            rule,
+            match_id,
+            is_error,
+            width,
+            children,
+        }))
+    }
+
+    pub fn rule(&self) -> Rule {
+        self.0.rule
+    }
+
+    pub fn match_id(&self) -> MatchId {
+        self.0.match_id
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.0.is_error
+    }
+
+    pub fn width(&self) -> usize {
+        self.0.width
+    }
+
+    pub fn children(&self) -> &[GreenChild] {
+        &self.0.children
+    }
+
+    /// Whether both nodes share the same data, rather than just being equal.
+    pub fn ptr_eq(&self, other: &GreenNode) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Build a green tree from the value of a parse. The tree starts at offset 0, so any input
+/// before the first token becomes a gap.
+pub fn build_green_tree(value: &RuleValue) -> GreenNode {
+    let use_bytes = value.span.bytes.is_some();
+    let mut builder = GreenBuilder {
+        use_bytes,
+        offset: 0,
+    };
+
+    let mut root = builder.build_node(value);
+
+    // Cover the rest of the input, if the root doesn't reach it.
+    let end = builder.get_range(&value.span).end;
+    if builder.offset < end {
+        let mut elements: Vec<_> = root.children().iter().map(|c| c.element.clone()).collect();
+        elements.push(GreenElement::Token(GreenToken::new(
+            TokenKind::Gap,
+            end - builder.offset,
+        )));
+        root = GreenNode::new(root.rule(), root.match_id(), root.is_error(), elements);
+    }
+
+    root
+}
+
+struct GreenBuilder {
+    use_bytes: bool,
+    /// The end of the last leaf that was added.
+    offset: usize,
+}
+
+impl GreenBuilder {
+    fn get_range(&self, span: &Span) -> Range<usize> {
+        if self.use_bytes {
+            span.bytes.clone().unwrap_or(span.tokens.clone())
+        } else {
+            span.tokens.clone()
+        }
+    }
+
+    fn build_node(&mut self, value: &RuleValue) -> GreenNode {
+        let mut elements = Vec::new();
+
+        for value in &value.values {
+            match value {
+                Value::Token(token, span) => {
+                    self.push_leaf(TokenKind::Token(*token), span, &mut elements)
+                }
+                Value::Error(span) => self.push_leaf(TokenKind::Error, span, &mut elements),
+                Value::Rule(rule) => {
+                    // Gaps go outside of nodes, so that node ranges match their spans.
+                    if !rule.span.is_empty() {
+                        self.push_gap(self.get_range(&rule.span).start, &mut elements);
+                    }
+
+                    let node = self.build_node(rule);
+                    elements.push(GreenElement::Node(node));
+                }
+            }
+        }
+
+        GreenNode::new(value.rule, value.match_id, value.is_error, elements)
+    }
+
+    /// Add a leaf, preceded by a gap if there's input between it and the previous leaf.
+    fn push_leaf(&mut self, kind: TokenKind, span: &Span, elements: &mut Vec<GreenElement>) {
+        let range = self.get_range(span);
+        self.push_gap(range.start, elements);
+
+        let width = range.end.saturating_sub(self.offset);
+        elements.push(GreenElement::Token(GreenToken::new(kind, width)));
+        self.offset += width;
+    }
+
+    fn push_gap(&mut self, until: usize, elements: &mut Vec<GreenElement>) {
+        if until > self.offset {
+            let gap = GreenToken::new(TokenKind::Gap, until - self.offset);
+            elements.push(GreenElement::Token(gap));
+            self.offset = until;
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use std::{ops::Range, rc::Rc};
+
+use crate::matches::{MatchId, Rule};
+
+use super::green::{GreenElement, GreenNode, GreenToken, TokenKind};
+
+/// A node in the red tree. Red nodes are created on demand while walking the tree, and
+/// are cheap to clone.
+#[derive(Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+struct NodeData {
+    green: GreenNode,
+    parent: Option<SyntaxNode>,
+    /// The index of this node within its parent's children.
+    index: usize,
+    offset: usize,
+}
+
+#[derive(Clone)]
+pub struct SyntaxToken {
+    green: GreenToken,
+    parent: SyntaxNode,
+    index: usize,
+    offset: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl SyntaxNode {
+    pub fn new_root(green: GreenNode) -> Self {
+        Self(Rc::new(NodeData {
+            green,
+            parent: None,
+            index: 0,
+            offset: 0,
+        }))
+    }
+
+    pub fn green(&self) -> &GreenNode {
+        &self.0.green
+    }
+
+    pub fn rule(&self) -> Rule {
+        self.0.green.rule()
+    }
+
+    pub fn match_id(&self) -> MatchId {
+        self.0.green.match_id()
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.0.green.is_error()
+    }
+
+    pub fn text_range(&self) -> Range<usize> {
+        self.0.offset..self.0.offset + self.0.green.width()
+    }
+
+    pub fn parent(&self) -> Option<SyntaxNode> {
+        self.0.parent.clone()
+    }
+
+    /// This node and all of its ancestors, from the innermost outwards.
+    pub fn ancestors(&self) -> impl Iterator<Item = SyntaxNode> {
+        std::iter::successors(Some(self.clone()), |node| node.parent())
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = SyntaxNode> + '_ {
+        self.children_with_tokens().filter_map(|e| match e {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    pub fn children_with_tokens(&self) -> impl Iterator<Item = SyntaxElement> + '_ {
+        (0..self.0.green.children().len()).filter_map(|i| self.child_at(i))
+    }
+
+    pub fn first_child_or_token(&self) -> Option<SyntaxElement> {
+        self.child_at(0)
+    }
+
+    pub fn last_child_or_token(&self) -> Option<SyntaxElement> {
+        let len = self.0.green.children().len();
+        self.child_at(len.checked_sub(1)?)
+    }
+
+    pub fn next_sibling(&self) -> Option<SyntaxNode> {
+        let parent = self.parent()?;
+        let len = parent.0.green.children().len();
+        (self.0.index + 1..len).find_map(|i| parent.child_at(i)?.into_node())
+    }
+
+    pub fn prev_sibling(&self) -> Option<SyntaxNode> {
+        let parent = self.parent()?;
+        (0..self.0.index)
+            .rev()
+            .find_map(|i| parent.child_at(i)?.into_node())
+    }
+
+    pub fn next_sibling_or_token(&self) -> Option<SyntaxElement> {
+        self.parent()?.child_at(self.0.index + 1)
+    }
+
+    pub fn prev_sibling_or_token(&self) -> Option<SyntaxElement> {
+        self.parent()?.child_at(self.0.index.checked_sub(1)?)
+    }
+
+    /// The innermost element whose range contains the offset. Empty elements never cover
+    /// an offset, and an offset at the boundary of two elements is covered by the second.
+    pub fn covering_element(&self, offset: usize) -> Option<SyntaxElement> {
+        if !self.text_range().contains(&offset) {
+            return None;
+        }
+
+        let mut node = self.clone();
+        loop {
+            let relative = offset - node.0.offset;
+            let children = node.0.green.children();
+
+            // Children are contiguous, so their ends are in order.
+            let index =
+                children.partition_point(|child| child.offset + child.element.width() <= relative);
+
+            match node.child_at(index) {
+                Some(SyntaxElement::Node(child)) => node = child,
+                Some(SyntaxElement::Token(token)) => return Some(SyntaxElement::Token(token)),
+                None => return Some(SyntaxElement::Node(node)),
+            }
+        }
+    }
+
+    fn child_at(&self, index: usize) -> Option<SyntaxElement> {
+        let child = self.0.green.children().get(index)?;
+        let offset = self.0.offset + child.offset;
+
+        let element = match &child.element {
+            GreenElement::Node(green) => SyntaxElement::Node(SyntaxNode(Rc::new(NodeData {
+                green: green.clone(),
+                parent: Some(self.clone()),
+                index,
+                offset,
+            }))),
+            GreenElement::Token(green) => SyntaxElement::Token(SyntaxToken {
+                green: green.clone(),
+                parent: self.clone(),
+                index,
+                offset,
+            }),
+        };
+
+        Some(element)
+    }
+}
+
+impl SyntaxToken {
+    pub fn green(&self) -> &GreenToken {
+        &self.green
+    }
+
+    pub fn kind(&self) -> TokenKind {
+        self.green.kind()
+    }
+
+    pub fn text_range(&self) -> Range<usize> {
+        self.offset..self.offset + self.green.width()
+    }
+
+    pub fn parent(&self) -> SyntaxNode {
+        self.parent.clone()
+    }
+
+    pub fn next_sibling_or_token(&self) -> Option<SyntaxElement> {
+        self.parent.child_at(self.index + 1)
+    }
+
+    pub fn prev_sibling_or_token(&self) -> Option<SyntaxElement> {
+        self.parent.child_at(self.index.checked_sub(1)?)
+    }
+}
+
+impl SyntaxElement {
+    pub fn text_range(&self) -> Range<usize> {
+        match self {
+            SyntaxElement::Node(node) => node.text_range(),
+            SyntaxElement::Token(token) => token.text_range(),
+        }
+    }
+
+    pub fn parent(&self) -> Option<SyntaxNode> {
+        match self {
+            SyntaxElement::Node(node) => node.parent(),
+            SyntaxElement::Token(token) => Some(token.parent()),
+        }
+    }
+
+    pub fn into_node(self) -> Option<SyntaxNode> {
+        match self {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        }
+    }
+
+    pub fn into_token(self) -> Option<SyntaxToken> {
+        match self {
+            SyntaxElement::Node(_) => None,
+            SyntaxElement::Token(token) => Some(token),
+        }
+    }
+}
+
+/// Red nodes are equal when they point to the same green node at the same offset.
+impl PartialEq for SyntaxNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.green.ptr_eq(&other.0.green) && self.0.offset == other.0.offset
+    }
+}
+
+impl Eq for SyntaxNode {}
+
+impl PartialEq for SyntaxToken {
+    fn eq(&self, other: &Self) -> bool {
+        self.parent == other.parent && self.index == other.index
+    }
+}
+
+impl Eq for SyntaxToken {}
+
+impl std::fmt::Debug for SyntaxNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}@{:?}", self.rule(), self.text_range())
+    }
+}
+
+impl std::fmt::Debug for SyntaxToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}@{:?}", self.kind(), self.text_range())
+    }
+}
+
+/// Prints the whole tree below the node, one element per line.
+impl std::fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_tree(f, 0)
+    }
+}
+
+impl SyntaxNode {
+    fn fmt_tree(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        writeln!(f, "{}{:?}", "    ".repeat(depth), self)?;
+
+        for child in self.children_with_tokens() {
+            match child {
+                SyntaxElement::Node(node) => node.fmt_tree(f, depth + 1)?,
+                SyntaxElement::Token(token) => {
+                    writeln!(f, "{}{:?}", "    ".repeat(depth + 1), token)?
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
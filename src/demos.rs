@@ -0,0 +1,229 @@
+//! Small grammars that show what the solver and the parsers can do, and that the tests run
+//! against.
+
+use crate::matches::*;
+
+fn set_binary_fields(grammar: &mut Grammar, id: MatchId) {
+    grammar.set_field_name(id, 0, "lhs");
+    grammar.set_field_name(id, 1, "op");
+    grammar.set_field_name(id, 2, "rhs");
+}
+
+pub fn make_calc_grammar() -> Grammar {
+    let mut grammar = Grammar::new();
+    grammar.add(
+        Rule::S,
+        vec![
+            Term::Token(Token::Start),
+            Term::Rule(Rule::Expr),
+            Term::Token(Token::Eof),
+        ],
+    );
+    grammar.add(Rule::Expr, vec![Term::Rule(Rule::Add)]);
+    let add = grammar.add(
+        Rule::Add,
+        vec![
+            Term::Rule(Rule::Add),
+            Term::Token(Token::Plus),
+            Term::Rule(Rule::Mul),
+        ],
+    );
+    set_binary_fields(&mut grammar, add);
+    grammar.set_break(add, 1, Break::Line);
+    grammar.add(Rule::Add, vec![Term::Rule(Rule::Mul)]);
+    let mul = grammar.add(
+        Rule::Mul,
+        vec![
+            Term::Rule(Rule::Mul),
+            Term::Token(Token::Star),
+            Term::Rule(Rule::Term),
+        ],
+    );
+    set_binary_fields(&mut grammar, mul);
+    grammar.add(Rule::Mul, vec![Term::Rule(Rule::Term)]);
+    grammar.add(Rule::Term, vec![Term::Token(Token::Num)]);
+
+    grammar.add(Rule::Term, vec![Term::Group(Group::Parens, Rule::S)]);
+    grammar.set_group_delimiters(Group::Parens, Token::LParen, Token::RParen);
+    grammar.add(
+        Rule::Term,
+        vec![
+            Term::Token(Token::LParen),
+            Term::Rule(Rule::Expr),
+            Term::Token(Token::RParen),
+        ],
+    );
+
+    grammar
+}
+
+pub fn make_calc2_grammar() -> Grammar {
+    let mut grammar = Grammar::new();
+    grammar.add(
+        Rule::S,
+        vec![
+            Term::Token(Token::Start),
+            Term::Rule(Rule::Expr),
+            Term::Token(Token::Eof),
+        ],
+    );
+    grammar.add(Rule::Expr, vec![Term::Rule(Rule::Add)]);
+    let add = grammar.add(
+        Rule::Add,
+        vec![
+            Term::Rule(Rule::Add),
+            Term::Rule(Rule::Op2),
+            Term::Rule(Rule::Mul),
+        ],
+    );
+    set_binary_fields(&mut grammar, add);
+    grammar.add(Rule::Add, vec![Term::Rule(Rule::Mul)]);
+    let mul = grammar.add(
+        Rule::Mul,
+        vec![
+            Term::Rule(Rule::Mul),
+            Term::Rule(Rule::Op1),
+            Term::Rule(Rule::Term),
+        ],
+    );
+    set_binary_fields(&mut grammar, mul);
+    grammar.add(Rule::Mul, vec![Term::Rule(Rule::Term)]);
+    grammar.add(Rule::Term, vec![Term::Token(Token::Num)]);
+
+    grammar.add(Rule::Op1, vec![Term::Token(Token::Star)]);
+    grammar.add(Rule::Op1, vec![Term::Token(Token::Slash)]);
+
+    grammar.add(Rule::Op2, vec![Term::Token(Token::Plus)]);
+    grammar.add(Rule::Op2, vec![Term::Token(Token::Minus)]);
+
+    grammar.add(Rule::Term, vec![Term::Group(Group::Parens, Rule::S)]);
+    grammar.set_group_delimiters(Group::Parens, Token::LParen, Token::RParen);
+    grammar.add(
+        Rule::Term,
+        vec![
+            Term::Token(Token::LParen),
+            Term::Rule(Rule::Expr),
+            Term::Token(Token::RParen),
+        ],
+    );
+
+    grammar
+}
+
+pub fn make_struct_fn_grammar() -> Grammar {
+    let mut grammar = Grammar::new();
+    grammar.add(
+        Rule::S,
+        vec![
+            Term::Token(Token::Start),
+            Term::Rule(Rule::Expr),
+            Term::Token(Token::Eof),
+        ],
+    );
+    grammar.add(Rule::Expr, vec![Term::Rule(Rule::Struct)]);
+    grammar.add(Rule::Expr, vec![Term::Rule(Rule::Fn)]);
+    grammar.add(
+        Rule::Struct,
+        vec![Term::Rule(Rule::Vis), Term::Token(Token::Struct)],
+    );
+    let function = grammar.add(
+        Rule::Fn,
+        vec![
+            Term::Rule(Rule::Vis),
+            Term::Token(Token::Fn),
+            Term::Token(Token::Name),
+        ],
+    );
+    // Once `fn` is seen, errors are about the function rather than the whole item
+    grammar.set_commit_point(function, 2, "function");
+    grammar.add(Rule::Vis, vec![]);
+    grammar.add(
+        Rule::Vis,
+        vec![Term::Token(Token::Pub), Term::Rule(Rule::VisModifier)],
+    );
+    grammar.add(Rule::VisModifier, vec![Term::Token(Token::Star)]);
+    grammar.add(Rule::VisModifier, vec![]);
+
+    grammar
+}
+
+pub fn make_array_grammar() -> Grammar {
+    let mut grammar = Grammar::new();
+    grammar.add(
+        Rule::S,
+        vec![
+            Term::Token(Token::Start),
+            Term::Rule(Rule::Expr),
+            Term::Token(Token::Eof),
+        ],
+    );
+    grammar.add(
+        Rule::Expr,
+        vec![Term::Rule(Rule::Expr), Term::Rule(Rule::Term)],
+    );
+    grammar.add(Rule::Expr, vec![Term::Rule(Rule::Term)]);
+    grammar.add(Rule::Expr, vec![]);
+    grammar.add(Rule::Term, vec![Term::Token(Token::Num)]);
+
+    grammar
+}
+
+pub fn make_statements_grammar() -> Grammar {
+    let mut grammar = Grammar::new();
+    grammar.add(
+        Rule::S,
+        vec![
+            Term::Token(Token::Start),
+            Term::Rule(Rule::Stmts),
+            Term::Token(Token::Eof),
+        ],
+    );
+    let stmts = grammar.add(
+        Rule::Stmts,
+        vec![Term::Rule(Rule::Stmts), Term::Rule(Rule::Stmt)],
+    );
+    grammar.set_break(stmts, 1, Break::HardLine);
+    grammar.add(Rule::Stmts, vec![Term::Rule(Rule::Stmt)]);
+    grammar.add(Rule::Stmts, vec![]);
+    grammar.add(
+        Rule::Stmt,
+        vec![
+            Term::Token(Token::Name),
+            Term::Token(Token::Eq),
+            Term::Rule(Rule::Expr),
+            Term::Token(Token::Semi),
+        ],
+    );
+    grammar.add(Rule::Expr, vec![Term::Token(Token::Num)]);
+    grammar.add(Rule::Expr, vec![Term::Token(Token::Name)]);
+
+    grammar.add_recovery(
+        Rule::Stmt,
+        vec![
+            Term::Token(Token::Name),
+            Term::Token(Token::Eq),
+            Term::Rule(Rule::Expr),
+        ],
+        "missing semicolon after statement",
+    );
+    grammar.add_recovery(
+        Rule::Stmt,
+        vec![
+            Term::Token(Token::Name),
+            Term::Rule(Rule::Expr),
+            Term::Token(Token::Semi),
+        ],
+        "missing `=` in assignment",
+    );
+
+    grammar.set_name(Rule::Stmt, "statement");
+    grammar.set_description(Rule::Stmt, "a statement, such as `x = 1;`");
+    grammar.set_name(Rule::Expr, "expression");
+    grammar.set_name(Token::Name, "identifier");
+    grammar.set_name(Token::Num, "number");
+    grammar.set_name(Token::Eq, "`=`");
+    grammar.set_name(Token::Semi, "`;`");
+    grammar.set_name(Token::Eof, "end of input");
+
+    grammar
+}
@@ -0,0 +1,274 @@
+use crate::{
+    matches::{Grammar, MatchId, Symbol, Token},
+    solver::GrammarSolver,
+};
+
+/// A problem found while parsing. Token indexes point into the flattened token stream,
+/// where each group is replaced by the tokens inside of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub token_index: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DiagnosticKind {
+    /// A recovery match from the grammar was used to parse a known mistake.
+    RecoveryMatch { match_id: MatchId, message: String },
+    /// The parser couldn't continue with the next token. The context is the label of the
+    /// innermost match that the parser was committed to, if any.
+    Unexpected {
+        expected: Vec<Symbol>,
+        found: Found,
+        context: Option<String>,
+    },
+    /// The root match ended before the input did. The rest of the input is left out of the
+    /// tree.
+    Trailing { found: Found },
+}
+
+/// The input item that the parser failed on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Found {
+    Token(Token),
+    Group,
+    End,
+}
+
+impl Diagnostic {
+    pub fn new(kind: DiagnosticKind, token_index: usize) -> Self {
+        Self { kind, token_index }
+    }
+
+    /// Display the diagnostic using the symbol names from the grammar.
+    pub fn display<'a>(&'a self, grammar: &'a Grammar) -> DiagnosticDisplay<'a> {
+        DiagnosticDisplay {
+            diagnostic: self,
+            grammar,
+        }
+    }
+}
+
+/// Options for limiting the errors that get reported while parsing. By default, every error
+/// is reported.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ErrorOptions {
+    /// The maximum number of errors to report. Errors past this are only counted.
+    pub max_errors: Option<usize>,
+    /// Errors within this many tokens of a previous error are suppressed as cascades.
+    pub cascade_distance: usize,
+    /// Only report the first error inside each group.
+    pub one_error_per_group: bool,
+}
+
+/// The number of errors found during a parse, and what happened to them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ErrorCounts {
+    pub reported: usize,
+    /// Errors suppressed because they were likely caused by a previous error.
+    pub suppressed: usize,
+    /// Errors dropped because the error limit was reached.
+    pub over_limit: usize,
+}
+
+/// Collects diagnostics during a parse, applying the limits from the error options.
+/// A single reporter is shared by the interpreters of all nested groups.
+#[derive(Debug, Clone, Default)]
+pub struct ErrorReporter {
+    options: ErrorOptions,
+    diagnostics: Vec<Diagnostic>,
+    /// Every diagnostic that was reported, including suppressed ones.
+    history: Vec<Diagnostic>,
+    counts: ErrorCounts,
+    /// The token index of the last error, including suppressed ones.
+    last_error_at: Option<usize>,
+    group_depth: usize,
+    /// Whether an error was already found inside the current group.
+    group_errored: bool,
+}
+
+impl ErrorReporter {
+    pub fn new(options: ErrorOptions) -> Self {
+        Self {
+            options,
+            ..Default::default()
+        }
+    }
+
+    pub fn report(&mut self, diagnostic: Diagnostic) {
+        let is_cascade = self.last_error_at.is_some_and(|last| {
+            let distance = diagnostic.token_index.abs_diff(last);
+            distance <= self.options.cascade_distance && self.options.cascade_distance > 0
+        });
+        let is_in_errored_group =
+            self.options.one_error_per_group && self.group_depth > 0 && self.group_errored;
+
+        self.last_error_at = Some(diagnostic.token_index);
+        self.group_errored = true;
+        self.history.push(diagnostic.clone());
+
+        if is_cascade || is_in_errored_group {
+            self.counts.suppressed += 1;
+        } else if self
+            .options
+            .max_errors
+            .is_some_and(|max| self.counts.reported >= max)
+        {
+            self.counts.over_limit += 1;
+        } else {
+            self.counts.reported += 1;
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    /// Start reporting errors for a nested group. Returns whether the outer group had errors,
+    /// which should be passed to `exit_group` afterwards.
+    pub fn enter_group(&mut self) -> bool {
+        self.group_depth += 1;
+        std::mem::replace(&mut self.group_errored, false)
+    }
+
+    pub fn exit_group(&mut self, outer_errored: bool) {
+        self.group_depth -= 1;
+        self.group_errored = outer_errored;
+    }
+
+    /// Every diagnostic that was reported so far, before any limits were applied. Reporting
+    /// them again in the same order gives the same results.
+    pub fn history(&self) -> &[Diagnostic] {
+        &self.history
+    }
+
+    pub fn finish(self) -> (Vec<Diagnostic>, ErrorCounts) {
+        (self.diagnostics, self.counts)
+    }
+}
+
+/// Simplify a set of expected symbols for display. Rules without a name are expanded into
+/// the tokens they can start with, and tokens that mostly cover the first set of a named
+/// rule are collapsed into that rule.
+pub fn collapse_expected(solver: &GrammarSolver, expected: &[Symbol]) -> Vec<Symbol> {
+    let grammar = solver.grammar();
+
+    let mut rules = Vec::new();
+    let mut tokens = Vec::new();
+    for &symbol in expected {
+        match symbol {
+            Symbol::Rule(rule) if grammar.has_name(rule) => push_unique(&mut rules, symbol),
+            Symbol::Rule(rule) => {
+                for first in solver.first_symbols_for_rule(rule) {
+                    push_unique(&mut tokens, first);
+                }
+            }
+            Symbol::Token(_) | Symbol::Group(_) => push_unique(&mut tokens, symbol),
+        }
+    }
+
+    // Tokens that an expected rule starts with are already implied by it.
+    for rule in &rules {
+        if let Symbol::Rule(rule) = rule {
+            let first = solver.first_symbols_for_rule(*rule);
+            tokens.retain(|t| !first.contains(t));
+        }
+    }
+
+    let mut candidates = grammar
+        .iter_rules()
+        .filter(|rule| grammar.has_name(*rule))
+        .filter(|rule| !rules.contains(&Symbol::Rule(*rule)))
+        .map(|rule| (rule, solver.first_symbols_for_rule(rule)))
+        .filter(|(_, first)| first.len() > 1)
+        .collect::<Vec<_>>();
+
+    // Prefer collapsing into the broadest rules first.
+    candidates
+        .sort_by_key(|(rule, first)| (std::cmp::Reverse(first.len()), grammar.get_name(*rule)));
+
+    for (rule, first) in candidates {
+        let covered = first.iter().filter(|f| tokens.contains(f)).count();
+
+        // "Mostly" covered means at least three quarters of the first set.
+        if covered > 1 && covered * 4 >= first.len() * 3 {
+            tokens.retain(|t| !first.contains(t));
+            rules.push(Symbol::Rule(rule));
+        }
+    }
+
+    // The expected symbols are gathered from hash maps, so sort them to keep messages stable
+    rules.sort();
+    tokens.sort();
+    rules.extend(tokens);
+    rules
+}
+
+fn push_unique(symbols: &mut Vec<Symbol>, symbol: Symbol) {
+    if !symbols.contains(&symbol) {
+        symbols.push(symbol);
+    }
+}
+
+pub struct DiagnosticDisplay<'a> {
+    diagnostic: &'a Diagnostic,
+    grammar: &'a Grammar,
+}
+
+impl DiagnosticDisplay<'_> {
+    fn found(&self, found: Found) -> String {
+        match found {
+            Found::Token(token) => self.grammar.get_name(token),
+            Found::Group => "group".into(),
+            Found::End => "end of input".into(),
+        }
+    }
+}
+
+impl std::fmt::Display for DiagnosticDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.diagnostic.kind {
+            DiagnosticKind::RecoveryMatch { message, .. } => write!(f, "{}", message)?,
+            DiagnosticKind::Unexpected {
+                expected,
+                found,
+                context,
+            } => {
+                if let Some(context) = context {
+                    write!(f, "invalid {}: ", context)?;
+                }
+
+                write!(f, "expected ")?;
+
+                let description = match expected.as_slice() {
+                    [symbol] => self.grammar.get_description(*symbol),
+                    _ => None,
+                };
+
+                if let Some(description) = description {
+                    write!(f, "{}", description)?;
+                } else if expected.is_empty() {
+                    write!(f, "nothing")?;
+                } else {
+                    for (i, symbol) in expected.iter().enumerate() {
+                        if i == expected.len() - 1 && i > 0 {
+                            write!(f, " or ")?;
+                        } else if i > 0 {
+                            write!(f, ", ")?;
+                        }
+
+                        write!(f, "{}", self.grammar.get_name(*symbol))?;
+                    }
+                }
+
+                write!(f, ", found {}", self.found(*found))?;
+            }
+            DiagnosticKind::Trailing { found } => {
+                write!(f, "unexpected {} after end of input", self.found(*found))?;
+            }
+        }
+
+        write!(f, " (at token {})", self.diagnostic.token_index)
+    }
+}
@@ -0,0 +1,441 @@
+//! Differential testing of the interpreter against the Earley parser.
+//!
+//! The interpreter builds its trees by wrapping and inserting stack items using the tables
+//! of the solver, which is easy to get subtly wrong. The Earley parser follows the grammar
+//! directly, so it makes a good oracle: every sentence of a grammar up to some length is
+//! parsed with both, along with small edits of those sentences that are mostly invalid, and
+//! any difference in which inputs are accepted or which trees are built is reported.
+
+use std::{
+    collections::HashSet,
+    panic::{self, AssertUnwindSafe},
+};
+
+use crate::{
+    earley::EarleyParser,
+    generate::{sentences, Rng},
+    interpreter::{solve, ITokenOrGroup, RuleValue},
+    matches::{Grammar, Group, Rule, Term, Token},
+    solver::GrammarSolver,
+};
+
+/// Options for checking a grammar.
+#[derive(Debug, Clone)]
+pub struct DiffOptions {
+    /// The most tokens in a sentence, in the flattened token stream.
+    pub max_len: usize,
+    /// The most sentences of each length, for each rule. Past this, only a sample of the
+    /// sentences is checked.
+    pub limit: usize,
+    /// The most edited sentences to check, on top of the sentences themselves.
+    pub max_edits: usize,
+    /// The most trees of an ambiguous input to look through for the tree of the interpreter.
+    pub max_trees: usize,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            max_len: 8,
+            limit: 50,
+            max_edits: 500,
+            max_trees: 64,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum MismatchKind {
+    /// One parser accepted the input and the other didn't.
+    Acceptance { interpreter: bool, reference: bool },
+    /// Both parsers accepted the input, but the tree of the interpreter isn't one of the
+    /// trees that the reference found.
+    Tree {
+        interpreter: RuleValue,
+        reference: RuleValue,
+    },
+    /// The interpreter panicked, with the message of the panic.
+    Panic(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    pub tokens: Vec<ITokenOrGroup>,
+    pub kind: MismatchKind,
+}
+
+/// The results of checking a grammar.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// The number of inputs that were checked.
+    pub checked: usize,
+    /// The number of valid inputs with more than one tree.
+    pub ambiguous: usize,
+    /// The number of ambiguous inputs with too many trees to find the interpreter's among.
+    pub unverified: usize,
+    /// Whether the limit left out sentences, so that only a sample of them was checked.
+    pub sampled: bool,
+    pub mismatches: Vec<Mismatch>,
+    /// The message of the panic if the solver couldn't be built for the grammar.
+    pub solver_panic: Option<String>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty() && self.solver_panic.is_none()
+    }
+}
+
+/// Check the interpreter against the Earley parser, over the sentences of the grammar and
+/// edits of them.
+pub fn check_grammar(grammar: &Grammar, options: &DiffOptions) -> Report {
+    let mut report = Report::default();
+
+    match panic::catch_unwind(|| GrammarSolver::new(grammar.clone())) {
+        Ok(solver) => {
+            let reference = EarleyParser::new(grammar.clone());
+            let (inputs, sampled) = inputs(grammar, options);
+            report.sampled = sampled;
+            for tokens in inputs {
+                check_input(&solver, &reference, tokens, options, &mut report);
+            }
+        }
+        Err(payload) => report.solver_panic = Some(panic_message(payload)),
+    }
+
+    report
+}
+
+fn check_input(
+    solver: &GrammarSolver,
+    reference: &EarleyParser,
+    tokens: Vec<ITokenOrGroup>,
+    options: &DiffOptions,
+    report: &mut Report,
+) {
+    report.checked += 1;
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| solve(solver, tokens.clone())));
+    let result = match result {
+        Ok(result) => result,
+        Err(payload) => {
+            let kind = MismatchKind::Panic(panic_message(payload));
+            report.mismatches.push(Mismatch { tokens, kind });
+            return;
+        }
+    };
+
+    let parse = reference.parse_forest(tokens.clone(), None);
+    let interpreter_accepts = result.diagnostics.is_empty();
+    let reference_accepts = parse.forest.root().is_some();
+
+    if interpreter_accepts != reference_accepts {
+        let kind = MismatchKind::Acceptance {
+            interpreter: interpreter_accepts,
+            reference: reference_accepts,
+        };
+        report.mismatches.push(Mismatch { tokens, kind });
+        return;
+    }
+
+    if !interpreter_accepts {
+        return;
+    }
+
+    let count = parse.forest.count_trees();
+    if count > 1 {
+        report.ambiguous += 1;
+    }
+
+    let trees = parse.forest.trees(reference.grammar(), options.max_trees);
+    if trees.contains(&result.value) {
+        return;
+    }
+
+    if count > trees.len() as u64 {
+        report.unverified += 1;
+        return;
+    }
+
+    let kind = MismatchKind::Tree {
+        interpreter: result.value,
+        reference: trees.into_iter().next().unwrap(),
+    };
+    report.mismatches.push(Mismatch { tokens, kind });
+}
+
+/// The sentences of the grammar, followed by single token edits of them that aren't
+/// sentences themselves. Also returns whether the sentences were only a sample.
+fn inputs(grammar: &Grammar, options: &DiffOptions) -> (Vec<Vec<ITokenOrGroup>>, bool) {
+    let sentences = sentences(grammar, options.max_len, options.limit);
+    let valid = sentences.items;
+    let mut seen: HashSet<_> = valid.iter().cloned().collect();
+
+    let mut tokens = Vec::new();
+    for (_, m) in grammar.iter_matches() {
+        for term in &m.terms {
+            if let Term::Token(token) = term {
+                if !tokens.contains(token) {
+                    tokens.push(*token);
+                }
+            }
+        }
+    }
+
+    let mut edits = Vec::new();
+    'outer: for sentence in &valid {
+        for edit in edits_of(sentence, &tokens) {
+            if edits.len() == options.max_edits {
+                break 'outer;
+            }
+
+            if seen.insert(edit.clone()) {
+                edits.push(edit);
+            }
+        }
+    }
+
+    let mut inputs = valid;
+    inputs.extend(edits);
+    (inputs, sentences.truncated)
+}
+
+/// Every way to insert, remove or replace one token at the top level of the sentence. The
+/// first and last tokens are kept, since inputs are always framed by `Start` and `Eof`.
+fn edits_of(sentence: &[ITokenOrGroup], tokens: &[Token]) -> Vec<Vec<ITokenOrGroup>> {
+    let mut edits = Vec::new();
+    if sentence.len() < 2 {
+        return edits;
+    }
+
+    for index in 1..sentence.len() {
+        for &token in tokens {
+            let mut edit = sentence.to_vec();
+            edit.insert(index, ITokenOrGroup::Token(token));
+            edits.push(edit);
+        }
+
+        if index == sentence.len() - 1 {
+            break;
+        }
+
+        let mut edit = sentence.to_vec();
+        edit.remove(index);
+        edits.push(edit);
+
+        for &token in tokens {
+            if sentence[index] != ITokenOrGroup::Token(token) {
+                let mut edit = sentence.to_vec();
+                edit[index] = ITokenOrGroup::Token(token);
+                edits.push(edit);
+            }
+        }
+    }
+
+    edits
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".into()
+    }
+}
+
+/// Options for generating random grammars.
+#[derive(Debug, Clone)]
+pub struct RandomGrammarOptions {
+    /// The number of rules besides the root, at most the number of rules that exist.
+    pub rules: usize,
+    /// The number of tokens to pick terms from, besides `Start` and `Eof`.
+    pub tokens: usize,
+    /// The most matches of each rule.
+    pub max_matches: usize,
+    /// The most terms of each match.
+    pub max_terms: usize,
+    /// The chance of a term being a group of the root rule.
+    pub group_chance: f64,
+    /// The chance of a match being empty.
+    pub empty_chance: f64,
+}
+
+impl Default for RandomGrammarOptions {
+    fn default() -> Self {
+        Self {
+            rules: 4,
+            tokens: 4,
+            max_matches: 3,
+            max_terms: 3,
+            group_chance: 0.05,
+            empty_chance: 0.1,
+        }
+    }
+}
+
+const RULES: [Rule; 17] = [
+    Rule::Expr,
+    Rule::Add,
+    Rule::Mul,
+    Rule::Op1,
+    Rule::Op2,
+    Rule::Op3,
+    Rule::Term,
+    Rule::Lambda,
+    Rule::Args,
+    Rule::Static,
+    Rule::Modifier,
+    Rule::Vis,
+    Rule::VisModifier,
+    Rule::Struct,
+    Rule::Fn,
+    Rule::Stmts,
+    Rule::Stmt,
+];
+
+const TOKENS: [Token; 15] = [
+    Token::Num,
+    Token::Name,
+    Token::Plus,
+    Token::Minus,
+    Token::Star,
+    Token::Slash,
+    Token::Pub,
+    Token::Fn,
+    Token::Struct,
+    Token::Crate,
+    Token::Arrow,
+    Token::Eq,
+    Token::Semi,
+    Token::LParen,
+    Token::RParen,
+];
+
+/// A random grammar, with the root `S -> Start Expr Eof` like the demo grammars. Every rule
+/// has at least one match that only uses tokens and later rules, so that every rule has
+/// sentences.
+pub fn random_grammar(rng: &mut Rng, options: &RandomGrammarOptions) -> Grammar {
+    let rules = &RULES[..options.rules.clamp(1, RULES.len())];
+    let tokens = &TOKENS[..options.tokens.clamp(1, TOKENS.len())];
+
+    let mut grammar = Grammar::new();
+    grammar.add(
+        Rule::S,
+        vec![
+            Term::Token(Token::Start),
+            Term::Rule(rules[0]),
+            Term::Token(Token::Eof),
+        ],
+    );
+
+    for (index, &rule) in rules.iter().enumerate() {
+        let later = &rules[index + 1..];
+        let len = 1 + rng.below(options.max_terms.max(1));
+        let terms = (0..len)
+            .map(|_| {
+                if later.is_empty() || rng.chance(0.5) {
+                    Term::Token(*rng.pick(tokens))
+                } else {
+                    Term::Rule(*rng.pick(later))
+                }
+            })
+            .collect();
+        grammar.add(rule, terms);
+
+        for _ in 1..1 + rng.below(options.max_matches.max(1)) {
+            if rng.chance(options.empty_chance) {
+                grammar.add(rule, Vec::new());
+                continue;
+            }
+
+            let len = 1 + rng.below(options.max_terms.max(1));
+            let terms = (0..len)
+                .map(|_| {
+                    if rng.chance(options.group_chance) {
+                        Term::Group(Group::Parens, Rule::S)
+                    } else if rng.chance(0.5) {
+                        Term::Token(*rng.pick(tokens))
+                    } else {
+                        Term::Rule(*rng.pick(rules))
+                    }
+                })
+                .collect();
+            grammar.add(rule, terms);
+        }
+    }
+
+    grammar
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(message) = &self.solver_panic {
+            return write!(f, "the solver panicked: {}", message);
+        }
+
+        write!(
+            f,
+            "{} inputs, {} ambiguous ({} unverified), {} mismatches",
+            self.checked,
+            self.ambiguous,
+            self.unverified,
+            self.mismatches.len()
+        )?;
+        if self.sampled {
+            write!(f, " (sampled)")?;
+        }
+
+        for mismatch in &self.mismatches {
+            write!(f, "\n  {}: ", TokensDisplay(&mismatch.tokens))?;
+            match &mismatch.kind {
+                MismatchKind::Acceptance {
+                    interpreter,
+                    reference,
+                } => {
+                    let verdict = |accepts: &bool| if *accepts { "accepts" } else { "rejects" };
+                    write!(
+                        f,
+                        "the interpreter {} it, but the reference {} it",
+                        verdict(interpreter),
+                        verdict(reference)
+                    )?;
+                }
+                MismatchKind::Tree {
+                    interpreter,
+                    reference,
+                } => {
+                    write!(
+                        f,
+                        "different trees\ninterpreter: {}reference: {}",
+                        interpreter, reference
+                    )?;
+                }
+                MismatchKind::Panic(message) => write!(f, "the interpreter panicked: {}", message)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Display a token stream on one line, with groups in parentheses.
+pub struct TokensDisplay<'a>(pub &'a [ITokenOrGroup]);
+
+impl std::fmt::Display for TokensDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, item) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+
+            match item {
+                ITokenOrGroup::Token(token) => write!(f, "{:?}", token)?,
+                ITokenOrGroup::Group(inner) => write!(f, "({})", TokensDisplay(inner))?,
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,503 @@
+//! An Earley parser, as a fallback for grammars that the interpreter can't parse.
+//!
+//! The interpreter relies on the tables of the solver to decide how to wrap and insert each
+//! value, and some grammars need wrap or insert actions that the tables can't pick between.
+//! This parser works for any grammar, at the cost of being slower. It recognizes the input
+//! with an Earley chart, using Aycock and Horspool's fix for empty rules, and then builds
+//! a parse forest from the chart. Trees from the forest are the same rule values that the
+//! interpreter makes, so the engines can be swapped once a grammar works with the
+//! interpreter. For ambiguous inputs, `parse` returns the forest's first tree, which may
+//! not be the one that the interpreter picks.
+//!
+//! Groups are parsed separately for each rule that the chart can take them as. Empty rules
+//! use the same empty values as the interpreter. Error recovery isn't supported, so parsing
+//! stops at the first token that no item can take.
+
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+
+use crate::{
+    diagnostics::{Diagnostic, DiagnosticKind, ErrorCounts, Found},
+    glr::{
+        forest::{Family, Forest, NodeId, NodeKind},
+        ForestParse,
+    },
+    interpreter::{ITokenOrGroup, ParseResult, RuleValue},
+    matches::{Grammar, MatchId, Rule, Symbol, Term},
+    solver::{EmptyRuleSolver, EmptySolverRuleValue},
+    span::{check_byte_ranges, ByteRangesError, Span},
+};
+
+/// A parser that works for any grammar.
+pub struct EarleyParser {
+    grammar: Grammar,
+    empty_rules: EmptyRuleSolver,
+}
+
+/// A position within a match, along with the position in the chart where the match started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Item {
+    match_id: MatchId,
+    /// The number of terms before the position.
+    dot: usize,
+    origin: usize,
+}
+
+#[derive(Debug, Default)]
+struct EarleySet {
+    items: Vec<Item>,
+    seen: HashSet<Item>,
+}
+
+impl EarleySet {
+    fn add(&mut self, item: Item) {
+        if self.seen.insert(item) {
+            self.items.push(item);
+        }
+    }
+
+    fn contains(&self, item: Item) -> bool {
+        self.seen.contains(&item)
+    }
+}
+
+impl EarleyParser {
+    pub fn new(grammar: Grammar) -> Self {
+        let empty_rules = EmptyRuleSolver::new(&grammar);
+        Self {
+            grammar,
+            empty_rules,
+        }
+    }
+
+    pub fn grammar(&self) -> &Grammar {
+        &self.grammar
+    }
+
+    /// Parse into the same result as the interpreter. If there is more than one parse, the
+    /// one using the lowest matches is picked. An invalid input gives a single error value.
+    pub fn parse(&self, tokens: Vec<ITokenOrGroup>) -> ParseResult {
+        let len = tokens.iter().map(|item| item.flat_len()).sum();
+        self.to_result(self.parse_forest(tokens, None), len, None)
+    }
+
+    /// Parse, with the byte range of each token in the flattened token stream, like
+    /// `solve_with_spans`.
+    pub fn parse_with_spans(
+        &self,
+        tokens: Vec<ITokenOrGroup>,
+        byte_ranges: &[Range<usize>],
+    ) -> Result<ParseResult, ByteRangesError> {
+        check_byte_ranges(&tokens, byte_ranges)?;
+        let len = tokens.iter().map(|item| item.flat_len()).sum();
+        let forest = self.parse_forest(tokens, Some(byte_ranges));
+        Ok(self.to_result(forest, len, Some(byte_ranges)))
+    }
+
+    /// Parse into a forest of every parse, like the GLR parser does.
+    pub fn parse_forest(
+        &self,
+        tokens: Vec<ITokenOrGroup>,
+        byte_ranges: Option<&[Range<usize>]>,
+    ) -> ForestParse {
+        let mut forest = Forest::new(byte_ranges);
+        let rule = self.grammar.get(self.grammar.root_id()).rule;
+
+        let mut diagnostics = Vec::new();
+        match self.parse_sequence(&mut forest, rule, &tokens, 0) {
+            Ok(root) => forest.set_root(Some(root)),
+            Err(diagnostic) => diagnostics.push(diagnostic),
+        }
+
+        ForestParse {
+            forest,
+            diagnostics,
+        }
+    }
+
+    fn to_result(
+        &self,
+        parse: ForestParse,
+        len: usize,
+        byte_ranges: Option<&[Range<usize>]>,
+    ) -> ParseResult {
+        let value = parse.forest.first_tree(&self.grammar).unwrap_or_else(|| {
+            RuleValue::root_error(&self.grammar, Span::new(0..len, byte_ranges))
+        });
+
+        ParseResult {
+            value,
+            error_counts: ErrorCounts {
+                reported: parse.diagnostics.len(),
+                ..Default::default()
+            },
+            diagnostics: parse.diagnostics,
+        }
+    }
+
+    /// Parse a sequence of tokens that starts at the position as the rule, returning the
+    /// node that derives all of them.
+    fn parse_sequence(
+        &self,
+        forest: &mut Forest,
+        rule: Rule,
+        tokens: &[ITokenOrGroup],
+        start: usize,
+    ) -> Result<NodeId, Diagnostic> {
+        let mut chart = Chart {
+            grammar: &self.grammar,
+            empty_rules: &self.empty_rules,
+            sets: vec![EarleySet::default()],
+            positions: vec![start],
+            groups: HashMap::new(),
+        };
+
+        for &match_id in self.grammar.get_matches_from_rule(rule) {
+            chart.sets[0].add(Item {
+                match_id,
+                dot: 0,
+                origin: 0,
+            });
+        }
+
+        for (index, item) in tokens.iter().enumerate() {
+            chart.complete(index);
+            chart.scan(self, forest, index, item)?;
+        }
+        chart.complete(tokens.len());
+
+        let end = tokens.len();
+        let is_complete = self
+            .grammar
+            .get_matches_from_rule(rule)
+            .iter()
+            .any(|&match_id| {
+                chart.sets[end].contains(Item {
+                    match_id,
+                    dot: self.grammar.get(match_id).terms.len(),
+                    origin: 0,
+                })
+            });
+
+        if !is_complete {
+            return Err(chart.unexpected(end, Found::End));
+        }
+
+        let mut builder = TreeBuilder {
+            chart: &chart,
+            nodes: HashMap::new(),
+        };
+        Ok(builder.rule_node(forest, rule, 0, end))
+    }
+}
+
+struct Chart<'a> {
+    grammar: &'a Grammar,
+    empty_rules: &'a EmptyRuleSolver,
+    /// The items before each item of the sequence, and after the last one.
+    sets: Vec<EarleySet>,
+    /// The position of each set in the flattened token stream.
+    positions: Vec<usize>,
+    /// The nodes of the groups that were parsed, by set and rule.
+    groups: HashMap<(usize, Rule), NodeId>,
+}
+
+impl Chart<'_> {
+    /// Predict and complete the items of a set, until no new items are added.
+    fn complete(&mut self, index: usize) {
+        let mut next = 0;
+        while next < self.sets[index].items.len() {
+            let item = self.sets[index].items[next];
+            next += 1;
+
+            let terms = &self.grammar.get(item.match_id).terms;
+            match terms.get(item.dot) {
+                None => {
+                    let rule = self.grammar.get(item.match_id).rule;
+                    // The origin is never past the current set, so this only reads from
+                    // the current set when the match is empty
+                    let waiting: Vec<Item> = self.sets[item.origin]
+                        .items
+                        .iter()
+                        .filter(|waiting| {
+                            self.grammar.get(waiting.match_id).terms.get(waiting.dot)
+                                == Some(&Term::Rule(rule))
+                        })
+                        .copied()
+                        .collect();
+
+                    for waiting in waiting {
+                        self.sets[index].add(Item {
+                            dot: waiting.dot + 1,
+                            ..waiting
+                        });
+                    }
+                }
+                Some(&Term::Rule(rule)) => {
+                    for &match_id in self.grammar.get_matches_from_rule(rule) {
+                        self.sets[index].add(Item {
+                            match_id,
+                            dot: 0,
+                            origin: index,
+                        });
+                    }
+
+                    // Empty rules can be completed before anything waiting on them is added,
+                    // so step over them here instead
+                    if self.empty_rules.is_empty(rule) {
+                        self.sets[index].add(Item {
+                            dot: item.dot + 1,
+                            ..item
+                        });
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    /// Take the next token or group into a new set.
+    fn scan(
+        &mut self,
+        parser: &EarleyParser,
+        forest: &mut Forest,
+        index: usize,
+        input: &ITokenOrGroup,
+    ) -> Result<(), Diagnostic> {
+        let mut next = EarleySet::default();
+        let mut group_errors = Vec::new();
+        let mut group_results: HashMap<Rule, Result<NodeId, Diagnostic>> = HashMap::new();
+        let position = self.positions[index];
+
+        for item in self.sets[index].items.clone() {
+            let advanced = Item {
+                dot: item.dot + 1,
+                ..item
+            };
+
+            match (self.grammar.get(item.match_id).terms.get(item.dot), input) {
+                (Some(&Term::Token(token)), ITokenOrGroup::Token(found)) if token == *found => {
+                    next.add(advanced);
+                }
+                (Some(&Term::Group(_, rule)), ITokenOrGroup::Group(inner)) => {
+                    let result = group_results
+                        .entry(rule)
+                        .or_insert_with(|| parser.parse_sequence(forest, rule, inner, position));
+
+                    match result {
+                        Ok(node) => {
+                            self.groups.insert((index, rule), *node);
+                            next.add(advanced);
+                        }
+                        Err(error) => group_errors.push(error.clone()),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if next.items.is_empty() {
+            // Errors inside of a group are more precise than the group not fitting
+            if let Some(error) = group_errors.into_iter().next() {
+                return Err(error);
+            }
+
+            let found = match input {
+                ITokenOrGroup::Token(token) => Found::Token(*token),
+                ITokenOrGroup::Group(_) => Found::Group,
+            };
+            return Err(self.unexpected(index, found));
+        }
+
+        self.sets.push(next);
+        self.positions.push(position + input.flat_len());
+        Ok(())
+    }
+
+    /// A diagnostic for an item that no item of the set could take, expecting whatever the
+    /// items could have taken.
+    fn unexpected(&self, index: usize, found: Found) -> Diagnostic {
+        let mut expected = Vec::new();
+        for item in &self.sets[index].items {
+            let symbol = match self.grammar.get(item.match_id).terms.get(item.dot) {
+                Some(Term::Token(token)) => Symbol::Token(*token),
+                Some(Term::Group(group, _)) => Symbol::Group(*group),
+                _ => continue,
+            };
+
+            if !expected.contains(&symbol) {
+                expected.push(symbol);
+            }
+        }
+
+        let kind = DiagnosticKind::Unexpected {
+            expected,
+            found,
+            context: None,
+        };
+        Diagnostic::new(kind, self.positions[index])
+    }
+
+    /// Whether the rule derives the items from one set to another.
+    fn derives(&self, rule: Rule, from: usize, to: usize) -> bool {
+        if from == to && self.empty_rules.is_empty(rule) {
+            return true;
+        }
+
+        self.grammar
+            .get_matches_from_rule(rule)
+            .iter()
+            .any(|&match_id| {
+                self.sets[to].contains(Item {
+                    match_id,
+                    dot: self.grammar.get(match_id).terms.len(),
+                    origin: from,
+                })
+            })
+    }
+}
+
+/// Builds the forest nodes of a sequence from its chart.
+struct TreeBuilder<'c, 'a> {
+    chart: &'c Chart<'a>,
+    /// The nodes of the sequence, by symbol and range of sets.
+    nodes: HashMap<(Term, Range<usize>), NodeId>,
+}
+
+impl TreeBuilder<'_, '_> {
+    fn node(
+        &mut self,
+        forest: &mut Forest,
+        kind: NodeKind,
+        term: Term,
+        sets: Range<usize>,
+    ) -> NodeId {
+        let positions = &self.chart.positions;
+        let tokens = positions[sets.start]..positions[sets.end];
+
+        *self
+            .nodes
+            .entry((term, sets))
+            .or_insert_with(|| forest.add_node(kind, tokens))
+    }
+
+    /// The node of a rule between two sets, with every derivation of it from the chart.
+    fn rule_node(&mut self, forest: &mut Forest, rule: Rule, from: usize, to: usize) -> NodeId {
+        let term = Term::Rule(rule);
+        if let Some(&id) = self.nodes.get(&(term, from..to)) {
+            return id;
+        }
+
+        let id = self.node(forest, NodeKind::Rule(rule), term, from..to);
+
+        // Empty values are picked the same way as the interpreter picks them
+        if from == to {
+            if let Some(value) = self.chart.empty_rules.get(rule) {
+                self.add_empty(forest, id, value, from);
+                return id;
+            }
+        }
+
+        let grammar = self.chart.grammar;
+        for &match_id in grammar.get_matches_from_rule(rule) {
+            let len = grammar.get(match_id).terms.len();
+            let item = Item {
+                match_id,
+                dot: len,
+                origin: from,
+            };
+            if !self.chart.sets[to].contains(item) {
+                continue;
+            }
+
+            for children in self.splits(forest, item, len, to) {
+                forest.add_family(id, Family { match_id, children });
+            }
+        }
+
+        id
+    }
+
+    fn add_empty(
+        &mut self,
+        forest: &mut Forest,
+        id: NodeId,
+        value: &EmptySolverRuleValue,
+        at: usize,
+    ) {
+        let mut children = Vec::new();
+        for field in &value.match_value.fields {
+            children.push(self.rule_node(forest, field.rule, at, at));
+        }
+
+        let family = Family {
+            match_id: value.match_value.id,
+            children,
+        };
+        forest.add_family(id, family);
+    }
+
+    /// Every way to derive the first terms of the item's match up to the set, as the nodes
+    /// of those terms.
+    fn splits(
+        &mut self,
+        forest: &mut Forest,
+        item: Item,
+        dot: usize,
+        to: usize,
+    ) -> Vec<Vec<NodeId>> {
+        if dot == 0 {
+            return if to == item.origin {
+                vec![Vec::new()]
+            } else {
+                Vec::new()
+            };
+        }
+
+        let before = Item {
+            dot: dot - 1,
+            ..item
+        };
+        let term = self.chart.grammar.get(item.match_id).terms[dot - 1];
+
+        let mut options = Vec::new();
+        match term {
+            Term::Token(token) => {
+                if to > item.origin && self.chart.sets[to - 1].contains(before) {
+                    let leaf = self.node(forest, NodeKind::Token(token), term, to - 1..to);
+                    options.push((to - 1, leaf));
+                }
+            }
+            Term::Group(group, rule) => {
+                if to > item.origin && self.chart.sets[to - 1].contains(before) {
+                    if let Some(&inner) = self.chart.groups.get(&(to - 1, rule)) {
+                        let kind = NodeKind::Group(group, inner);
+                        let leaf = self.node(forest, kind, term, to - 1..to);
+                        options.push((to - 1, leaf));
+                    }
+                }
+            }
+            Term::Rule(rule) => {
+                for from in item.origin..=to {
+                    if self.chart.sets[from].contains(before) && self.chart.derives(rule, from, to)
+                    {
+                        let child = self.rule_node(forest, rule, from, to);
+                        options.push((from, child));
+                    }
+                }
+            }
+        }
+
+        let mut splits = Vec::new();
+        for (from, child) in options {
+            for mut children in self.splits(forest, item, dot - 1, from) {
+                children.push(child);
+                splits.push(children);
+            }
+        }
+
+        splits
+    }
+}
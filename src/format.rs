@@ -0,0 +1,330 @@
+//! A formatter that lays out trees using the layout annotations of the grammar.
+//!
+//! Trees are first turned into a document, in the style of Wadler's "prettier printer".
+//! Documents are text with places where lines can be broken, grouped so that each group is
+//! either printed on a single line or has all of its lines broken. Each match with a layout
+//! is a group, with its breaks before the terms that the grammar says, and nested indentation
+//! for its indented terms. Tokens without a break in between are separated by the spacing
+//! rules of the text options.
+
+use crate::{
+    interpreter::RuleValue,
+    matches::{Break, Grammar, MatchId, Token},
+    unparse::{pieces, Piece, TextOptions},
+};
+
+/// A document to lay out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Doc {
+    Text(String),
+    /// A new line, or the text if the enclosing group fits on one line.
+    Line(String),
+    /// A new line, which stops the enclosing groups from fitting on one line.
+    HardLine,
+    /// A document whose lines are indented by the number of spaces.
+    Nest(usize, Box<Doc>),
+    /// A document that's printed on one line if it fits.
+    Group(Box<Doc>),
+    Concat(Vec<Doc>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Flat,
+    Broken,
+}
+
+impl Doc {
+    pub fn text(text: impl Into<String>) -> Self {
+        Doc::Text(text.into())
+    }
+
+    /// A space, or a new line.
+    pub fn line() -> Self {
+        Doc::Line(" ".into())
+    }
+
+    /// Nothing, or a new line.
+    pub fn soft_line() -> Self {
+        Doc::Line(String::new())
+    }
+
+    pub fn nest(indent: usize, doc: Doc) -> Self {
+        Doc::Nest(indent, Box::new(doc))
+    }
+
+    pub fn group(doc: Doc) -> Self {
+        Doc::Group(Box::new(doc))
+    }
+
+    /// Print the document, breaking the lines of groups that don't fit within the width.
+    pub fn render(&self, width: usize) -> String {
+        let mut out = String::new();
+        let mut column = 0;
+        let mut stack = vec![(0, Mode::Broken, self)];
+
+        while let Some((indent, mode, doc)) = stack.pop() {
+            match doc {
+                Doc::Text(text) => {
+                    out.push_str(text);
+                    column = match text.rfind('\n') {
+                        Some(i) => text[i + 1..].chars().count(),
+                        None => column + text.chars().count(),
+                    };
+                }
+                Doc::Line(flat) if mode == Mode::Flat => {
+                    out.push_str(flat);
+                    column += flat.chars().count();
+                }
+                Doc::Line(_) | Doc::HardLine => {
+                    new_line(&mut out, indent);
+                    column = indent;
+                }
+                Doc::Nest(by, doc) => stack.push((indent + by, mode, doc)),
+                Doc::Group(doc) => {
+                    let mode = match mode {
+                        Mode::Flat => Mode::Flat,
+                        _ if fits(width.saturating_sub(column), (indent, doc), &stack) => {
+                            Mode::Flat
+                        }
+                        _ => Mode::Broken,
+                    };
+                    stack.push((indent, mode, doc));
+                }
+                Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (indent, mode, doc))),
+            }
+        }
+
+        // Breaks before tokens that print nothing (e.g. `Eof`) can leave empty lines at the end
+        let len = out.trim_end().len();
+        out.truncate(len);
+        out
+    }
+}
+
+fn new_line(out: &mut String, indent: usize) {
+    let len = out.trim_end_matches(' ').len();
+    out.truncate(len);
+    out.push('\n');
+    out.push_str(&" ".repeat(indent));
+}
+
+/// Whether a group fits on the rest of the line when printed flat, along with whatever
+/// follows it up to the next line break.
+fn fits(width: usize, group: (usize, &Doc), rest: &[(usize, Mode, &Doc)]) -> bool {
+    let mut remaining = width as isize;
+    let mut stack = vec![(group.0, Mode::Flat, group.1)];
+    let mut rest = rest.iter().rev();
+
+    loop {
+        let Some((indent, mode, doc)) = stack.pop().or_else(|| rest.next().copied()) else {
+            return true;
+        };
+
+        match doc {
+            Doc::Text(text) => match text.find('\n') {
+                Some(i) => return remaining >= text[..i].chars().count() as isize,
+                None => remaining -= text.chars().count() as isize,
+            },
+            Doc::Line(flat) if mode == Mode::Flat => remaining -= flat.chars().count() as isize,
+            Doc::Line(_) => return true,
+            Doc::HardLine => return mode == Mode::Broken,
+            Doc::Nest(by, doc) => stack.push((indent + by, mode, doc)),
+            Doc::Group(doc) => stack.push((indent, mode, doc)),
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (indent, mode, doc))),
+        }
+
+        if remaining < 0 {
+            return false;
+        }
+    }
+}
+
+/// Options for formatting trees.
+#[derive(Debug, Clone)]
+pub struct FormatOptions<'a> {
+    /// The text of tokens, and the spacing between tokens without a break in between.
+    pub text: TextOptions<'a>,
+    /// The width that lines are kept within, where possible.
+    pub width: usize,
+    /// The number of spaces that indented terms are indented by.
+    pub indent: usize,
+}
+
+impl Default for FormatOptions<'_> {
+    fn default() -> Self {
+        Self {
+            text: TextOptions::default(),
+            width: 80,
+            indent: 4,
+        }
+    }
+}
+
+/// Format a tree as text.
+pub fn format(grammar: &Grammar, root: &RuleValue, options: &FormatOptions) -> String {
+    to_doc(grammar, root, options).render(options.width)
+}
+
+/// Turn a tree into a document, using the layouts of its matches.
+pub fn to_doc(grammar: &Grammar, root: &RuleValue, options: &FormatOptions) -> Doc {
+    let mut builder = DocBuilder {
+        grammar,
+        options,
+        frames: vec![Vec::new()],
+        matches: Vec::new(),
+        previous: None,
+        has_break: false,
+    };
+
+    for piece in pieces(grammar, root) {
+        builder.add(piece);
+    }
+
+    Doc::Concat(builder.frames.pop().unwrap())
+}
+
+struct DocBuilder<'a, 'o> {
+    grammar: &'a Grammar,
+    options: &'a FormatOptions<'o>,
+    /// The documents of the open matches and nests, innermost last.
+    frames: Vec<Vec<Doc>>,
+    matches: Vec<OpenMatch>,
+    /// The last token that was printed, if any.
+    previous: Option<Token>,
+    /// Whether there's a break since the last token, which replaces the spacing rules.
+    has_break: bool,
+}
+
+struct OpenMatch {
+    id: MatchId,
+    /// Whether the indented terms of the match are open.
+    is_nested: bool,
+}
+
+impl DocBuilder<'_, '_> {
+    fn add(&mut self, piece: Piece) {
+        match piece {
+            Piece::Token(token, span) => {
+                let text = self.options.text.text(self.grammar, token, span);
+                self.push_token(token, text);
+            }
+            Piece::Open(group) | Piece::Close(group) => {
+                let Some((open, close)) = self.grammar.get_group_delimiters(group) else {
+                    return;
+                };
+
+                let token = if matches!(piece, Piece::Open(_)) {
+                    open
+                } else {
+                    close
+                };
+                let text = self.options.text.text(self.grammar, token, None);
+                self.push_token(token, text);
+            }
+            Piece::Error(span) => {
+                let Some(text) = self.options.text.source_text(span) else {
+                    return;
+                };
+
+                if !text.is_empty() {
+                    if self.previous.is_some() && !self.has_break {
+                        let space = self.options.text.spacing.default.clone();
+                        self.push(Doc::Text(space));
+                    }
+                    self.push(Doc::text(text));
+                    self.has_break = false;
+                }
+            }
+            Piece::Enter(id) => {
+                self.frames.push(Vec::new());
+                self.matches.push(OpenMatch {
+                    id,
+                    is_nested: false,
+                });
+            }
+            Piece::Term(index) => self.start_term(index),
+            Piece::Exit => {
+                let open = self.matches.pop().unwrap();
+                if open.is_nested {
+                    self.close_nest();
+                }
+
+                let doc = Doc::Concat(self.frames.pop().unwrap());
+                let layout = self.grammar.get_layout(open.id);
+                let can_break = layout.is_some_and(|layout| {
+                    layout
+                        .breaks
+                        .iter()
+                        .any(|b| matches!(b, Some(Break::Line | Break::SoftLine)))
+                });
+
+                self.push(if can_break { Doc::group(doc) } else { doc });
+            }
+        }
+    }
+
+    fn start_term(&mut self, index: usize) {
+        let open = self.matches.last().unwrap();
+        let Some(layout) = self.grammar.get_layout(open.id) else {
+            return;
+        };
+
+        if let Some(indent) = &layout.indent {
+            if open.is_nested && index == indent.end {
+                self.matches.last_mut().unwrap().is_nested = false;
+                self.close_nest();
+            }
+
+            if index == indent.start && index < indent.end {
+                self.matches.last_mut().unwrap().is_nested = true;
+                self.frames.push(Vec::new());
+            }
+        }
+
+        let Some(kind) = layout.breaks.get(index).copied().flatten() else {
+            return;
+        };
+
+        // Breaks at the start of the output, or right after another break, are dropped
+        if self.previous.is_none() || self.has_break {
+            return;
+        }
+
+        self.push(match kind {
+            Break::None => Doc::text(""),
+            Break::Space => Doc::text(" "),
+            Break::SoftLine => Doc::soft_line(),
+            Break::Line => Doc::line(),
+            Break::HardLine => Doc::HardLine,
+        });
+        self.has_break = true;
+    }
+
+    fn close_nest(&mut self) {
+        let doc = Doc::Concat(self.frames.pop().unwrap());
+        self.push(Doc::nest(self.options.indent, doc));
+    }
+
+    fn push_token(&mut self, token: Token, text: String) {
+        // Tokens that are only there for the parser print nothing, and take no space
+        if text.is_empty() {
+            return;
+        }
+
+        if let Some(previous) = self.previous {
+            if !self.has_break {
+                let space = self.options.text.spacing.get(previous, token).to_string();
+                self.push(Doc::Text(space));
+            }
+        }
+
+        self.push(Doc::Text(text));
+        self.previous = Some(token);
+        self.has_break = false;
+    }
+
+    fn push(&mut self, doc: Doc) {
+        self.frames.last_mut().unwrap().push(doc);
+    }
+}
@@ -0,0 +1,413 @@
+//! Generating sentences from a grammar, for fuzzing and for testing parsers against each
+//! other. Sentences can be picked at random, with limits on their size and depth and weights
+//! for picking matches, or enumerated up to a length.
+
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+};
+
+use crate::{
+    interpreter::ITokenOrGroup,
+    matches::{Grammar, MatchId, Rule, Term},
+};
+
+/// A small xorshift random number generator, so that generated grammars and sentences can
+/// be reproduced from a seed.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // The state must never be zero
+        let state = seed ^ 0x9e37_79b9_7f4a_7c15;
+        Self {
+            state: if state == 0 { 1 } else { state },
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// A number from zero up to, but not including, the bound.
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound.max(1) as u64) as usize
+    }
+
+    /// A number from zero up to, but not including, one.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// True with the probability, from zero to one.
+    pub fn chance(&mut self, probability: f64) -> bool {
+        self.next_f64() < probability
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+/// Options for generating random sentences.
+#[derive(Debug, Clone)]
+pub struct GenerateOptions {
+    /// The most tokens in a sentence, in the flattened token stream.
+    pub max_len: usize,
+    /// The most rules that can be nested in a sentence, including the root.
+    pub max_depth: usize,
+    /// How likely each match is to be picked over the other matches of its rule. Matches
+    /// without a weight have a weight of one.
+    weights: HashMap<MatchId, f64>,
+}
+
+impl Default for GenerateOptions {
+    fn default() -> Self {
+        Self {
+            max_len: 32,
+            max_depth: 16,
+            weights: HashMap::new(),
+        }
+    }
+}
+
+impl GenerateOptions {
+    /// Set the weight of a match. A weight of zero means the match is never picked.
+    pub fn set_weight(&mut self, id: MatchId, weight: f64) {
+        self.weights.insert(id, weight.max(0.0));
+    }
+
+    pub fn weight(&self, id: MatchId) -> f64 {
+        self.weights.get(&id).copied().unwrap_or(1.0)
+    }
+}
+
+/// Generates random sentences of a grammar.
+pub struct Generator<'a> {
+    grammar: &'a Grammar,
+    options: GenerateOptions,
+    /// The fewest tokens that each rule can derive.
+    min_lens: HashMap<Rule, usize>,
+    /// The fewest rules that need to be nested to derive each rule, including itself.
+    min_depths: HashMap<Rule, usize>,
+}
+
+impl<'a> Generator<'a> {
+    pub fn new(grammar: &'a Grammar, options: GenerateOptions) -> Self {
+        let mut generator = Self {
+            grammar,
+            options,
+            min_lens: HashMap::new(),
+            min_depths: HashMap::new(),
+        };
+
+        // Rules that never finish deriving are left out, so they're never picked
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for rule in rules_in_order(grammar) {
+                for &id in grammar.get_matches_from_rule(rule) {
+                    let Some((len, depth)) = generator.match_minimums(id) else {
+                        continue;
+                    };
+
+                    if generator.min_lens.get(&rule).is_none_or(|&min| len < min) {
+                        generator.min_lens.insert(rule, len);
+                        changed = true;
+                    }
+                    if generator
+                        .min_depths
+                        .get(&rule)
+                        .is_none_or(|&min| depth < min)
+                    {
+                        generator.min_depths.insert(rule, depth);
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        generator
+    }
+
+    pub fn options(&self) -> &GenerateOptions {
+        &self.options
+    }
+
+    /// A random sentence of the root rule, or `None` if the root has no sentences within
+    /// the limits.
+    pub fn sentence(&self, rng: &mut Rng) -> Option<Vec<ITokenOrGroup>> {
+        let root = self.grammar.get(self.grammar.root_id()).rule;
+        self.rule_sentence(root, rng)
+    }
+
+    /// A random sentence of any rule, within the limits.
+    pub fn rule_sentence(&self, rule: Rule, rng: &mut Rng) -> Option<Vec<ITokenOrGroup>> {
+        self.expand(rule, self.options.max_depth, self.options.max_len, rng)
+    }
+
+    /// The fewest tokens and nested rules of a match, if all of its rules can finish.
+    fn match_minimums(&self, id: MatchId) -> Option<(usize, usize)> {
+        let mut len = 0;
+        let mut depth = 1;
+
+        for term in &self.grammar.get(id).terms {
+            match term {
+                Term::Token(_) => len += 1,
+                Term::Rule(rule) | Term::Group(_, rule) => {
+                    len += self.min_lens.get(rule)?;
+                    depth = depth.max(1 + self.min_depths.get(rule)?);
+                }
+            }
+        }
+
+        Some((len, depth))
+    }
+
+    fn term_min_len(&self, term: &Term) -> usize {
+        match term {
+            Term::Token(_) => 1,
+            Term::Rule(rule) | Term::Group(_, rule) => self.min_lens[rule],
+        }
+    }
+
+    /// Derive a rule within the depth and number of tokens. Matches are picked by weight,
+    /// out of the ones that fit, and another is tried if one doesn't work out.
+    fn expand(
+        &self,
+        rule: Rule,
+        depth: usize,
+        max_len: usize,
+        rng: &mut Rng,
+    ) -> Option<Vec<ITokenOrGroup>> {
+        let mut candidates: Vec<(MatchId, f64)> = self
+            .grammar
+            .get_matches_from_rule(rule)
+            .iter()
+            .filter(|&&id| {
+                self.match_minimums(id)
+                    .is_some_and(|(len, min_depth)| len <= max_len && min_depth <= depth)
+            })
+            .map(|&id| (id, self.options.weight(id)))
+            .filter(|&(_, weight)| weight > 0.0)
+            .collect();
+
+        while !candidates.is_empty() {
+            let total: f64 = candidates.iter().map(|(_, weight)| weight).sum();
+            let mut target = rng.next_f64() * total;
+            let mut index = candidates.len() - 1;
+            for (i, (_, weight)) in candidates.iter().enumerate() {
+                if target < *weight {
+                    index = i;
+                    break;
+                }
+                target -= weight;
+            }
+
+            let (id, _) = candidates.swap_remove(index);
+            if let Some(sentence) = self.expand_match(id, depth, max_len, rng) {
+                return Some(sentence);
+            }
+        }
+
+        None
+    }
+
+    fn expand_match(
+        &self,
+        id: MatchId,
+        depth: usize,
+        max_len: usize,
+        rng: &mut Rng,
+    ) -> Option<Vec<ITokenOrGroup>> {
+        let terms = &self.grammar.get(id).terms;
+        let mut sentence = Vec::new();
+        let mut len = 0;
+
+        for (index, term) in terms.iter().enumerate() {
+            // Leave enough room for the terms after this one
+            let rest: usize = terms[index + 1..]
+                .iter()
+                .map(|term| self.term_min_len(term))
+                .sum();
+            let budget = max_len.checked_sub(len + rest)?;
+
+            match term {
+                Term::Token(token) => sentence.push(ITokenOrGroup::Token(*token)),
+                Term::Rule(rule) => {
+                    sentence.extend(self.expand(*rule, depth - 1, budget, rng)?);
+                }
+                Term::Group(_, rule) => {
+                    let inner = self.expand(*rule, depth - 1, budget, rng)?;
+                    sentence.push(ITokenOrGroup::Group(inner));
+                }
+            }
+
+            len = sentence.iter().map(|item| item.flat_len()).sum();
+        }
+
+        Some(sentence)
+    }
+}
+
+/// The sentences of a grammar up to a length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sentences {
+    /// The sentences, shortest first.
+    pub items: Vec<Vec<ITokenOrGroup>>,
+    /// Whether the limit left out sentences of some rule. The items are then only a sample
+    /// of the sentences up to the length.
+    pub truncated: bool,
+}
+
+/// The sentences of the root rule with up to `max_len` tokens in the flattened token stream,
+/// shortest first. At most `limit` sentences of each length are kept for each rule, so that
+/// grammars with many sentences still finish quickly, and the result says whether any were
+/// left out.
+pub fn sentences(grammar: &Grammar, max_len: usize, limit: usize) -> Sentences {
+    let mut enumerator = Enumerator {
+        grammar,
+        rules: rules_in_order(grammar),
+        limit,
+        by_len: Vec::new(),
+        truncated: Cell::new(false),
+    };
+
+    let root = grammar.get(grammar.root_id()).rule;
+    let mut items = Vec::new();
+    for len in 0..=max_len {
+        enumerator.add_len();
+        if let Some(found) = enumerator.by_len[len].get(&root) {
+            items.extend(found.items.iter().cloned());
+        }
+    }
+
+    Sentences {
+        items,
+        truncated: enumerator.truncated.get(),
+    }
+}
+
+/// The rules of the grammar in the order that they're first used, so that generating is
+/// deterministic.
+pub fn rules_in_order(grammar: &Grammar) -> Vec<Rule> {
+    let mut rules = Vec::new();
+    for (_, m) in grammar.iter_matches() {
+        if !rules.contains(&m.rule) {
+            rules.push(m.rule);
+        }
+    }
+    rules
+}
+
+#[derive(Debug, Default)]
+struct SentenceSet {
+    items: Vec<Vec<ITokenOrGroup>>,
+    seen: HashSet<Vec<ITokenOrGroup>>,
+}
+
+struct Enumerator<'a> {
+    grammar: &'a Grammar,
+    rules: Vec<Rule>,
+    limit: usize,
+    /// The sentences of each rule, by length.
+    by_len: Vec<HashMap<Rule, SentenceSet>>,
+    /// Whether a sentence was left out because of the limit.
+    truncated: Cell<bool>,
+}
+
+impl Enumerator<'_> {
+    /// Find the sentences of the next length. Rules can use the sentences of other rules of
+    /// the same length through empty terms, so this repeats until nothing changes.
+    fn add_len(&mut self) {
+        let len = self.by_len.len();
+        self.by_len.push(HashMap::new());
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for rule in self.rules.clone() {
+                for &id in self.grammar.get_matches_from_rule(rule) {
+                    let terms = &self.grammar.get(id).terms;
+                    for sentence in self.sequences(terms, len) {
+                        let set = self.by_len[len].entry(rule).or_default();
+                        if set.seen.contains(&sentence) {
+                            continue;
+                        }
+
+                        if set.items.len() == self.limit {
+                            self.truncated.set(true);
+                            continue;
+                        }
+
+                        set.seen.insert(sentence.clone());
+                        set.items.push(sentence);
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The sentences of a sequence of terms with exactly the length.
+    fn sequences(&self, terms: &[Term], len: usize) -> Vec<Vec<ITokenOrGroup>> {
+        let Some((last, rest)) = terms.split_last() else {
+            return if len == 0 {
+                vec![Vec::new()]
+            } else {
+                Vec::new()
+            };
+        };
+
+        let mut sequences = Vec::new();
+        for last_len in 0..=len {
+            let ends = self.term_sentences(*last, last_len);
+            if ends.is_empty() {
+                continue;
+            }
+
+            for start in self.sequences(rest, len - last_len) {
+                for end in &ends {
+                    if sequences.len() == self.limit {
+                        self.truncated.set(true);
+                        return sequences;
+                    }
+
+                    let mut sequence = start.clone();
+                    sequence.extend(end.iter().cloned());
+                    sequences.push(sequence);
+                }
+            }
+        }
+
+        sequences
+    }
+
+    fn term_sentences(&self, term: Term, len: usize) -> Vec<Vec<ITokenOrGroup>> {
+        match term {
+            Term::Token(token) if len == 1 => vec![vec![ITokenOrGroup::Token(token)]],
+            Term::Token(_) => Vec::new(),
+            Term::Rule(rule) => self.rule_sentences(rule, len).to_vec(),
+            Term::Group(_, rule) => self
+                .rule_sentences(rule, len)
+                .iter()
+                .map(|inner| vec![ITokenOrGroup::Group(inner.clone())])
+                .collect(),
+        }
+    }
+
+    fn rule_sentences(&self, rule: Rule, len: usize) -> &[Vec<ITokenOrGroup>] {
+        self.by_len[len]
+            .get(&rule)
+            .map(|set| set.items.as_slice())
+            .unwrap_or(&[])
+    }
+}
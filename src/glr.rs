@@ -0,0 +1,407 @@
+//! An opt-in generalized LR parser for ambiguous grammars.
+//!
+//! The regular interpreter resolves ambiguity as it goes, by taking the first first set that
+//! matches and the best scoring wrap action. This parser instead follows every alternative
+//! at once, with the stacks of all of them merged into a graph-structured stack, and returns
+//! every parse in a shared packed parse forest. Filters can then remove the unwanted parses,
+//! and the remaining trees can be turned into the same rule values as the interpreter makes.
+//!
+//! The parser runs on the LR(0) automaton of the grammar, following Tomita's algorithm with
+//! Farshi's fix for empty matches. Groups are parsed separately for each rule that a state
+//! can take them as, and error recovery isn't supported. Parsing stops at the first token
+//! that no stack can take.
+
+use std::{collections::HashMap, ops::Range};
+
+use crate::{
+    diagnostics::{Diagnostic, DiagnosticKind, Found},
+    interpreter::ITokenOrGroup,
+    matches::{Grammar, MatchId, Rule, Symbol, Term},
+    span::{check_byte_ranges, ByteRangesError},
+};
+
+use automaton::{Automaton, StateId};
+use forest::{Family, Forest, NodeId, NodeKind};
+
+pub mod automaton;
+pub mod filters;
+pub mod forest;
+
+/// A parser that finds every parse of an input.
+pub struct GlrParser {
+    grammar: Grammar,
+    automaton: Automaton,
+}
+
+/// The forest of an input, with a diagnostic for where parsing stopped if it was invalid.
+#[derive(Debug, Clone)]
+pub struct ForestParse {
+    pub forest: Forest,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl GlrParser {
+    pub fn new(grammar: Grammar) -> Self {
+        let automaton = Automaton::new(&grammar);
+        Self { grammar, automaton }
+    }
+
+    pub fn grammar(&self) -> &Grammar {
+        &self.grammar
+    }
+
+    pub fn automaton(&self) -> &Automaton {
+        &self.automaton
+    }
+
+    pub fn parse(&self, tokens: Vec<ITokenOrGroup>) -> ForestParse {
+        self.parse_forest(tokens, None)
+    }
+
+    /// Parse, with the byte range of each token in the flattened token stream. The spans of
+    /// trees from the forest then have byte ranges too.
+    pub fn parse_with_spans(
+        &self,
+        tokens: Vec<ITokenOrGroup>,
+        byte_ranges: &[Range<usize>],
+    ) -> Result<ForestParse, ByteRangesError> {
+        check_byte_ranges(&tokens, byte_ranges)?;
+        Ok(self.parse_forest(tokens, Some(byte_ranges)))
+    }
+
+    fn parse_forest(
+        &self,
+        tokens: Vec<ITokenOrGroup>,
+        byte_ranges: Option<&[Range<usize>]>,
+    ) -> ForestParse {
+        let mut forest = Forest::new(byte_ranges);
+        let rule = self.grammar.get(self.grammar.root_id()).rule;
+
+        let mut diagnostics = Vec::new();
+        match self.parse_sequence(&mut forest, rule, &tokens, 0) {
+            Ok(root) => forest.set_root(Some(root)),
+            Err(diagnostic) => diagnostics.push(diagnostic),
+        }
+
+        ForestParse {
+            forest,
+            diagnostics,
+        }
+    }
+
+    /// Parse a sequence of tokens that starts at the position as the rule, returning the
+    /// node that derives all of them.
+    fn parse_sequence(
+        &self,
+        forest: &mut Forest,
+        rule: Rule,
+        tokens: &[ITokenOrGroup],
+        start: usize,
+    ) -> Result<NodeId, Diagnostic> {
+        let start_state = self
+            .automaton
+            .start(rule)
+            .expect("Only the root rule and group rules are parsed");
+
+        let mut stack = Stack {
+            grammar: &self.grammar,
+            automaton: &self.automaton,
+            nodes: vec![StackNode {
+                state: start_state,
+                position: start,
+                edges: Vec::new(),
+            }],
+            frontier: HashMap::from([(start_state, 0)]),
+            forest_nodes: HashMap::new(),
+        };
+
+        let mut position = start;
+        stack.reduce(forest, position);
+
+        for item in tokens {
+            let mut next = HashMap::new();
+            let mut group_errors = Vec::new();
+            let mut groups = HashMap::new();
+
+            for (state, node) in stack.sorted_frontier() {
+                let gotos = &self.automaton.state(state).gotos;
+
+                match item {
+                    ITokenOrGroup::Token(token) => {
+                        let term = Term::Token(*token);
+                        if let Some(&target) = gotos.get(&term) {
+                            let leaf = stack.forest_node(
+                                forest,
+                                NodeKind::Token(*token),
+                                term,
+                                position..position + 1,
+                            );
+                            stack.shift(&mut next, target, node, leaf, position + 1);
+                        }
+                    }
+                    ITokenOrGroup::Group(inner) => {
+                        let mut terms: Vec<_> = gotos
+                            .iter()
+                            .filter(|(term, _)| matches!(term, Term::Group(_, _)))
+                            .map(|(term, target)| (*term, *target))
+                            .collect();
+                        terms.sort_by_key(|(_, target)| *target);
+
+                        for (term, target) in terms {
+                            let Term::Group(group, group_rule) = term else {
+                                continue;
+                            };
+
+                            let result = groups.entry(group_rule).or_insert_with(|| {
+                                self.parse_sequence(forest, group_rule, inner, position)
+                            });
+
+                            match result {
+                                Ok(inner) => {
+                                    let end = position + item.flat_len();
+                                    let leaf = stack.forest_node(
+                                        forest,
+                                        NodeKind::Group(group, *inner),
+                                        term,
+                                        position..end,
+                                    );
+                                    stack.shift(&mut next, target, node, leaf, end);
+                                }
+                                Err(error) => group_errors.push(error.clone()),
+                            }
+                        }
+                    }
+                }
+            }
+
+            if next.is_empty() {
+                // Errors inside of a group are more precise than the group not fitting
+                if let Some(error) = group_errors.into_iter().next() {
+                    return Err(error);
+                }
+
+                let found = match item {
+                    ITokenOrGroup::Token(token) => Found::Token(*token),
+                    ITokenOrGroup::Group(_) => Found::Group,
+                };
+                return Err(stack.unexpected(found, position));
+            }
+
+            position += item.flat_len();
+            stack.frontier = next;
+            stack.reduce(forest, position);
+        }
+
+        stack
+            .forest_nodes
+            .get(&(Term::Rule(rule), start..position))
+            .copied()
+            .ok_or_else(|| stack.unexpected(Found::End, position))
+    }
+}
+
+/// A graph-structured stack, where stacks that reached the same state at the same position
+/// are merged into one node.
+struct Stack<'a> {
+    grammar: &'a Grammar,
+    automaton: &'a Automaton,
+    nodes: Vec<StackNode>,
+    /// The nodes at the current position, by state.
+    frontier: HashMap<StateId, usize>,
+    /// The forest nodes of the sequence, by symbol and range. Groups have their own nodes,
+    /// so that their contents are never shared with the nodes around them.
+    forest_nodes: HashMap<(Term, Range<usize>), NodeId>,
+}
+
+/// An edge of the stack, from a node to a node below it.
+type Edge = (usize, usize);
+
+struct StackNode {
+    state: StateId,
+    position: usize,
+    /// The nodes below this one, with the forest node of the symbol in between.
+    edges: Vec<(usize, NodeId)>,
+}
+
+impl Stack<'_> {
+    /// Get the forest node for a symbol and range, adding it if it's new.
+    fn forest_node(
+        &mut self,
+        forest: &mut Forest,
+        kind: NodeKind,
+        term: Term,
+        tokens: Range<usize>,
+    ) -> NodeId {
+        *self
+            .forest_nodes
+            .entry((term, tokens.clone()))
+            .or_insert_with(|| forest.add_node(kind, tokens))
+    }
+
+    fn sorted_frontier(&self) -> Vec<(StateId, usize)> {
+        let mut frontier: Vec<_> = self.frontier.iter().map(|(&s, &n)| (s, n)).collect();
+        frontier.sort();
+        frontier
+    }
+
+    fn shift(
+        &mut self,
+        next: &mut HashMap<StateId, usize>,
+        state: StateId,
+        below: usize,
+        leaf: NodeId,
+        position: usize,
+    ) {
+        match next.get(&state) {
+            Some(&node) => {
+                if !self.nodes[node].edges.iter().any(|(to, _)| *to == below) {
+                    self.nodes[node].edges.push((below, leaf));
+                }
+            }
+            None => {
+                next.insert(state, self.nodes.len());
+                self.nodes.push(StackNode {
+                    state,
+                    position,
+                    edges: vec![(below, leaf)],
+                });
+            }
+        }
+    }
+
+    /// Apply every reduction at the position, until no new stack nodes or edges are added.
+    fn reduce(&mut self, forest: &mut Forest, position: usize) {
+        let mut queue: Vec<(usize, MatchId, Option<Edge>)> = Vec::new();
+        for (state, node) in self.sorted_frontier() {
+            for &id in &self.automaton.state(state).reductions {
+                queue.push((node, id, None));
+            }
+        }
+
+        while let Some((node, id, through)) = queue.pop() {
+            let rule = self.grammar.get(id).rule;
+            let len = self.grammar.get(id).terms.len();
+
+            for (below, children) in self.paths(node, len, through) {
+                let start = self.nodes[below].position;
+                let parent = self.forest_node(
+                    forest,
+                    NodeKind::Rule(rule),
+                    Term::Rule(rule),
+                    start..position,
+                );
+                forest.add_family(
+                    parent,
+                    Family {
+                        match_id: id,
+                        children,
+                    },
+                );
+
+                let state = self.nodes[below].state;
+                let Some(&target) = self.automaton.state(state).gotos.get(&Term::Rule(rule)) else {
+                    continue;
+                };
+
+                match self.frontier.get(&target) {
+                    Some(&existing) => {
+                        if self.nodes[existing]
+                            .edges
+                            .iter()
+                            .any(|(to, _)| *to == below)
+                        {
+                            continue;
+                        }
+                        self.nodes[existing].edges.push((below, parent));
+
+                        // Paths through the new edge can give new reductions from any node
+                        // at this position, not only from the node with the edge
+                        for (state, node) in self.sorted_frontier() {
+                            for &id in &self.automaton.state(state).reductions {
+                                if !self.grammar.get(id).terms.is_empty() {
+                                    queue.push((node, id, Some((existing, below))));
+                                }
+                            }
+                        }
+                    }
+                    None => {
+                        let new = self.nodes.len();
+                        self.nodes.push(StackNode {
+                            state: target,
+                            position,
+                            edges: vec![(below, parent)],
+                        });
+                        self.frontier.insert(target, new);
+
+                        for &id in &self.automaton.state(target).reductions {
+                            queue.push((new, id, None));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Every path of `len` edges down from the node, with the node at the end of the path
+    /// and the forest nodes along it in input order. If an edge is given, only paths through
+    /// it are returned.
+    fn paths(&self, node: usize, len: usize, through: Option<Edge>) -> Vec<(usize, Vec<NodeId>)> {
+        let mut paths = Vec::new();
+        let mut labels = Vec::new();
+        self.walk(node, len, through, false, &mut labels, &mut paths);
+        paths
+    }
+
+    fn walk(
+        &self,
+        node: usize,
+        len: usize,
+        through: Option<Edge>,
+        used: bool,
+        labels: &mut Vec<NodeId>,
+        paths: &mut Vec<(usize, Vec<NodeId>)>,
+    ) {
+        if len == 0 {
+            if through.is_none() || used {
+                paths.push((node, labels.iter().rev().copied().collect()));
+            }
+            return;
+        }
+
+        for &(below, label) in &self.nodes[node].edges {
+            let used = used || through == Some((node, below));
+            labels.push(label);
+            self.walk(below, len - 1, through, used, labels, paths);
+            labels.pop();
+        }
+    }
+
+    /// A diagnostic for an item that no stack could take, expecting whatever the stacks at
+    /// the position could have taken.
+    fn unexpected(&self, found: Found, position: usize) -> Diagnostic {
+        let mut expected = Vec::new();
+        for (state, _) in self.sorted_frontier() {
+            let mut terms: Vec<_> = self.automaton.state(state).gotos.keys().collect();
+            terms.sort_by_key(|term| format!("{:?}", term));
+
+            for term in terms {
+                let symbol = match term {
+                    Term::Token(token) => Symbol::Token(*token),
+                    Term::Group(group, _) => Symbol::Group(*group),
+                    Term::Rule(_) => continue,
+                };
+
+                if !expected.contains(&symbol) {
+                    expected.push(symbol);
+                }
+            }
+        }
+
+        let kind = DiagnosticKind::Unexpected {
+            expected,
+            found,
+            context: None,
+        };
+        Diagnostic::new(kind, position)
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::matches::{Grammar, MatchId, Rule, Term};
+
+/// A position within a match, e.g. `Add -> Add . Plus Mul`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Item {
+    pub match_id: MatchId,
+    /// The number of terms before the position.
+    pub dot: usize,
+}
+
+impl Item {
+    /// The term after the position, if it isn't at the end of the match.
+    pub fn next_term(self, grammar: &Grammar) -> Option<Term> {
+        grammar.get(self.match_id).terms.get(self.dot).copied()
+    }
+
+    pub fn is_complete(self, grammar: &Grammar) -> bool {
+        self.dot == grammar.get(self.match_id).terms.len()
+    }
+}
+
+pub type StateId = usize;
+
+#[derive(Debug, Clone)]
+pub struct State {
+    /// The items that the state was reached with.
+    pub kernel: Vec<Item>,
+    /// The kernel, along with the start of every match that can come next, sorted.
+    pub items: Vec<Item>,
+    pub gotos: HashMap<Term, StateId>,
+    /// The matches that are complete in this state.
+    pub reductions: Vec<MatchId>,
+}
+
+/// The LR(0) automaton of a grammar, with a start state for the root rule and for the rule
+/// of every group. Recovery matches aren't included.
+#[derive(Debug, Clone)]
+pub struct Automaton {
+    states: Vec<State>,
+    starts: HashMap<Rule, StateId>,
+}
+
+impl Automaton {
+    pub fn new(grammar: &Grammar) -> Self {
+        let mut builder = Builder {
+            grammar,
+            states: Vec::new(),
+            by_kernel: HashMap::new(),
+        };
+
+        let mut start_rules = vec![grammar.get(grammar.root_id()).rule];
+        for (_, m) in grammar.iter_matches() {
+            for term in &m.terms {
+                if let Term::Group(_, rule) = term {
+                    if !start_rules.contains(rule) {
+                        start_rules.push(*rule);
+                    }
+                }
+            }
+        }
+
+        let mut starts = HashMap::new();
+        for rule in start_rules {
+            let kernel = grammar
+                .get_matches_from_rule(rule)
+                .iter()
+                .map(|&match_id| Item { match_id, dot: 0 })
+                .collect();
+            starts.insert(rule, builder.add_state(kernel));
+        }
+
+        builder.build();
+
+        Self {
+            states: builder.states,
+            starts,
+        }
+    }
+
+    /// The state to start parsing a rule from, if the rule is the root or the rule of a group.
+    pub fn start(&self, rule: Rule) -> Option<StateId> {
+        self.starts.get(&rule).copied()
+    }
+
+    pub fn state(&self, id: StateId) -> &State {
+        &self.states[id]
+    }
+
+    pub fn states(&self) -> &[State] {
+        &self.states
+    }
+}
+
+struct Builder<'a> {
+    grammar: &'a Grammar,
+    states: Vec<State>,
+    by_kernel: HashMap<Vec<Item>, StateId>,
+}
+
+impl Builder<'_> {
+    /// Get the state with the kernel, adding it if it's new. Its gotos are filled in by
+    /// `build`.
+    fn add_state(&mut self, mut kernel: Vec<Item>) -> StateId {
+        kernel.sort();
+        kernel.dedup();
+
+        if let Some(&id) = self.by_kernel.get(&kernel) {
+            return id;
+        }
+
+        let items = self.closure(&kernel);
+        let reductions = items
+            .iter()
+            .filter(|item| item.is_complete(self.grammar))
+            .map(|item| item.match_id)
+            .collect();
+
+        let id = self.states.len();
+        self.by_kernel.insert(kernel.clone(), id);
+        self.states.push(State {
+            kernel,
+            items,
+            gotos: HashMap::new(),
+            reductions,
+        });
+
+        id
+    }
+
+    fn closure(&self, kernel: &[Item]) -> Vec<Item> {
+        let mut items: BTreeSet<Item> = kernel.iter().copied().collect();
+        let mut queue = kernel.to_vec();
+
+        while let Some(item) = queue.pop() {
+            if let Some(Term::Rule(rule)) = item.next_term(self.grammar) {
+                for &match_id in self.grammar.get_matches_from_rule(rule) {
+                    let item = Item { match_id, dot: 0 };
+                    if items.insert(item) {
+                        queue.push(item);
+                    }
+                }
+            }
+        }
+
+        items.into_iter().collect()
+    }
+
+    fn build(&mut self) {
+        let mut next = 0;
+        while next < self.states.len() {
+            // Group the items of the state by the term after them, in order
+            let mut moves: Vec<(Term, Vec<Item>)> = Vec::new();
+            for &item in &self.states[next].items {
+                let Some(term) = item.next_term(self.grammar) else {
+                    continue;
+                };
+
+                let moved = Item {
+                    match_id: item.match_id,
+                    dot: item.dot + 1,
+                };
+                match moves.iter_mut().find(|(t, _)| *t == term) {
+                    Some((_, kernel)) => kernel.push(moved),
+                    None => moves.push((term, vec![moved])),
+                }
+            }
+
+            for (term, kernel) in moves {
+                let target = self.add_state(kernel);
+                self.states[next].gotos.insert(term, target);
+            }
+
+            next += 1;
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+
+use crate::matches::{Grammar, MatchId, Term};
+
+use super::forest::{Family, Forest, NodeId, NodeKind};
+
+/// Disambiguation filters, which remove derivations from a forest. They follow the filters
+/// of SDF: priorities and associativity restrict which matches can be the direct children of
+/// which, rejects remove whole nodes, and preferences pick between the derivations of a node.
+#[derive(Debug, Clone, Default)]
+pub struct Filters {
+    /// Children that a match can't have, by match and term index. A missing index means
+    /// any term.
+    forbidden: HashSet<(MatchId, Option<usize>, MatchId)>,
+    rejects: HashSet<MatchId>,
+    prefers: HashSet<MatchId>,
+    avoids: HashSet<MatchId>,
+}
+
+impl Filters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Give a match priority over another, so that the lower match can't be a direct child
+    /// of the higher one, e.g. an addition can't be an operand of a multiplication.
+    pub fn set_priority(&mut self, higher: MatchId, lower: MatchId) {
+        self.forbidden.insert((higher, None, lower));
+    }
+
+    /// Make a binary match group to the left, so that it can't be its own last term.
+    pub fn set_left_assoc(&mut self, grammar: &Grammar, id: MatchId) {
+        let last = grammar.get(id).terms.len().saturating_sub(1);
+        self.forbidden.insert((id, Some(last), id));
+    }
+
+    /// Make a binary match group to the right, so that it can't be its own first term.
+    pub fn set_right_assoc(&mut self, id: MatchId) {
+        self.forbidden.insert((id, Some(0), id));
+    }
+
+    /// Reject a match, so that any node with a derivation using it is removed, along with
+    /// its other derivations. This is how keywords are kept out of identifiers.
+    pub fn reject(&mut self, id: MatchId) {
+        self.rejects.insert(id);
+    }
+
+    /// Prefer a match, so that other derivations of a node that has one using it are removed.
+    pub fn prefer(&mut self, id: MatchId) {
+        self.prefers.insert(id);
+    }
+
+    /// Avoid a match, so that derivations using it are removed from nodes that have others.
+    pub fn avoid(&mut self, id: MatchId) {
+        self.avoids.insert(id);
+    }
+
+    /// Remove the derivations that the filters don't allow. Nodes without any derivations
+    /// left are removed from their parents, and the forest loses its root if the root has
+    /// none left.
+    pub fn apply(&self, grammar: &Grammar, forest: &mut Forest) {
+        let ids: Vec<NodeId> = forest.ids().collect();
+
+        for &id in &ids {
+            let families = &mut forest.node_mut(id).families;
+
+            if families.iter().any(|f| self.rejects.contains(&f.match_id)) {
+                families.clear();
+            }
+
+            if families.iter().any(|f| self.prefers.contains(&f.match_id)) {
+                families.retain(|f| self.prefers.contains(&f.match_id));
+            }
+
+            if families.iter().any(|f| !self.avoids.contains(&f.match_id)) {
+                families.retain(|f| !self.avoids.contains(&f.match_id));
+            }
+        }
+
+        // Removing derivations can leave other nodes without any, so repeat until nothing
+        // changes
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for &id in &ids {
+                if !matches!(forest.node(id).kind, NodeKind::Rule(_)) {
+                    continue;
+                }
+
+                let families = forest.node(id).families.clone();
+                let kept: Vec<Family> = families
+                    .iter()
+                    .filter(|family| self.is_allowed(grammar, forest, family))
+                    .cloned()
+                    .collect();
+
+                if kept.len() != families.len() {
+                    forest.node_mut(id).families = kept;
+                    changed = true;
+                }
+            }
+        }
+
+        if forest.root().is_some_and(|root| is_dead(forest, root)) {
+            forest.set_root(None);
+        }
+    }
+
+    fn is_allowed(&self, grammar: &Grammar, forest: &Forest, family: &Family) -> bool {
+        family.children.iter().enumerate().all(|(index, &child)| {
+            !is_dead(forest, child) && !self.is_forbidden(grammar, forest, family, index, child)
+        })
+    }
+
+    /// Whether every derivation of a child is forbidden within the parent. Children that
+    /// were collapsed through single term matches (e.g. `Expr -> Add`) are looked through.
+    fn is_forbidden(
+        &self,
+        grammar: &Grammar,
+        forest: &Forest,
+        parent: &Family,
+        index: usize,
+        child: NodeId,
+    ) -> bool {
+        // Groups are how a lower priority match is written inside of a higher one
+        if !matches!(forest.node(child).kind, NodeKind::Rule(_)) {
+            return false;
+        }
+
+        let mut seen = HashSet::new();
+        let mut queue = vec![child];
+        let mut any_allowed = false;
+
+        while let Some(id) = queue.pop() {
+            if !seen.insert(id) {
+                continue;
+            }
+
+            for family in &forest.node(id).families {
+                let forbidden = [Some(index), None].iter().any(|&i| {
+                    self.forbidden
+                        .contains(&(parent.match_id, i, family.match_id))
+                });
+                if forbidden {
+                    continue;
+                }
+
+                match grammar.get(family.match_id).terms.as_slice() {
+                    [Term::Rule(_)] => queue.extend(&family.children),
+                    _ => any_allowed = true,
+                }
+            }
+        }
+
+        !any_allowed
+    }
+}
+
+/// Whether a node has no derivations left.
+fn is_dead(forest: &Forest, id: NodeId) -> bool {
+    let node = forest.node(id);
+    match node.kind {
+        NodeKind::Token(_) => false,
+        NodeKind::Rule(_) => node.families.is_empty(),
+        NodeKind::Group(_, inner) => is_dead(forest, inner),
+    }
+}
@@ -0,0 +1,304 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+
+use crate::{
+    interpreter::{RuleValue, Value},
+    matches::{Grammar, Group, MatchId, Rule, Token},
+    span::Span,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Token(Token),
+    /// A rule, with its derivations in the families of the node.
+    Rule(Rule),
+    /// A group, with the node of its contents.
+    Group(Group, NodeId),
+}
+
+/// Everything that derives one symbol from one range of tokens. Token ranges are the same
+/// as in spans.
+#[derive(Debug, Clone)]
+pub struct ForestNode {
+    pub kind: NodeKind,
+    pub tokens: Range<usize>,
+    /// The ways that a rule node can be derived. More than one family means that the node
+    /// is ambiguous, and none means that every derivation was filtered out.
+    pub families: Vec<Family>,
+}
+
+/// One derivation of a rule node, from a match and the nodes of its terms.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Family {
+    pub match_id: MatchId,
+    pub children: Vec<NodeId>,
+}
+
+/// A shared packed parse forest. Nodes are shared between every derivation that uses them,
+/// so the forest stays polynomial in size even when the number of trees is exponential.
+#[derive(Debug, Clone, Default)]
+pub struct Forest {
+    nodes: Vec<ForestNode>,
+    root: Option<NodeId>,
+    byte_ranges: Option<Vec<Range<usize>>>,
+}
+
+impl Forest {
+    /// An empty forest for an input with the byte ranges, if it has them.
+    pub fn new(byte_ranges: Option<&[Range<usize>]>) -> Self {
+        Self {
+            byte_ranges: byte_ranges.map(|ranges| ranges.to_vec()),
+            ..Default::default()
+        }
+    }
+
+    pub fn set_root(&mut self, root: Option<NodeId>) {
+        self.root = root;
+    }
+
+    /// The node that derives the whole input from the root rule, if the input was valid.
+    pub fn root(&self) -> Option<NodeId> {
+        self.root
+    }
+
+    pub fn node(&self, id: NodeId) -> &ForestNode {
+        &self.nodes[id.0]
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> &mut ForestNode {
+        &mut self.nodes[id.0]
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = NodeId> {
+        (0..self.nodes.len()).map(NodeId)
+    }
+
+    pub fn add_node(&mut self, kind: NodeKind, tokens: Range<usize>) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(ForestNode {
+            kind,
+            tokens,
+            families: Vec::new(),
+        });
+
+        id
+    }
+
+    pub fn add_family(&mut self, id: NodeId, family: Family) {
+        let families = &mut self.nodes[id.0].families;
+        if !families.contains(&family) {
+            families.push(family);
+        }
+    }
+
+    /// The rule nodes under the root that have more than one derivation.
+    pub fn ambiguous_nodes(&self) -> Vec<NodeId> {
+        let mut nodes: Vec<_> = self
+            .reachable()
+            .into_iter()
+            .filter(|&id| self.node(id).families.len() > 1)
+            .collect();
+        nodes.sort();
+        nodes
+    }
+
+    pub fn is_ambiguous(&self) -> bool {
+        !self.ambiguous_nodes().is_empty()
+    }
+
+    pub fn reachable(&self) -> HashSet<NodeId> {
+        let mut seen = HashSet::new();
+        let mut queue: Vec<_> = self.root.into_iter().collect();
+
+        while let Some(id) = queue.pop() {
+            if !seen.insert(id) {
+                continue;
+            }
+
+            match self.node(id).kind {
+                NodeKind::Token(_) => {}
+                NodeKind::Rule(_) => {
+                    for family in &self.node(id).families {
+                        queue.extend(&family.children);
+                    }
+                }
+                NodeKind::Group(_, inner) => queue.push(inner),
+            }
+        }
+
+        seen
+    }
+
+    /// The number of trees in the forest, saturating at `u64::MAX`. Derivations that
+    /// contain themselves (e.g. through `A -> A`) aren't counted.
+    pub fn count_trees(&self) -> u64 {
+        let mut counts = HashMap::new();
+        match self.root {
+            Some(root) => self.count_node(root, &mut counts, &mut HashSet::new()),
+            None => 0,
+        }
+    }
+
+    fn count_node(
+        &self,
+        id: NodeId,
+        counts: &mut HashMap<NodeId, u64>,
+        visiting: &mut HashSet<NodeId>,
+    ) -> u64 {
+        if let Some(&count) = counts.get(&id) {
+            return count;
+        }
+
+        if !visiting.insert(id) {
+            return 0;
+        }
+
+        let node = self.node(id);
+        let count = match node.kind {
+            NodeKind::Token(_) => 1,
+            NodeKind::Group(_, inner) => self.count_node(inner, counts, visiting),
+            NodeKind::Rule(_) => node
+                .families
+                .iter()
+                .map(|family| {
+                    family.children.iter().fold(1u64, |total, &child| {
+                        total.saturating_mul(self.count_node(child, counts, visiting))
+                    })
+                })
+                .fold(0u64, |total, count| total.saturating_add(count)),
+        };
+
+        visiting.remove(&id);
+        counts.insert(id, count);
+        count
+    }
+
+    /// The first tree in the forest. At each node, this uses the family with the fewest
+    /// empty children, and then the lowest match.
+    ///
+    /// This isn't how the interpreter picks between parses, so for an ambiguous input it may
+    /// be a different tree than the interpreter's, which is still one of `trees`. Use filters
+    /// to pick a specific tree.
+    pub fn first_tree(&self, grammar: &Grammar) -> Option<RuleValue> {
+        self.trees(grammar, 1).pop()
+    }
+
+    /// Up to `limit` trees from the forest, as the rule values that the interpreter would
+    /// have produced for them.
+    pub fn trees(&self, grammar: &Grammar, limit: usize) -> Vec<RuleValue> {
+        let Some(root) = self.root else {
+            return Vec::new();
+        };
+
+        let mut builder = TreeBuilder {
+            forest: self,
+            grammar,
+            limit,
+            visiting: HashSet::new(),
+        };
+
+        builder
+            .values(root)
+            .into_iter()
+            .filter_map(|value| match value {
+                Value::Rule(rule) => Some(rule),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+struct TreeBuilder<'a> {
+    forest: &'a Forest,
+    grammar: &'a Grammar,
+    limit: usize,
+    /// The nodes on the current path, to skip derivations that contain themselves.
+    visiting: HashSet<NodeId>,
+}
+
+impl TreeBuilder<'_> {
+    fn span(&self, tokens: Range<usize>) -> Span {
+        Span::new(tokens, self.forest.byte_ranges.as_deref())
+    }
+
+    /// Every value that a node can be, up to the limit.
+    fn values(&mut self, id: NodeId) -> Vec<Value> {
+        if !self.visiting.insert(id) {
+            return Vec::new();
+        }
+
+        let node = self.forest.node(id);
+        let values = match node.kind {
+            NodeKind::Token(token) => vec![Value::Token(token, self.span(node.tokens.clone()))],
+            NodeKind::Group(_, inner) => self.values(inner),
+            NodeKind::Rule(_) => {
+                // Like the interpreter, avoid empty matches where there's another way
+                let mut families: Vec<_> = node.families.iter().collect();
+                families.sort_by_key(|family| {
+                    let empty = family.children.iter().filter(|&&child| {
+                        let tokens = &self.forest.node(child).tokens;
+                        tokens.start == tokens.end
+                    });
+                    (empty.count(), family.match_id)
+                });
+
+                let mut values = Vec::new();
+                for family in families {
+                    for children in self.children(&family.children) {
+                        if values.len() == self.limit {
+                            break;
+                        }
+
+                        let span = self.span(node.tokens.clone());
+                        let value =
+                            RuleValue::from_match(self.grammar, family.match_id, children, span);
+                        values.push(Value::Rule(value));
+                    }
+                }
+
+                values
+            }
+        };
+
+        self.visiting.remove(&id);
+        values
+    }
+
+    /// Every combination of values of the children, up to the limit.
+    fn children(&mut self, children: &[NodeId]) -> Vec<Vec<Value>> {
+        let mut combinations = vec![Vec::new()];
+
+        for &child in children {
+            let values = self.values(child);
+            let mut next = Vec::new();
+            'outer: for combination in &combinations {
+                for value in &values {
+                    if next.len() == self.limit {
+                        break 'outer;
+                    }
+
+                    let mut combination: Vec<Value> = combination.clone();
+                    combination.push(value.clone());
+                    next.push(combination);
+                }
+            }
+
+            combinations = next;
+        }
+
+        combinations
+    }
+}
@@ -1,31 +1,84 @@
 use core::panic;
+use std::{collections::HashSet, ops::Range};
+
+pub mod events;
+pub mod incremental;
+mod token_source;
+pub mod tree_sink;
+pub mod visit;
+
+pub use token_source::{BufferedSource, TokenSource};
+pub use tree_sink::{TreeSink, ValueBuilder};
+
+use incremental::{CachedGroup, Reuse};
+use tree_sink::{NodeInfo, RecordingSink};
 
 use crate::{
-    matches::{MatchId, Rule, Token},
+    diagnostics::{
+        collapse_expected, Diagnostic, DiagnosticKind, ErrorCounts, ErrorOptions, ErrorReporter,
+        Found,
+    },
+    matches::{Grammar, MatchId, Rule, Symbol, Term, Token},
     solver::{
         EmptySolverRuleValue, EmptyWrapAction, FirstSet, FollowSet, GrammarSolver, MatchIndex,
         TokenOrGroup,
     },
+    span::{check_byte_ranges, ByteRangesError, Span},
 };
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ITokenOrGroup {
     Token(Token),
     Group(Vec<ITokenOrGroup>),
 }
 
+impl ITokenOrGroup {
+    /// The number of tokens this item takes up in the flattened token stream.
+    pub fn flat_len(&self) -> usize {
+        match self {
+            ITokenOrGroup::Token(_) => 1,
+            ITokenOrGroup::Group(tokens) => tokens.iter().map(|t| t.flat_len()).sum(),
+        }
+    }
+}
+
 pub struct TokenReader<'a> {
-    pub tokens: &'a [ITokenOrGroup],
-    pub index: usize,
+    source: Box<dyn TokenSource + 'a>,
+    /// The index of the next token within the flattened token stream.
+    pub position: usize,
+    /// The byte range of each token in the flattened token stream, if known.
+    pub byte_ranges: Option<&'a [Range<usize>]>,
 }
 
 impl<'a> TokenReader<'a> {
-    pub fn new(tokens: &'a [ITokenOrGroup]) -> Self {
-        Self { tokens, index: 0 }
+    pub fn new(source: impl TokenSource + 'a) -> Self {
+        Self::new_at(source, 0, None)
+    }
+
+    /// Create a reader for tokens that start at a given position in the flattened token stream.
+    pub fn new_at(
+        source: impl TokenSource + 'a,
+        position: usize,
+        byte_ranges: Option<&'a [Range<usize>]>,
+    ) -> Self {
+        Self {
+            source: Box::new(source),
+            position,
+            byte_ranges,
+        }
+    }
+
+    pub fn set_lookahead(&mut self, lookahead: usize) {
+        self.source.set_lookahead(lookahead);
+    }
+
+    /// Get the span of a range of positions in the flattened token stream.
+    pub fn span(&self, tokens: Range<usize>) -> Span {
+        Span::new(tokens, self.byte_ranges)
     }
 
     pub fn does_match(&self, by: usize, token2: &TokenOrGroup) -> bool {
-        let token = self.tokens.get(self.index + by);
+        let token = self.source.peek(by);
 
         let Some(token) = token else {
             return false;
@@ -43,43 +96,149 @@ impl<'a> TokenReader<'a> {
         }
     }
 
-    pub fn next(&mut self) -> Option<&ITokenOrGroup> {
-        let token = self.tokens.get(self.index);
-        self.index += 1;
+    /// Look at the item `by` items ahead, within the lookahead of the source.
+    pub fn peek_at(&self, by: usize) -> Option<&ITokenOrGroup> {
+        self.source.peek(by)
+    }
+
+    pub fn peek(&self) -> Option<&ITokenOrGroup> {
+        self.source.peek(0)
+    }
+}
+
+impl Iterator for TokenReader<'_> {
+    type Item = ITokenOrGroup;
+
+    fn next(&mut self) -> Option<ITokenOrGroup> {
+        let token = self.source.next();
+        if let Some(token) = &token {
+            self.position += token.flat_len();
+        }
         token
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Value {
-    Token(Token),
+    Token(Token, Span),
     Rule(RuleValue),
-    Error,
+    Error(Span),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct MatchValue {
-    pub match_id: MatchId,
-    pub values: Vec<Value>,
+impl Value {
+    pub fn span(&self) -> &Span {
+        match self {
+            Value::Token(_, span) => span,
+            Value::Rule(rule) => &rule.span,
+            Value::Error(span) => span,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RuleValue {
     pub rule: Rule,
     pub match_id: MatchId,
     pub values: Vec<Value>,
+    /// Whether this value was parsed using a recovery match.
+    pub is_error: bool,
+    pub span: Span,
+}
+
+impl RuleValue {
+    /// The value of a match that was parsed without error recovery, collapsed into its only
+    /// value like the interpreter does if the match is a single rule or group. Empty values
+    /// are never collapsed.
+    pub fn from_match(
+        grammar: &Grammar,
+        match_id: MatchId,
+        values: Vec<Value>,
+        span: Span,
+    ) -> Self {
+        let m = grammar.get(match_id);
+        let is_single_rule = matches!(m.terms.as_slice(), [Term::Rule(_) | Term::Group(_, _)]);
+        if is_single_rule && span.tokens.start < span.tokens.end {
+            if let [Value::Rule(inner)] = values.as_slice() {
+                let mut inner = inner.clone();
+                inner.rule = m.rule;
+                inner.span = span;
+                return inner;
+            }
+        }
+
+        Self {
+            rule: m.rule,
+            match_id,
+            values,
+            is_error: false,
+            span,
+        }
+    }
+
+    /// The value of an input that a parser without error recovery rejected: the root match,
+    /// with a single error over the whole span.
+    pub fn root_error(grammar: &Grammar, span: Span) -> Self {
+        Self {
+            rule: grammar.get(grammar.root_id()).rule,
+            match_id: grammar.root_id(),
+            values: vec![Value::Error(span.clone())],
+            is_error: true,
+            span,
+        }
+    }
+}
+
+/// The output of a parse, including any diagnostics found along the way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParseResult {
+    pub value: RuleValue,
+    pub diagnostics: Vec<Diagnostic>,
+    pub error_counts: ErrorCounts,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct StackItem {
     linked_to_above: bool,
-    match_value: MatchValue,
+    match_id: MatchId,
+    /// The number of values in the match so far. The values themselves go to the tree sink.
+    len: usize,
+    /// Whether the first value of the match is a rule, so that a match with only that value
+    /// can collapse into it.
+    first_is_rule: bool,
+    /// The token position that diagnostics for this item are reported at. This is where
+    /// the item was pushed, or where it was reinterpreted as a recovery match.
+    report_at: usize,
+    /// The token position where the match starts.
+    start: usize,
+    /// The token position where the last value ends, or the start if there are no values.
+    end: usize,
+    /// Whether the sink was told that the item's node is sure to stay in the tree.
+    pinned: bool,
+}
+
+/// The kind of a value appended to a stack item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValueKind {
+    Token,
+    Rule,
+    Error,
 }
 
 pub struct Interpreter<'a> {
     stack: Vec<StackItem>,
     token_reader: TokenReader<'a>,
     solver: &'a GrammarSolver,
+    /// Whether recovery matches should be considered for the next step.
+    recovering: bool,
+    reporter: ErrorReporter,
+    sink: &'a mut dyn TreeSink,
+    /// The groups of a previous parse that can be reused, when reparsing after an edit.
+    reuse: Option<Reuse<'a>>,
+    /// The groups parsed at this nesting level, if they're being kept for reuse.
+    groups: Option<Vec<CachedGroup>>,
 }
 
 enum WrapStatusAction<'a> {
@@ -110,7 +269,8 @@ enum WrapStatus<'a> {
 }
 
 enum ReduceSolveResult {
-    Finished(RuleValue),
+    /// The root was sealed, covering the given token positions.
+    Finished(Range<usize>),
     Success,
     Error,
 }
@@ -126,19 +286,109 @@ enum HasChild {
     No,
 }
 
-pub fn solve(solver: &GrammarSolver, tokens: Vec<ITokenOrGroup>) -> RuleValue {
-    let interpreter = Interpreter {
-        stack: Vec::new(),
-        token_reader: TokenReader::new(&tokens),
-        solver: &solver,
-    };
+pub fn solve(solver: &GrammarSolver, tokens: Vec<ITokenOrGroup>) -> ParseResult {
+    solve_with_options(solver, tokens, ErrorOptions::default())
+}
+
+pub fn solve_with_options(
+    solver: &GrammarSolver,
+    tokens: Vec<ITokenOrGroup>,
+    options: ErrorOptions,
+) -> ParseResult {
+    solve_source(solver, BufferedSource::new(tokens), options)
+}
+
+/// Parse tokens pulled from a token source as the parse goes, instead of a complete list.
+pub fn solve_source(
+    solver: &GrammarSolver,
+    source: impl TokenSource,
+    options: ErrorOptions,
+) -> ParseResult {
+    solve_tokens(solver, TokenReader::new(source), options)
+}
+
+/// Parse tokens that have known byte ranges in the source text, so that the spans of the
+/// resulting values include byte ranges. There is one byte range for each token in the
+/// flattened token stream, and it's an error to give fewer.
+pub fn solve_with_spans(
+    solver: &GrammarSolver,
+    tokens: Vec<ITokenOrGroup>,
+    byte_ranges: &[Range<usize>],
+    options: ErrorOptions,
+) -> Result<ParseResult, ByteRangesError> {
+    check_byte_ranges(&tokens, byte_ranges)?;
+    let reader = TokenReader::new_at(BufferedSource::new(tokens), 0, Some(byte_ranges));
+    Ok(solve_tokens(solver, reader, options))
+}
+
+/// Parse tokens from a token source into a tree sink, instead of building rule values.
+/// Byte ranges work the same way as in `solve_with_spans`, except that the length of a
+/// source isn't known up front, so tokens past the end of the byte ranges get spans
+/// without bytes.
+pub fn solve_into(
+    solver: &GrammarSolver,
+    source: impl TokenSource,
+    byte_ranges: Option<&[Range<usize>]>,
+    sink: &mut dyn TreeSink,
+    options: ErrorOptions,
+) -> (Vec<Diagnostic>, ErrorCounts) {
+    let reader = TokenReader::new_at(source, 0, byte_ranges);
+    solve_tokens_into(solver, reader, sink, options)
+}
+
+fn solve_tokens(
+    solver: &GrammarSolver,
+    token_reader: TokenReader,
+    options: ErrorOptions,
+) -> ParseResult {
+    let mut builder = ValueBuilder::new();
+    let (diagnostics, error_counts) =
+        solve_tokens_into(solver, token_reader, &mut builder, options);
+
+    ParseResult {
+        value: builder.finish(),
+        diagnostics,
+        error_counts,
+    }
+}
 
-    interpreter.solve(solver.root_rule())
+fn solve_tokens_into(
+    solver: &GrammarSolver,
+    token_reader: TokenReader,
+    sink: &mut dyn TreeSink,
+    options: ErrorOptions,
+) -> (Vec<Diagnostic>, ErrorCounts) {
+    let reporter = ErrorReporter::new(options);
+    let mut interpreter = Interpreter::new(solver, token_reader, reporter, sink);
+
+    interpreter.solve(solver.root_rule());
+    interpreter.reporter.finish()
 }
 
 impl<'a> Interpreter<'a> {
-    fn solve(mut self, root_rule: Rule) -> RuleValue {
-        let first_set = self.solver.first_set_for_rule(root_rule);
+    fn new(
+        solver: &'a GrammarSolver,
+        mut token_reader: TokenReader<'a>,
+        reporter: ErrorReporter,
+        sink: &'a mut dyn TreeSink,
+    ) -> Self {
+        token_reader.set_lookahead(solver.max_lookahead());
+
+        Self {
+            stack: Vec::new(),
+            token_reader,
+            solver,
+            recovering: false,
+            reporter,
+            sink,
+            reuse: None,
+            groups: None,
+        }
+    }
+
+    /// Parse the input into the sink, returning the token positions covered by the root.
+    fn solve(&mut self, root_rule: Rule) -> Range<usize> {
+        let first_set = self.tables().first_set_for_rule(root_rule);
         if !self.solve_first_set(&[], first_set) {
             panic!("No first set matched");
         }
@@ -146,24 +396,43 @@ impl<'a> Interpreter<'a> {
         loop {
             self.print_stack();
 
-            let mi = self.get_match_index_of_top_stack_item();
-
-            let follow_set = self.solver.follow_set_for_match(mi);
-            if self.solve_follow_sets(follow_set) {
-                continue;
+            let mut result = self.solve_step();
+            if let ReduceSolveResult::Error = result {
+                result = self.solve_recovery_matches();
+            }
+            if let ReduceSolveResult::Error = result {
+                result = self.solve_committed_prefix();
             }
 
-            let reduce_result = self.solve_reduce_sets();
-            match reduce_result {
-                ReduceSolveResult::Finished(value) => return value,
+            match result {
+                ReduceSolveResult::Finished(range) => {
+                    self.report_trailing();
+                    return range;
+                }
                 ReduceSolveResult::Success => continue,
                 ReduceSolveResult::Error => {
                     // Continue
                 }
             }
 
+            self.report_unexpected();
+
             println!("Error");
-            while !self.solve_error() {
+            let committed = self.get_committed_stack_item();
+            loop {
+                // Committed items aren't unwound, unless the end of the input is reached or
+                // the token is where the root continues (e.g. `Eof`), which closes them anyway
+                let floor = match committed {
+                    Some(i) if self.token_reader.peek().is_some() && !self.does_root_continue() => {
+                        i
+                    }
+                    _ => 0,
+                };
+
+                if self.solve_error(floor) {
+                    break;
+                }
+
                 // While not solved, skip tokens
                 let token = self.token_reader.next();
                 println!("Skipping token: {:?}", token);
@@ -171,6 +440,338 @@ impl<'a> Interpreter<'a> {
         }
     }
 
+    /// Report that the parser couldn't continue at the current token.
+    fn report_unexpected(&mut self) {
+        let found = match self.token_reader.peek() {
+            Some(ITokenOrGroup::Token(token)) => Found::Token(*token),
+            Some(ITokenOrGroup::Group(_)) => Found::Group,
+            None => Found::End,
+        };
+
+        let expected = collapse_expected(self.solver, &self.get_expected_symbols());
+        let context = self.get_committed_stack_item().map(|i| {
+            let match_id = self.stack[i].match_id;
+            let commit = self.solver.grammar().get_commit_point(match_id).unwrap();
+            commit.label.clone()
+        });
+
+        let kind = DiagnosticKind::Unexpected {
+            expected,
+            found,
+            context,
+        };
+        self.reporter
+            .report(Diagnostic::new(kind, self.token_reader.position));
+    }
+
+    /// Report tokens after the end of the root match, which the root can't take. They're
+    /// left out of the tree.
+    fn report_trailing(&mut self) {
+        let found = match self.token_reader.peek() {
+            Some(ITokenOrGroup::Token(token)) => Found::Token(*token),
+            Some(ITokenOrGroup::Group(_)) => Found::Group,
+            None => return,
+        };
+
+        let kind = DiagnosticKind::Trailing { found };
+        self.reporter
+            .report(Diagnostic::new(kind, self.token_reader.position));
+    }
+
+    /// Whether the next token fits the root item, after the rest of the stack is closed.
+    fn does_root_continue(&self) -> bool {
+        let mut index = if self.stack.len() > 1 {
+            self.get_match_index_of_stack_item_if_child_inserted(0)
+        } else {
+            self.get_match_index_of_stack_item(0)
+        };
+        let len = self.solver.get_match(index.id).terms.len();
+
+        while index.index <= len {
+            if self.does_follow_set_match_for(index) {
+                return true;
+            }
+            index.index += 1;
+        }
+
+        false
+    }
+
+    /// Find the innermost stack item that has passed its commit point, and can't be sealed yet.
+    fn get_committed_stack_item(&self) -> Option<usize> {
+        let grammar = self.solver.grammar();
+        let top = self.stack.len() - 1;
+
+        (0..self.stack.len()).rev().find(|&i| {
+            let item = &self.stack[i];
+            let Some(commit) = grammar.get_commit_point(item.match_id) else {
+                return false;
+            };
+
+            if item.len < commit.index {
+                return false;
+            }
+
+            // Items below the top still have a child that will be inserted into them
+            let mi = if i == top {
+                self.get_match_index_of_stack_item(i)
+            } else {
+                self.get_match_index_of_stack_item_if_child_inserted(i)
+            };
+
+            self.tables().get_seal_action_for_match(mi).is_none()
+        })
+    }
+
+    /// Collect the symbols that would have allowed the parser to continue. This walks down
+    /// the stack for as long as the items could be sealed, including the matches that the
+    /// items could be wrapped into.
+    fn get_expected_symbols(&self) -> Vec<Symbol> {
+        let tables = self.tables();
+        let mut expected = Vec::new();
+
+        let mut i = self.stack.len() - 1;
+        let mut has_child = HasChild::No;
+        loop {
+            let mi = match has_child {
+                HasChild::Yes => self.get_match_index_of_stack_item_if_child_inserted(i),
+                HasChild::No => self.get_match_index_of_stack_item(i),
+            };
+
+            let mut follow_sets = vec![tables.follow_set_for_match(mi)];
+
+            // Items can only be wrapped or inserted into their parent once they can be sealed
+            let can_seal = tables.get_seal_action_for_match(mi).is_some();
+
+            if can_seal && !self.stack[i].linked_to_above && i > 0 {
+                let parent_mi = self.get_match_index_of_stack_item(i - 1);
+                let parent_term = self
+                    .solver
+                    .get_match(parent_mi.id)
+                    .terms
+                    .get(parent_mi.index);
+                let child_rule = self.solver.get_match_rule(mi.id);
+
+                if let Some(parent_rule) = parent_term.and_then(|t| t.as_rule()) {
+                    if let Some(wrap_data) = tables.get_wrap_data(*parent_rule, child_rule) {
+                        for action in &wrap_data.wrap_actions {
+                            follow_sets.push(tables.follow_set_for_match(action.if_matches));
+                        }
+                    }
+                }
+            }
+
+            for set in follow_sets.into_iter().flatten() {
+                let symbol = match set {
+                    FollowSet::Direct(direct) => direct.tokens.first().map(|t| t.as_symbol()),
+                    FollowSet::Enter(enter) => Some(Symbol::Rule(enter.rule)),
+                };
+
+                if let Some(symbol) = symbol {
+                    if !expected.contains(&symbol) {
+                        expected.push(symbol);
+                    }
+                }
+            }
+
+            if i == 0 || !can_seal {
+                break;
+            }
+
+            i -= 1;
+            has_child = HasChild::Yes;
+        }
+
+        expected
+    }
+
+    /// Returns the solver tables to use for the current step. The recovery tables are used
+    /// while recovering, and for as long as a recovery match is on the stack.
+    fn tables(&self) -> &'a GrammarSolver {
+        let solver: &'a GrammarSolver = self.solver;
+        let Some(recovery) = solver.recovery_solver() else {
+            return solver;
+        };
+
+        let grammar = solver.grammar();
+        let has_recovery_match = self
+            .stack
+            .iter()
+            .any(|item| grammar.is_recovery_match(item.match_id));
+
+        if self.recovering || has_recovery_match {
+            recovery
+        } else {
+            solver
+        }
+    }
+
+    /// Try to continue parsing using the recovery matches of the grammar.
+    fn solve_recovery_matches(&mut self) -> ReduceSolveResult {
+        if self.solver.recovery_solver().is_none() {
+            return ReduceSolveResult::Error;
+        }
+
+        // If a recovery match is already on the stack, then they've already been tried.
+        if self.tables().grammar().includes_recovery_matches() {
+            return ReduceSolveResult::Error;
+        }
+
+        self.recovering = true;
+
+        let mut result = self.solve_step();
+
+        if let ReduceSolveResult::Error = result {
+            result = self.solve_recovery_prefixes();
+        }
+
+        self.recovering = false;
+
+        result
+    }
+
+    /// A stack item may have been parsed as a regular match, while a recovery match with the
+    /// same prefix would allow it to continue (e.g. a missing semicolon). Items below the top
+    /// also count the child above them, which is inserted into them next, so a mistake right
+    /// after a rule term is found too. Items are tried from the top down.
+    fn solve_recovery_prefixes(&mut self) -> ReduceSolveResult {
+        let grammar = self.solver.grammar();
+
+        for index in (0..self.stack.len()).rev() {
+            let item = &self.stack[index];
+            let original_id = item.match_id;
+            let original_report_at = item.report_at;
+            let consumed = if index == self.stack.len() - 1 {
+                item.len
+            } else {
+                item.len + 1
+            };
+
+            let original = grammar.get(original_id);
+            if original.terms.len() < consumed {
+                continue;
+            }
+
+            let candidates: Vec<MatchId> = self
+                .tables()
+                .grammar()
+                .get_matches_from_rule(original.rule)
+                .iter()
+                .copied()
+                .filter(|&id| grammar.is_recovery_match(id))
+                .filter(|&id| {
+                    let terms = &grammar.get(id).terms;
+                    terms.len() >= consumed && terms[..consumed] == original.terms[..consumed]
+                })
+                .collect();
+
+            for id in candidates {
+                let item = &mut self.stack[index];
+                item.match_id = id;
+                item.report_at = self.token_reader.position;
+
+                let result = self.solve_step();
+                if !matches!(result, ReduceSolveResult::Error) {
+                    return result;
+                }
+            }
+
+            let item = &mut self.stack[index];
+            item.match_id = original_id;
+            item.report_at = original_report_at;
+        }
+
+        ReduceSolveResult::Error
+    }
+
+    /// Partially match a token run that reaches the commit point of its match. This way, the
+    /// error is reported at the failing token within the run, instead of at its start.
+    fn solve_committed_prefix(&mut self) -> ReduceSolveResult {
+        let mi = self.get_match_index_of_top_stack_item();
+
+        for set in self.tables().follow_set_for_match(mi) {
+            match set {
+                FollowSet::Direct(direct) => {
+                    let start = mi.index + direct.append_extra_emptys.len();
+                    if let Some(len) = self.get_committed_prefix_len(mi.id, start, &direct.tokens) {
+                        self.append_emptys(&direct.append_extra_emptys);
+                        self.parse_tokens(&direct.tokens[..len]);
+                        return ReduceSolveResult::Success;
+                    }
+                }
+                FollowSet::Enter(enter) => {
+                    for first_set in self.tables().first_set_for_rule(enter.rule) {
+                        // The last pushed match is the one that the tokens belong to
+                        let Some(last) = first_set.then.last() else {
+                            continue;
+                        };
+
+                        let start = last.append_empty_fields.len();
+                        if self
+                            .get_committed_prefix_len(last.id, start, &first_set.tokens)
+                            .is_some()
+                        {
+                            self.append_emptys(&enter.append_extra);
+                            self.insert_first_set_data(first_set);
+                            return ReduceSolveResult::Success;
+                        }
+                    }
+                }
+            }
+        }
+
+        ReduceSolveResult::Error
+    }
+
+    /// Get the number of tokens at the start of `tokens` that match the input, if they reach
+    /// the commit point of the match. The tokens start at term `start` within the match.
+    fn get_committed_prefix_len(
+        &self,
+        id: MatchId,
+        start: usize,
+        tokens: &[TokenOrGroup],
+    ) -> Option<usize> {
+        let commit = self.solver.grammar().get_commit_point(id)?;
+
+        let len = tokens
+            .iter()
+            .enumerate()
+            .take_while(|(i, token)| self.token_reader.does_match(*i, token))
+            .count();
+
+        if len > 0 && start + len >= commit.index {
+            Some(len)
+        } else {
+            None
+        }
+    }
+
+    /// Try to make progress using the follow sets, and then the reduce sets.
+    fn solve_step(&mut self) -> ReduceSolveResult {
+        let mi = self.get_match_index_of_top_stack_item();
+        let follow_set = self.tables().follow_set_for_match(mi);
+        if self.solve_follow_sets(follow_set) {
+            return ReduceSolveResult::Success;
+        }
+
+        self.solve_reduce_sets()
+    }
+
+    /// Push a stack item. The node for it must already be started in the sink.
+    fn push_stack_item(&mut self, linked_to_above: bool, match_id: MatchId, start: usize) {
+        self.stack.push(StackItem {
+            linked_to_above,
+            match_id,
+            len: 0,
+            first_is_rule: false,
+            report_at: self.token_reader.position,
+            start,
+            end: start,
+            pinned: false,
+        });
+        self.pin_top_stack_item();
+    }
+
     fn solve_reduce_sets(&mut self) -> ReduceSolveResult {
         let mut reduce_stack = Vec::new();
 
@@ -223,20 +824,14 @@ impl<'a> Interpreter<'a> {
                         self.wrap_top_stack_item_into_empty(wrap);
                     }
 
-                    let value = self.seal_top_stack_item();
+                    let sealed = self.seal_top_stack_item();
 
-                    let match_value = MatchValue {
-                        match_id,
-                        values: vec![],
-                    };
-                    let stack_value = StackItem {
-                        linked_to_above: false,
-                        match_value,
-                    };
-                    self.stack.push(stack_value);
+                    self.sink.wrap_last_node();
+                    self.push_stack_item(false, match_id, sealed.start);
 
                     self.append_emptys(append_before);
-                    self.append_value(value);
+                    self.sink.insert_wrapped_node();
+                    self.push_value(ValueKind::Rule, sealed.end);
                 }
                 WrapStatusAction::InsertIntoAbove {
                     wrap_above,
@@ -250,16 +845,13 @@ impl<'a> Interpreter<'a> {
                         self.wrap_top_stack_item_into_empty(wrap);
                     }
 
-                    let value = self.seal_top_stack_item();
+                    let sealed = self.seal_top_stack_item();
 
-                    if self.stack.len() == 0 {
-                        return match value {
-                            Value::Rule(rule) => ReduceSolveResult::Finished(rule),
-                            _ => panic!("Expected rule value"),
-                        };
+                    if self.stack.is_empty() {
+                        return ReduceSolveResult::Finished(sealed);
                     }
 
-                    self.append_value(value);
+                    self.push_value(ValueKind::Rule, sealed.end);
                 }
             }
         }
@@ -267,7 +859,9 @@ impl<'a> Interpreter<'a> {
         ReduceSolveResult::Success
     }
 
-    fn solve_error(&mut self) -> bool {
+    /// Recover from an error by filling the remaining terms of stack items with errors,
+    /// until the next token fits. Stack items below `floor` are left untouched.
+    fn solve_error(&mut self, floor: usize) -> bool {
         let mut action_stack = Vec::new();
 
         let mut i = self.stack.len() - 1;
@@ -280,14 +874,14 @@ impl<'a> Interpreter<'a> {
             }
 
             index.index += 1;
-            // If the match isn't finished, continue
-            if index.index < max_index {
+            // If the match isn't finished, fill the skipped term with an error and continue
+            if index.index <= max_index {
                 action_stack.push(ErrorResolveAction::AppendError);
                 continue;
             }
 
             // If we reached the end of the last stack item, we failed
-            if i == 0 {
+            if i <= floor {
                 return false;
             }
 
@@ -299,7 +893,7 @@ impl<'a> Interpreter<'a> {
             if self.stack[i].linked_to_above {
                 action_stack.push(ErrorResolveAction::InsertIntoAbove { wrap_above: &[] });
             } else {
-                let wrap_actions = self.solver.get_wrap_data(parent_rule, child_rule);
+                let wrap_actions = self.tables().get_wrap_data(parent_rule, child_rule);
                 if let Some(wrap_actions) = wrap_actions {
                     if let Some(insert_action) = &wrap_actions.insert_action {
                         action_stack.push(ErrorResolveAction::InsertIntoAbove {
@@ -313,8 +907,9 @@ impl<'a> Interpreter<'a> {
                 }
             }
 
+            // Either way, the parent receives a value for the child's term
             i -= 1;
-            index = self.get_match_index_of_stack_item(i);
+            index = self.get_match_index_of_stack_item_if_child_inserted(i);
             max_index = self.solver.get_match(index.id).terms.len();
         }
 
@@ -326,12 +921,17 @@ impl<'a> Interpreter<'a> {
                         self.wrap_top_stack_item_into_empty(wrap);
                     }
 
-                    let value = self.seal_top_stack_item();
-                    self.append_value(value);
+                    let sealed = self.seal_top_stack_item();
+                    self.push_value(ValueKind::Rule, sealed.end);
                 }
                 ErrorResolveAction::DiscardChildAndInsertError => {
-                    self.stack.pop();
-                    self.append_error();
+                    let child = self.stack.pop().unwrap();
+                    self.sink.abandon_node();
+
+                    let position = self.token_reader.position;
+                    self.sink
+                        .error(self.token_reader.span(child.start..position));
+                    self.push_value(ValueKind::Error, position);
                 }
             }
         }
@@ -340,24 +940,45 @@ impl<'a> Interpreter<'a> {
     }
 
     fn get_matching_first_set<'b>(&self, first_sets: &'b [FirstSet]) -> Option<&'b FirstSet> {
+        if let Some(ITokenOrGroup::Token(Token::Hole)) = self.token_reader.peek() {
+            return self.get_hole_first_set(first_sets);
+        }
+
         first_sets
             .iter()
             .find(|first_set| self.matches_tokens(&first_set.tokens))
     }
 
-    fn insert_first_set_data(&mut self, set: &FirstSet) {
-        for action in &set.then {
-            let match_value = MatchValue {
-                match_id: action.id,
-                values: Self::process_empty_items(&action.append_empty_fields),
-            };
+    /// A hole can be any of the rules that the first sets lead to. The innermost rule that
+    /// the next token can follow is picked, so that the tokens after the hole can still wrap
+    /// it into the outer rules (e.g. `$x + $y`, where `$x` becomes the left side of the
+    /// addition).
+    fn get_hole_first_set<'b>(&self, first_sets: &'b [FirstSet]) -> Option<&'b FirstSet> {
+        let tables = self.tables();
+        let grammar = tables.grammar();
+        let hole_sets = tables.hole_sets()?;
+        let next = self.token_reader.peek_at(1);
 
-            let stack_value = StackItem {
-                linked_to_above: action.linked_to_above,
-                match_value,
-            };
+        first_sets
+            .iter()
+            .filter(|first_set| self.matches_tokens(&first_set.tokens))
+            .filter_map(|first_set| Some((first_set, first_set.then.last()?.id)))
+            .min_by_key(|(first_set, id)| {
+                let rule = grammar.get_rule_from_match(*id);
+                let follows = hole_sets.can_follow(rule, next);
+                let depth = hole_sets.left_corners(rule);
+                (!follows, depth, first_set.then.len(), *id)
+            })
+            .map(|(first_set, _)| first_set)
+    }
 
-            self.stack.push(stack_value);
+    fn insert_first_set_data(&mut self, set: &FirstSet) {
+        let position = self.token_reader.position;
+
+        for action in &set.then {
+            self.sink.start_node();
+            self.push_stack_item(action.linked_to_above, action.id, position);
+            self.append_emptys(&action.append_empty_fields);
         }
     }
 
@@ -367,7 +988,7 @@ impl<'a> Interpreter<'a> {
         first_sets: &[FirstSet],
     ) -> bool {
         if let Some(set) = self.get_matching_first_set(first_sets) {
-            if append_emptys.len() > 0 {
+            if !append_emptys.is_empty() {
                 self.append_emptys(append_emptys);
             }
 
@@ -388,7 +1009,7 @@ impl<'a> Interpreter<'a> {
                     }
                 }
                 FollowSet::Enter(enter) => {
-                    let first_sets = self.solver.first_set_for_rule(enter.rule);
+                    let first_sets = self.tables().first_set_for_rule(enter.rule);
                     if self.get_matching_first_set(first_sets).is_some() {
                         return true;
                     }
@@ -400,7 +1021,7 @@ impl<'a> Interpreter<'a> {
     }
 
     fn does_follow_set_match_for(&self, ind: MatchIndex) -> bool {
-        let follow_sets = self.solver.follow_set_for_match(ind);
+        let follow_sets = self.tables().follow_set_for_match(ind);
         self.does_follow_set_match(follow_sets)
     }
 
@@ -415,7 +1036,7 @@ impl<'a> Interpreter<'a> {
                     }
                 }
                 FollowSet::Enter(enter) => {
-                    let first_sets = self.solver.first_set_for_rule(enter.rule);
+                    let first_sets = self.tables().first_set_for_rule(enter.rule);
                     if self.solve_first_set(&enter.append_extra, first_sets) {
                         return true;
                     }
@@ -427,76 +1048,229 @@ impl<'a> Interpreter<'a> {
     }
 
     fn parse_tokens(&mut self, tokens: &[TokenOrGroup]) {
-        let top_value = self.stack.last_mut().unwrap();
-
         for token in tokens {
             match token {
                 TokenOrGroup::Token(_) => {
+                    let position = self.token_reader.position;
                     let next_item = self.token_reader.next().unwrap();
                     let next_token = match next_item {
                         ITokenOrGroup::Token(token) => token,
                         ITokenOrGroup::Group(_) => panic!("Expected token, got group"),
                     };
 
-                    let value = Value::Token(next_token.clone());
-                    top_value.match_value.values.push(value);
+                    let span = self.token_reader.span(position..position + 1);
+                    self.sink.token(next_token, span);
+                    self.push_value(ValueKind::Token, position + 1);
                 }
                 TokenOrGroup::Group(_, rule) => {
+                    let position = self.token_reader.position;
                     let next_item = self.token_reader.next().unwrap();
-                    let next_token_reader = match next_item {
+                    let tokens = match next_item {
                         ITokenOrGroup::Token(_) => panic!("Expected group, got token"),
-                        ITokenOrGroup::Group(tokens) => TokenReader::new(tokens.as_slice()),
+                        ITokenOrGroup::Group(tokens) => tokens,
                     };
 
-                    let interpreter = Interpreter {
-                        stack: Vec::new(),
-                        token_reader: next_token_reader,
-                        solver: self.solver,
+                    let previous = self.reuse.and_then(|r| r.find(position, *rule));
+                    let end = match previous {
+                        Some(previous) if previous.has_tokens(&tokens) => {
+                            self.reuse_group(previous, position)
+                        }
+                        _ => self.parse_group(tokens, position, *rule, previous),
                     };
 
-                    let rule_value = interpreter.solve(*rule);
-
-                    let value = Value::Rule(rule_value);
-                    top_value.match_value.values.push(value);
+                    self.push_value(ValueKind::Rule, end);
                 }
             }
         }
     }
 
+    /// Parse the tokens of a group with a nested interpreter, returning where its value
+    /// ends. If a previous parse had a group in the same place, then the groups inside of it
+    /// can be reused.
+    fn parse_group(
+        &mut self,
+        tokens: Vec<ITokenOrGroup>,
+        position: usize,
+        rule: Rule,
+        previous: Option<&'a CachedGroup>,
+    ) -> usize {
+        let cached_tokens = self.groups.is_some().then(|| tokens.clone());
+
+        let byte_ranges = self.token_reader.byte_ranges;
+        let reader = TokenReader::new_at(BufferedSource::new(tokens), position, byte_ranges);
+
+        // The reporter is shared with the nested interpreter, then taken back
+        let mut reporter = std::mem::take(&mut self.reporter);
+        let outer_errored = reporter.enter_group();
+        let history_start = reporter.history().len();
+
+        // When groups are kept for reuse, the events of the group are recorded on the way
+        let mut recording = RecordingSink {
+            inner: &mut *self.sink,
+            events: Vec::new(),
+        };
+        let sink: &mut dyn TreeSink = if cached_tokens.is_some() {
+            &mut recording
+        } else {
+            &mut *recording.inner
+        };
+
+        let (end, mut reporter, children) = {
+            let mut interpreter = Interpreter::new(self.solver, reader, reporter, sink);
+            interpreter.reuse = self.reuse.zip(previous).map(|(r, p)| r.enter(p));
+            interpreter.groups = cached_tokens.as_ref().map(|_| Vec::new());
+
+            let end = interpreter.solve(rule).end;
+            (end, interpreter.reporter, interpreter.groups)
+        };
+
+        if let (Some(groups), Some(tokens)) = (&mut self.groups, cached_tokens) {
+            groups.push(CachedGroup::new(
+                rule,
+                position,
+                tokens,
+                end,
+                recording.events,
+                reporter.history()[history_start..].to_vec(),
+                children.unwrap_or_default(),
+            ));
+        }
+
+        reporter.exit_group(outer_errored);
+        self.reporter = reporter;
+
+        end
+    }
+
+    /// Reuse a group from a previous parse that had the same tokens, moving it to its new
+    /// position and returning where its value ends. Its events and diagnostics are sent
+    /// again, as if it was parsed.
+    fn reuse_group(&mut self, previous: &CachedGroup, position: usize) -> usize {
+        let group = previous.moved_to(position, self.token_reader.byte_ranges);
+
+        let outer_errored = self.reporter.enter_group();
+        for diagnostic in group.diagnostics() {
+            self.reporter.report(diagnostic.clone());
+        }
+        self.reporter.exit_group(outer_errored);
+
+        for event in group.events() {
+            event.clone().send_to(self.sink);
+        }
+
+        let end = group.end();
+        if let Some(groups) = &mut self.groups {
+            groups.push(group);
+        }
+
+        end
+    }
+
     fn append_emptys(&mut self, tokens: &[EmptySolverRuleValue]) {
-        let top_value = self.stack.last_mut().unwrap();
+        let position = self.stack.last().unwrap().end;
 
         for token in tokens {
-            let value = Self::process_empty_item(token);
-            top_value.match_value.values.push(value);
+            self.emit_empty_item(token, position);
+            self.push_value(ValueKind::Rule, position);
         }
     }
 
-    fn append_value(&mut self, value: Value) {
-        let top_value = self.stack.last_mut().unwrap();
-        top_value.match_value.values.push(value);
+    /// Account for a value that was sent to the sink inside of the top stack item.
+    fn push_value(&mut self, kind: ValueKind, end: usize) {
+        let top = self.stack.last_mut().unwrap();
+        if top.len == 0 {
+            top.first_is_rule = kind == ValueKind::Rule;
+        }
+
+        top.len += 1;
+        top.end = end;
+        self.pin_top_stack_item();
     }
 
-    fn append_error(&mut self) {
-        let top_value = self.stack.last_mut().unwrap();
-        top_value.match_value.values.push(Value::Error);
+    /// Tell the sink once the node of the top stack item is sure to stay in the tree, along
+    /// with any node that it gets wrapped into.
+    fn pin_top_stack_item(&mut self) {
+        let index = self.stack.len() - 1;
+        let item = &self.stack[index];
+        if item.pinned || self.can_collapse(item) || !self.is_kept_by_parent(index) {
+            return;
+        }
+
+        self.stack[index].pinned = true;
+        self.sink.pin_node();
     }
 
-    fn process_empty_item(item: &EmptySolverRuleValue) -> Value {
-        let rule = RuleValue {
-            rule: item.rule,
-            match_id: item.match_value.id,
-            values: Self::process_empty_items(&item.match_value.fields),
-        };
+    /// Whether sealing the item could collapse it. Every term has a value once a match is
+    /// sealed, so only matches with a single term collapse.
+    fn can_collapse(&self, item: &StackItem) -> bool {
+        self.solver.get_match(item.match_id).terms.len() == 1
+            && !self.solver.grammar().is_recovery_match(item.match_id)
+            && (item.len == 0 || item.first_is_rule)
+    }
+
+    /// Whether the item at `index` is sure to be inserted into the item below it, instead of
+    /// being discarded by error recovery, and so is every node that it can be wrapped into
+    /// on the way. The root is always kept.
+    fn is_kept_by_parent(&self, index: usize) -> bool {
+        let item = &self.stack[index];
+        if index == 0 || item.linked_to_above {
+            return true;
+        }
+
+        let parent_rule = self.get_expecting_rule_for_stack_item(index - 1);
+        // Either set of tables may be in use by the time the item is sealed
+        let solvers: Vec<_> = std::iter::once(self.solver)
+            .chain(self.solver.recovery_solver())
+            .collect();
+
+        let mut seen = HashSet::new();
+        let mut queue = vec![self.solver.get_match(item.match_id).rule];
+        while let Some(rule) = queue.pop() {
+            if !seen.insert(rule) {
+                continue;
+            }
 
-        Value::Rule(rule)
+            for solver in &solvers {
+                let Some(data) = solver.get_wrap_data(parent_rule, rule) else {
+                    return false;
+                };
+                if data.insert_action.is_none() {
+                    return false;
+                }
+
+                for action in &data.wrap_actions {
+                    queue.push(solver.get_match(action.if_matches.id).rule);
+                }
+            }
+        }
+
+        true
     }
 
-    fn process_empty_items(items: &[EmptySolverRuleValue]) -> Vec<Value> {
-        items
-            .iter()
-            .map(|item| Self::process_empty_item(item))
-            .collect()
+    /// Append an error covering the tokens skipped since the end of the top stack item.
+    fn append_error(&mut self) {
+        let start = self.stack.last().unwrap().end;
+        let position = self.token_reader.position;
+
+        self.sink.error(self.token_reader.span(start..position));
+        self.push_value(ValueKind::Error, position);
+    }
+
+    /// Send the value of an empty rule to the sink, with a zero width span at the given
+    /// position.
+    fn emit_empty_item(&mut self, item: &EmptySolverRuleValue, position: usize) {
+        self.sink.start_node();
+
+        for field in &item.match_value.fields {
+            self.emit_empty_item(field, position);
+        }
+
+        self.sink.finish_node(NodeInfo {
+            rule: item.rule,
+            match_id: item.match_value.id,
+            is_error: false,
+            span: self.token_reader.span(position..position),
+        });
     }
 
     fn matches_tokens(&self, tokens: &[TokenOrGroup]) -> bool {
@@ -509,99 +1283,92 @@ impl<'a> Interpreter<'a> {
         true
     }
 
-    fn seal_top_stack_item(&mut self) -> Value {
+    /// Seal the top stack item and finish its node, returning the token positions it covers.
+    fn seal_top_stack_item(&mut self) -> Range<usize> {
         let mi = self.get_match_index_of_top_stack_item();
 
         let action = self
-            .solver
+            .tables()
             .get_seal_action_for_match(mi)
             .expect("No seal action found");
 
         self.append_emptys(&action.append_extra);
 
         let stack_item = self.stack.pop().unwrap();
+        let span = self.token_reader.span(stack_item.start..stack_item.end);
+
+        let match_id = stack_item.match_id;
+        let recovery_message = self.solver.grammar().get_recovery_message(match_id);
+        if let Some(message) = recovery_message {
+            let kind = DiagnosticKind::RecoveryMatch {
+                match_id,
+                message: message.to_string(),
+            };
+            self.reporter
+                .report(Diagnostic::new(kind, stack_item.report_at));
+        }
+        let is_error = recovery_message.is_some();
 
-        let should_propagate_inner_rule = if stack_item.match_value.values.len() == 1 {
-            match stack_item.match_value.values.first().unwrap() {
-                Value::Token(_) => false,
-                Value::Rule(_) => true,
-                Value::Error => false,
-            }
-        } else {
-            false
-        };
+        // Recovery matches are never propagated, so that they remain visible in the tree.
+        let should_propagate_inner_rule =
+            stack_item.len == 1 && stack_item.first_is_rule && !is_error;
 
-        let rule = if should_propagate_inner_rule {
+        if should_propagate_inner_rule {
             // This condition helps make the tree of rules look cleaner when printed
-            let mut stack_item = stack_item;
-            let inner_rule = match stack_item.match_value.values.pop().unwrap() {
-                Value::Rule(rule) => rule,
-                _ => unreachable!(),
-            };
-
-            RuleValue {
-                rule: action.into_rule,
-                match_id: inner_rule.match_id,
-                values: inner_rule.values,
-            }
+            self.sink.collapse_node(action.into_rule, span);
         } else {
-            RuleValue {
+            self.sink.finish_node(NodeInfo {
                 rule: action.into_rule,
-                match_id: stack_item.match_value.match_id,
-                values: stack_item.match_value.values,
-            }
-        };
+                match_id,
+                is_error,
+                span,
+            });
+        }
 
-        Value::Rule(rule)
+        stack_item.start..stack_item.end
     }
 
     fn wrap_top_stack_item_into_empty(&mut self, empty: &EmptyWrapAction) {
         let sealed = self.seal_top_stack_item();
 
-        let mut new_match = MatchValue {
-            match_id: empty.match_id,
-            values: vec![],
-        };
+        self.sink.wrap_last_node();
+        self.push_stack_item(false, empty.match_id, sealed.start);
 
         for left in &empty.left_empty {
-            let value = Self::process_empty_item(left);
-            new_match.values.push(value);
+            self.emit_empty_item(left, sealed.start);
+            self.push_value(ValueKind::Rule, sealed.start);
         }
 
-        new_match.values.push(sealed);
+        self.sink.insert_wrapped_node();
+        self.push_value(ValueKind::Rule, sealed.end);
 
         for right in &empty.right_empty {
-            let value = Self::process_empty_item(right);
-            new_match.values.push(value);
+            self.emit_empty_item(right, sealed.end);
+            self.push_value(ValueKind::Rule, sealed.end);
         }
-
-        self.stack.push(StackItem {
-            linked_to_above: false,
-            match_value: new_match,
-        });
     }
 
     fn get_match_index_of_stack_item(&self, index: usize) -> MatchIndex {
         let stack_item = &self.stack[index];
         MatchIndex {
-            id: stack_item.match_value.match_id,
-            index: stack_item.match_value.values.len(),
+            id: stack_item.match_id,
+            index: stack_item.len,
         }
     }
 
     fn get_match_index_of_top_stack_item(&self) -> MatchIndex {
         let stack_item = self.stack.last().unwrap();
         MatchIndex {
-            id: stack_item.match_value.match_id,
-            index: stack_item.match_value.values.len(),
+            id: stack_item.match_id,
+            index: stack_item.len,
         }
     }
 
     fn get_match_index_of_stack_item_if_child_inserted(&self, index: usize) -> MatchIndex {
         let stack_item = &self.stack[index];
         MatchIndex {
-            id: stack_item.match_value.match_id,
-            index: stack_item.match_value.values.len() + 1,
+            id: stack_item.match_id,
+            index: stack_item.len + 1,
         }
     }
 
@@ -620,12 +1387,12 @@ impl<'a> Interpreter<'a> {
             HasChild::No => self.get_match_index_of_stack_item(index),
         };
 
-        let follow_set = self.solver.follow_set_for_match(mi);
+        let follow_set = self.tables().follow_set_for_match(mi);
         if self.does_follow_set_match(follow_set) {
             return WrapStatus::Matches;
         }
 
-        let seal_action = self.solver.get_seal_action_for_match(mi);
+        let seal_action = self.tables().get_seal_action_for_match(mi);
         let Some(seal_action) = seal_action else {
             return WrapStatus::Error;
         };
@@ -647,10 +1414,11 @@ impl<'a> Interpreter<'a> {
 
         let parent_rule = self.get_expecting_rule_for_stack_item(index - 1);
         let child_rule = self.solver.get_match_rule(mi.id);
-        let wrap_data = self
-            .solver
-            .get_wrap_data(parent_rule, child_rule)
-            .expect("No wrap data found");
+        // Contexts that the tables never saw (e.g. ones created by a recovery match) can't
+        // be wrapped, so the caller falls back to its error handling
+        let Some(wrap_data) = self.tables().get_wrap_data(parent_rule, child_rule) else {
+            return WrapStatus::Error;
+        };
 
         for action in &wrap_data.wrap_actions {
             if self.does_follow_set_match_for(action.if_matches) {
@@ -665,8 +1433,8 @@ impl<'a> Interpreter<'a> {
 
         if let Some(insert) = &wrap_data.insert_action {
             let parent_mi = self.get_match_index_of_stack_item_if_child_inserted(index - 1);
-            let parent_follow_set = self.solver.follow_set_for_match(parent_mi);
-            if self.does_follow_set_match(parent_follow_set) || parent_follow_set.len() == 0 {
+            let parent_follow_set = self.tables().follow_set_for_match(parent_mi);
+            if self.does_follow_set_match(parent_follow_set) || parent_follow_set.is_empty() {
                 return WrapStatus::Action(WrapStatusAction::InsertIntoAbove {
                     wrap_above: &insert.wrap_actions,
                     seal_append: &seal_action.append_extra,
@@ -724,10 +1492,6 @@ struct StackItemDisplay<'a> {
 impl std::fmt::Display for StackItemDisplay<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let new_spacing = Spacing(self.spacing.0 + 1);
-        let values_list = ValuesListDisplay {
-            values: &self.item.match_value.values,
-            spacing: new_spacing,
-        };
 
         writeln!(f, "{}StackItem {{", self.spacing)?;
 
@@ -736,12 +1500,8 @@ impl std::fmt::Display for StackItemDisplay<'_> {
             "{}linked_to_above: {},",
             new_spacing, self.item.linked_to_above
         )?;
-        writeln!(
-            f,
-            "{}match: {:?},",
-            new_spacing, self.item.match_value.match_id
-        )?;
-        writeln!(f, "{}values: {},", new_spacing, values_list)?;
+        writeln!(f, "{}match: {:?},", new_spacing, self.item.match_id)?;
+        writeln!(f, "{}values: {},", new_spacing, self.item.len)?;
         write!(f, "{}}}", self.spacing)?;
 
         Ok(())
@@ -754,7 +1514,7 @@ struct StackDisplay<'a> {
 
 impl<'a> StackDisplay<'a> {
     fn new(stack: &'a [StackItem]) -> Self {
-        Self { item: &stack }
+        Self { item: stack }
     }
 }
 
@@ -818,30 +1578,12 @@ impl std::fmt::Display for RuleDisplay<'_> {
             spacing: self.spacing,
         };
 
-        write!(
-            f,
-            "{}{:?}({:?}) {}",
-            self.spacing, self.rule.rule, self.rule.match_id, values_list
-        )
-    }
-}
-
-struct MatchDisplay<'a> {
-    match_: &'a MatchValue,
-    spacing: Spacing,
-}
-
-impl std::fmt::Display for MatchDisplay<'_> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let values_list = ValuesListDisplay {
-            values: &self.match_.values,
-            spacing: self.spacing,
-        };
+        let error = if self.rule.is_error { " Error" } else { "" };
 
         write!(
             f,
-            "{}{:?} {}",
-            self.spacing, self.match_.match_id, values_list
+            "{}{:?}({:?}){} {}",
+            self.spacing, self.rule.rule, self.rule.match_id, error, values_list
         )
     }
 }
@@ -854,7 +1596,7 @@ struct ValueDisplay<'a> {
 impl std::fmt::Display for ValueDisplay<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.value {
-            Value::Token(token) => write!(f, "{}{:?}", self.spacing, token),
+            Value::Token(token, _) => write!(f, "{}{:?}", self.spacing, token),
             Value::Rule(rule) => write!(
                 f,
                 "{}",
@@ -863,7 +1605,7 @@ impl std::fmt::Display for ValueDisplay<'_> {
                     spacing: self.spacing
                 }
             ),
-            Value::Error => write!(f, "{}Error", self.spacing),
+            Value::Error(_) => write!(f, "{}Error", self.spacing),
         }
     }
 }
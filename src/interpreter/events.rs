@@ -0,0 +1,496 @@
+use std::{collections::VecDeque, ops::Range};
+
+use crate::{
+    diagnostics::{Diagnostic, ErrorCounts, ErrorOptions},
+    matches::{Rule, Token},
+    solver::GrammarSolver,
+    span::Span,
+};
+
+use super::{
+    solve_into,
+    tree_sink::{NodeInfo, TreeSink},
+    TokenSource,
+};
+
+/// A flat parse event. Nodes are written as a start event, the events of their children,
+/// and a finish event with the finished node.
+///
+/// When the parser finds out that a finished node belongs inside of a new node (e.g. `1`
+/// followed by `+` becomes the left side of an addition), the new node's start event is
+/// written later in the list, and points back to the start event of the node it wraps.
+/// The new node comes right before the wrapped node, and the events between the new node's
+/// start and its `Wrapped` marker are the children that come before the wrapped node. This
+/// way, earlier events never have to change, so they can be passed on while the parse is
+/// still going: see `stream_events`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Start {
+        /// The index of the start event of a finished node that becomes the first child of
+        /// this node.
+        wraps: Option<usize>,
+    },
+    Finish(NodeInfo),
+    Token(Token, Span),
+    Error(Span),
+    /// The place of the wrapped node within the node that wraps it.
+    Wrapped,
+    /// An event that was replaced by another, and should be skipped.
+    Tombstone,
+}
+
+/// An event of the tree in plain preorder, with wrapped nodes moved into place.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PreorderEvent {
+    Start,
+    Finish(NodeInfo),
+    Token(Token, Span),
+    Error(Span),
+}
+
+/// The output of a parse in event mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventParse {
+    pub events: Vec<Event>,
+    pub diagnostics: Vec<Diagnostic>,
+    pub error_counts: ErrorCounts,
+}
+
+/// Parse tokens into a flat list of events instead of a tree. This saves building nested
+/// values, but the list is only returned once the parse has finished. Use `stream_events`
+/// to handle the events as they're parsed.
+pub fn solve_events(
+    solver: &GrammarSolver,
+    source: impl TokenSource,
+    byte_ranges: Option<&[Range<usize>]>,
+    options: ErrorOptions,
+) -> EventParse {
+    let mut events = Vec::new();
+    let (diagnostics, error_counts) =
+        stream_events(solver, source, byte_ranges, options, |event| {
+            events.push(event)
+        });
+
+    EventParse {
+        events,
+        diagnostics,
+        error_counts,
+    }
+}
+
+/// Parse tokens into events, passing each event on as soon as it can no longer change. The
+/// events come in the same order as in the list from `solve_events`, so an event's index is
+/// the number of events passed on before it.
+///
+/// The events of a node are held back for as long as error recovery could still discard
+/// the node or collapse it into its child, which the interpreter rules out for most nodes
+/// once it has parsed their first value or two.
+pub fn stream_events(
+    solver: &GrammarSolver,
+    source: impl TokenSource,
+    byte_ranges: Option<&[Range<usize>]>,
+    options: ErrorOptions,
+    on_event: impl FnMut(Event),
+) -> (Vec<Diagnostic>, ErrorCounts) {
+    let mut sink = StreamingSink {
+        builder: EventBuilder::new(),
+        on_event,
+    };
+    let result = solve_into(solver, source, byte_ranges, &mut sink, options);
+
+    for event in sink.builder.finish() {
+        (sink.on_event)(event);
+    }
+    result
+}
+
+impl EventParse {
+    /// Iterate over the events in preorder, moving wrapped nodes into place on the way.
+    pub fn into_preorder(self) -> PreorderEvents {
+        PreorderEvents::new(self.events)
+    }
+}
+
+/// Send a finished event list to a tree sink, e.g. to build a tree of rule values.
+pub fn replay_events(events: Vec<Event>, sink: &mut dyn TreeSink) {
+    for event in PreorderEvents::new(events) {
+        match event {
+            PreorderEvent::Start => sink.start_node(),
+            PreorderEvent::Finish(node) => sink.finish_node(node),
+            PreorderEvent::Token(token, span) => sink.token(token, span),
+            PreorderEvent::Error(span) => sink.error(span),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct OpenNode {
+    /// The index of the node's start event.
+    start: usize,
+    /// The index of the first event that belongs to the node. This is before the start
+    /// event when the node wraps a node that was finished before it.
+    first: usize,
+    /// Whether the node is sure to stay in the tree, along with the nodes it's wrapped into.
+    pinned: bool,
+    /// Whether the node wraps a pinned node, so that it can't be abandoned, though it can
+    /// still collapse.
+    kept: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FinishedNode {
+    start: usize,
+    first: usize,
+    /// The index of the node's finish event, which changes if a node collapses into it.
+    finish: usize,
+    pinned: bool,
+}
+
+/// A tree sink that writes a flat list of events.
+///
+/// Events aren't final when they're written: a finish event changes when a node is
+/// collapsed into it, and the events of nodes that the interpreter abandons or collapses
+/// are removed again. Events before every node that can still change are settled, and can
+/// be taken out while the parse goes on.
+#[derive(Debug, Clone, Default)]
+pub struct EventBuilder {
+    /// The events that weren't taken yet.
+    events: Vec<Event>,
+    /// The number of events that were taken, which is the index of the first event left.
+    taken: usize,
+    open: Vec<OpenNode>,
+    /// Nodes that were taken out by a deferred wrap, and haven't been inserted yet.
+    wrapped: Vec<FinishedNode>,
+    last_finished: Option<FinishedNode>,
+    /// The number of events when `last_finished` was set. It can only be wrapped or
+    /// collapsed into while nothing else was written after it.
+    last_finished_at: usize,
+}
+
+impl EventBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The events that weren't taken yet, once the parse has finished.
+    pub fn finish(self) -> Vec<Event> {
+        assert!(self.open.is_empty(), "Not every node was finished");
+        self.events
+    }
+
+    /// Take the events that can no longer change, in order.
+    pub fn take_settled(&mut self) -> std::vec::Drain<'_, Event> {
+        let settled = self.settled();
+        let len = settled
+            .checked_sub(self.taken)
+            .expect("Events that were taken can still change");
+        self.taken = settled;
+        self.events.drain(..len)
+    }
+
+    /// The index of the first event that can still change.
+    fn settled(&self) -> usize {
+        let open = self.open.iter().filter_map(|n| match n {
+            OpenNode { pinned: true, .. } => None,
+            OpenNode { kept: true, .. } => Some(n.start),
+            _ => Some(n.first),
+        });
+        let finished = |n: &FinishedNode| if n.pinned { n.finish } else { n.first };
+        let wrapped = self.wrapped.iter().map(finished);
+        let last_finished = self
+            .last_finished
+            .filter(|_| self.last_finished_at == self.len())
+            .as_ref()
+            .map(finished);
+
+        open.chain(wrapped)
+            .chain(last_finished)
+            .min()
+            .unwrap_or(self.len())
+    }
+
+    fn len(&self) -> usize {
+        self.taken + self.events.len()
+    }
+
+    fn get_mut(&mut self, index: usize) -> &mut Event {
+        let index = index
+            .checked_sub(self.taken)
+            .expect("Changed an event that was already taken");
+        &mut self.events[index]
+    }
+
+    fn truncate(&mut self, len: usize) {
+        let len = len
+            .checked_sub(self.taken)
+            .expect("Removed an event that was already taken");
+        self.events.truncate(len);
+    }
+
+    fn set_last_finished(&mut self, node: Option<FinishedNode>) {
+        self.last_finished = node;
+        self.last_finished_at = self.len();
+    }
+}
+
+impl TreeSink for EventBuilder {
+    fn start_node(&mut self) {
+        let index = self.len();
+        self.open.push(OpenNode {
+            start: index,
+            first: index,
+            pinned: false,
+            kept: false,
+        });
+        self.events.push(Event::Start { wraps: None });
+    }
+
+    fn token(&mut self, token: Token, span: Span) {
+        self.events.push(Event::Token(token, span));
+    }
+
+    fn error(&mut self, span: Span) {
+        self.events.push(Event::Error(span));
+    }
+
+    fn finish_node(&mut self, info: NodeInfo) {
+        let open = self.open.pop().expect("No open node to finish");
+        let finish = self.len();
+        self.events.push(Event::Finish(info));
+
+        self.set_last_finished(Some(FinishedNode {
+            start: open.start,
+            first: open.first,
+            finish,
+            pinned: open.pinned,
+        }));
+    }
+
+    fn collapse_node(&mut self, rule: Rule, span: Span) {
+        let open = self.open.pop().expect("No open node to collapse");
+        assert!(!open.pinned, "Collapsed a pinned node");
+        let child = self.last_finished.expect("No child to collapse into");
+        // A node that wrapped the child stood in the same place as the child, so the child
+        // keeps its promise there
+        let pinned = open.kept && child.pinned;
+
+        match self.get_mut(child.finish) {
+            Event::Finish(node) => {
+                node.rule = rule;
+                node.span = span;
+            }
+            _ => panic!("Expected a finish event"),
+        }
+
+        if open.start > child.start {
+            // The node wrapped the child, so it's the last thing written
+            self.truncate(open.start);
+        } else {
+            *self.get_mut(open.start) = Event::Tombstone;
+        }
+
+        self.set_last_finished(Some(FinishedNode {
+            first: open.first,
+            pinned,
+            ..child
+        }));
+    }
+
+    fn abandon_node(&mut self) {
+        let open = self.open.pop().expect("No open node to abandon");
+        assert!(!open.pinned && !open.kept, "Abandoned a pinned node");
+        self.truncate(open.first);
+        self.set_last_finished(None);
+    }
+
+    fn wrap_last_node(&mut self) {
+        let child = self.last_finished.take().expect("No finished node to wrap");
+        let start = self.len();
+
+        self.wrapped.push(child);
+        self.open.push(OpenNode {
+            start,
+            first: child.first,
+            pinned: false,
+            kept: child.pinned,
+        });
+        self.events.push(Event::Start {
+            wraps: Some(child.start),
+        });
+    }
+
+    fn insert_wrapped_node(&mut self) {
+        let child = self.wrapped.pop().expect("No wrapped node to insert");
+        self.events.push(Event::Wrapped);
+        self.set_last_finished(Some(child));
+    }
+
+    fn pin_node(&mut self) {
+        self.open.last_mut().expect("No open node to pin").pinned = true;
+    }
+}
+
+/// Passes the settled events of a builder on after every change.
+struct StreamingSink<F: FnMut(Event)> {
+    builder: EventBuilder,
+    on_event: F,
+}
+
+impl<F: FnMut(Event)> StreamingSink<F> {
+    fn flush(&mut self) {
+        for event in self.builder.take_settled() {
+            (self.on_event)(event);
+        }
+    }
+}
+
+impl<F: FnMut(Event)> TreeSink for StreamingSink<F> {
+    fn start_node(&mut self) {
+        self.builder.start_node();
+        self.flush();
+    }
+
+    fn token(&mut self, token: Token, span: Span) {
+        self.builder.token(token, span);
+        self.flush();
+    }
+
+    fn error(&mut self, span: Span) {
+        self.builder.error(span);
+        self.flush();
+    }
+
+    fn finish_node(&mut self, node: NodeInfo) {
+        self.builder.finish_node(node);
+        self.flush();
+    }
+
+    fn collapse_node(&mut self, rule: Rule, span: Span) {
+        self.builder.collapse_node(rule, span);
+        self.flush();
+    }
+
+    fn abandon_node(&mut self) {
+        self.builder.abandon_node();
+        self.flush();
+    }
+
+    fn wrap_last_node(&mut self) {
+        self.builder.wrap_last_node();
+        self.flush();
+    }
+
+    fn insert_wrapped_node(&mut self) {
+        self.builder.insert_wrapped_node();
+        self.flush();
+    }
+
+    fn pin_node(&mut self) {
+        self.builder.pin_node();
+        self.flush();
+    }
+}
+
+/// Iterates over an event list in preorder. Events are taken out of the list as they
+/// are used, since a node that wraps another is visited before its position in the list.
+pub struct PreorderEvents {
+    events: Vec<Event>,
+    /// The index of the start event of the node that wraps each node, if any.
+    wrapped_by: Vec<Option<usize>>,
+    index: usize,
+    pending: VecDeque<PreorderEvent>,
+}
+
+impl PreorderEvents {
+    pub fn new(events: Vec<Event>) -> Self {
+        let mut wrapped_by = vec![None; events.len()];
+        for (index, event) in events.iter().enumerate() {
+            if let Event::Start { wraps: Some(child) } = event {
+                wrapped_by[*child] = Some(index);
+            }
+        }
+
+        Self {
+            events,
+            wrapped_by,
+            index: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn take(&mut self, index: usize) -> Event {
+        std::mem::replace(&mut self.events[index], Event::Tombstone)
+    }
+
+    /// Queue a node that starts at `index`, after all of the nodes that wrap it.
+    fn queue_start(&mut self, index: usize) {
+        let mut parents = Vec::new();
+        let mut next = self.wrapped_by[index];
+        while let Some(parent) = next {
+            match self.take(parent) {
+                Event::Start { .. } => {
+                    parents.push(parent);
+                    next = self.wrapped_by[parent];
+                }
+                _ => panic!("Wrapping node doesn't have a start event"),
+            }
+        }
+
+        for parent in parents.into_iter().rev() {
+            self.pending.push_back(PreorderEvent::Start);
+            self.queue_children_before_wrapped(parent);
+        }
+
+        self.pending.push_back(PreorderEvent::Start);
+    }
+
+    /// Queue the children of a wrapping node that come before its wrapped node.
+    fn queue_children_before_wrapped(&mut self, start: usize) {
+        let mut depth = 0;
+        for index in start + 1..self.events.len() {
+            let event = match self.take(index) {
+                Event::Wrapped if depth == 0 => return,
+                Event::Start { .. } => {
+                    depth += 1;
+                    PreorderEvent::Start
+                }
+                Event::Finish(node) => {
+                    depth -= 1;
+                    PreorderEvent::Finish(node)
+                }
+                Event::Token(token, span) => PreorderEvent::Token(token, span),
+                Event::Error(span) => PreorderEvent::Error(span),
+                Event::Wrapped | Event::Tombstone => continue,
+            };
+
+            self.pending.push_back(event);
+        }
+    }
+}
+
+impl Iterator for PreorderEvents {
+    type Item = PreorderEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() {
+            if self.index >= self.events.len() {
+                return None;
+            }
+
+            let index = self.index;
+            self.index += 1;
+
+            match self.take(index) {
+                Event::Start { .. } => self.queue_start(index),
+                Event::Finish(node) => self.pending.push_back(PreorderEvent::Finish(node)),
+                Event::Token(token, span) => {
+                    self.pending.push_back(PreorderEvent::Token(token, span))
+                }
+                Event::Error(span) => self.pending.push_back(PreorderEvent::Error(span)),
+                Event::Wrapped | Event::Tombstone => {}
+            }
+        }
+
+        self.pending.pop_front()
+    }
+}
@@ -0,0 +1,278 @@
+use std::ops::Range;
+
+use crate::{
+    diagnostics::{Diagnostic, ErrorOptions, ErrorReporter},
+    matches::Rule,
+    solver::GrammarSolver,
+    span::{check_byte_ranges, ByteRangesError, Span},
+};
+
+use super::{
+    tree_sink::{NodeInfo, TreeEvent},
+    BufferedSource, ITokenOrGroup, Interpreter, ParseResult, TokenReader, ValueBuilder,
+};
+
+/// An edit to the flattened token stream, where the tokens in `range` were replaced by
+/// `new_len` new tokens. Positions before the range are unchanged, and positions after it
+/// are shifted by the difference in length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenEdit {
+    pub range: Range<usize>,
+    pub new_len: usize,
+}
+
+impl TokenEdit {
+    /// Map a position in the edited token stream back to the same position before the edit.
+    /// Positions inside the edited range have no equivalent.
+    fn get_old_position(&self, position: usize) -> Option<usize> {
+        if position <= self.range.start {
+            Some(position)
+        } else if position >= self.range.start + self.new_len {
+            Some(position - self.new_len + self.range.len())
+        } else {
+            None
+        }
+    }
+}
+
+/// The result of parsing a group, kept so that it can be reused after an edit. The parse of
+/// a group only depends on its rule and its tokens, so a group with the same tokens can be
+/// moved to its new position instead of being parsed again. The result is kept as the
+/// events that were sent to the tree sink, so that it works with any sink.
+#[derive(Debug, Clone)]
+pub struct CachedGroup {
+    rule: Rule,
+    /// The position of the group's first token in the flattened token stream.
+    start: usize,
+    tokens: Vec<ITokenOrGroup>,
+    /// The token position where the value of the group ends.
+    end: usize,
+    events: Vec<TreeEvent>,
+    /// Every diagnostic reported inside the group, including suppressed ones, so that they
+    /// can be reported again in the same order.
+    diagnostics: Vec<Diagnostic>,
+    /// The groups nested directly inside this one, in order.
+    children: Vec<CachedGroup>,
+    /// Whether the group was moved from a previous parse instead of being parsed.
+    reused: bool,
+}
+
+impl CachedGroup {
+    pub fn new(
+        rule: Rule,
+        start: usize,
+        tokens: Vec<ITokenOrGroup>,
+        end: usize,
+        events: Vec<TreeEvent>,
+        diagnostics: Vec<Diagnostic>,
+        children: Vec<CachedGroup>,
+    ) -> Self {
+        Self {
+            rule,
+            start,
+            tokens,
+            end,
+            events,
+            diagnostics,
+            children,
+            reused: false,
+        }
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn events(&self) -> &[TreeEvent] {
+        &self.events
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn has_tokens(&self, tokens: &[ITokenOrGroup]) -> bool {
+        self.tokens == tokens
+    }
+
+    /// Copy the group to a new start position, updating the spans in its events and the
+    /// positions of its diagnostics.
+    pub fn moved_to(&self, start: usize, byte_ranges: Option<&[Range<usize>]>) -> Self {
+        let mover = Mover {
+            from: self.start,
+            to: start,
+            byte_ranges,
+        };
+        mover.move_group(self)
+    }
+}
+
+struct Mover<'a> {
+    from: usize,
+    to: usize,
+    byte_ranges: Option<&'a [Range<usize>]>,
+}
+
+impl Mover<'_> {
+    fn move_position(&self, position: usize) -> usize {
+        position - self.from + self.to
+    }
+
+    fn move_span(&self, span: &Span) -> Span {
+        let tokens = self.move_position(span.tokens.start)..self.move_position(span.tokens.end);
+        Span::new(tokens, self.byte_ranges)
+    }
+
+    fn move_group(&self, group: &CachedGroup) -> CachedGroup {
+        let diagnostics = group
+            .diagnostics
+            .iter()
+            .map(|d| Diagnostic::new(d.kind.clone(), self.move_position(d.token_index)))
+            .collect();
+
+        CachedGroup {
+            rule: group.rule,
+            start: self.move_position(group.start),
+            tokens: group.tokens.clone(),
+            end: self.move_position(group.end),
+            events: group.events.iter().map(|e| self.move_event(e)).collect(),
+            diagnostics,
+            children: group.children.iter().map(|c| self.move_group(c)).collect(),
+            reused: true,
+        }
+    }
+
+    fn move_event(&self, event: &TreeEvent) -> TreeEvent {
+        match event {
+            TreeEvent::Token(token, span) => TreeEvent::Token(*token, self.move_span(span)),
+            TreeEvent::Error(span) => TreeEvent::Error(self.move_span(span)),
+            TreeEvent::FinishNode(node) => TreeEvent::FinishNode(NodeInfo {
+                span: self.move_span(&node.span),
+                ..node.clone()
+            }),
+            TreeEvent::CollapseNode(rule, span) => {
+                TreeEvent::CollapseNode(*rule, self.move_span(span))
+            }
+            other => other.clone(),
+        }
+    }
+}
+
+/// The groups of a previous parse that can be reused at the current nesting level.
+#[derive(Debug, Clone, Copy)]
+pub struct Reuse<'a> {
+    edit: &'a TokenEdit,
+    groups: &'a [CachedGroup],
+}
+
+impl<'a> Reuse<'a> {
+    /// Find the previous group that was at the same position as a group in the edited input.
+    /// Its tokens still need to be compared before it's reused.
+    pub fn find(&self, position: usize, rule: Rule) -> Option<&'a CachedGroup> {
+        let old_position = self.edit.get_old_position(position)?;
+
+        let first = self.groups.partition_point(|g| g.start < old_position);
+        self.groups[first..]
+            .iter()
+            .take_while(|g| g.start == old_position)
+            .find(|g| g.rule == rule)
+    }
+
+    /// The groups to reuse inside of a previous group.
+    pub fn enter(&self, group: &'a CachedGroup) -> Reuse<'a> {
+        Reuse {
+            edit: self.edit,
+            groups: &group.children,
+        }
+    }
+}
+
+/// A parse that keeps the results of its groups, so that the input can be reparsed after
+/// an edit. Groups whose tokens didn't change are reused, so only the groups enclosing the
+/// edit are parsed again.
+#[derive(Debug, Clone)]
+pub struct IncrementalParse {
+    pub result: ParseResult,
+    groups: Vec<CachedGroup>,
+}
+
+impl IncrementalParse {
+    pub fn new(
+        solver: &GrammarSolver,
+        tokens: Vec<ITokenOrGroup>,
+        byte_ranges: Option<&[Range<usize>]>,
+        options: ErrorOptions,
+    ) -> Result<Self, ByteRangesError> {
+        solve_incremental(solver, tokens, byte_ranges, None, options)
+    }
+
+    /// Parse the edited tokens, reusing the groups from this parse. The tokens and byte
+    /// ranges are the full input after the edit. The result is the same as a full parse.
+    pub fn reparse(
+        &self,
+        solver: &GrammarSolver,
+        tokens: Vec<ITokenOrGroup>,
+        byte_ranges: Option<&[Range<usize>]>,
+        edit: &TokenEdit,
+        options: ErrorOptions,
+    ) -> Result<Self, ByteRangesError> {
+        let reuse = Reuse {
+            edit,
+            groups: &self.groups,
+        };
+        solve_incremental(solver, tokens, byte_ranges, Some(reuse), options)
+    }
+
+    /// How many groups were reused from the previous parse, including the groups nested in
+    /// them.
+    pub fn reused_groups(&self) -> usize {
+        count_groups(&self.groups, true)
+    }
+
+    /// How many groups were parsed, because they're new or enclose an edit.
+    pub fn parsed_groups(&self) -> usize {
+        count_groups(&self.groups, false)
+    }
+}
+
+fn count_groups(groups: &[CachedGroup], reused: bool) -> usize {
+    groups
+        .iter()
+        .map(|g| usize::from(g.reused == reused) + count_groups(&g.children, reused))
+        .sum()
+}
+
+fn solve_incremental(
+    solver: &GrammarSolver,
+    tokens: Vec<ITokenOrGroup>,
+    byte_ranges: Option<&[Range<usize>]>,
+    reuse: Option<Reuse>,
+    options: ErrorOptions,
+) -> Result<IncrementalParse, ByteRangesError> {
+    if let Some(byte_ranges) = byte_ranges {
+        check_byte_ranges(&tokens, byte_ranges)?;
+    }
+    let reader = TokenReader::new_at(BufferedSource::new(tokens), 0, byte_ranges);
+
+    let mut builder = ValueBuilder::new();
+
+    let (reporter, groups) = {
+        let reporter = ErrorReporter::new(options);
+        let mut interpreter = Interpreter::new(solver, reader, reporter, &mut builder);
+        interpreter.reuse = reuse;
+        interpreter.groups = Some(Vec::new());
+
+        interpreter.solve(solver.root_rule());
+        (interpreter.reporter, interpreter.groups.unwrap_or_default())
+    };
+    let (diagnostics, error_counts) = reporter.finish();
+
+    Ok(IncrementalParse {
+        result: ParseResult {
+            value: builder.finish(),
+            diagnostics,
+            error_counts,
+        },
+        groups,
+    })
+}
@@ -0,0 +1,80 @@
+use std::collections::VecDeque;
+
+use super::ITokenOrGroup;
+
+/// A source of input tokens that the interpreter pulls from as it parses, e.g. a lexer
+/// running in lockstep with the parser. Groups are produced whole, with their tokens inside.
+///
+/// The interpreter only ever looks a bounded number of items ahead, which it tells the
+/// source through `set_lookahead` before parsing starts. A source only needs to buffer
+/// that many items.
+pub trait TokenSource {
+    /// Set the number of upcoming items that must be available to `peek`.
+    fn set_lookahead(&mut self, lookahead: usize);
+
+    /// Get the upcoming item `k` places ahead without consuming it, where 0 is the next
+    /// item. `k` is always less than the lookahead.
+    fn peek(&self, k: usize) -> Option<&ITokenOrGroup>;
+
+    /// Consume the next item.
+    fn next(&mut self) -> Option<ITokenOrGroup>;
+}
+
+impl<S: TokenSource + ?Sized> TokenSource for &mut S {
+    fn set_lookahead(&mut self, lookahead: usize) {
+        (**self).set_lookahead(lookahead);
+    }
+
+    fn peek(&self, k: usize) -> Option<&ITokenOrGroup> {
+        (**self).peek(k)
+    }
+
+    fn next(&mut self) -> Option<ITokenOrGroup> {
+        (**self).next()
+    }
+}
+
+/// A token source that reads from an iterator, buffering only as many items as the
+/// lookahead requires.
+pub struct BufferedSource<I: Iterator<Item = ITokenOrGroup>> {
+    iter: I,
+    buffer: VecDeque<ITokenOrGroup>,
+    lookahead: usize,
+}
+
+impl<I: Iterator<Item = ITokenOrGroup>> BufferedSource<I> {
+    pub fn new(iter: impl IntoIterator<IntoIter = I>) -> Self {
+        Self {
+            iter: iter.into_iter(),
+            buffer: VecDeque::new(),
+            lookahead: 0,
+        }
+    }
+
+    fn fill(&mut self) {
+        while self.buffer.len() < self.lookahead {
+            match self.iter.next() {
+                Some(item) => self.buffer.push_back(item),
+                None => break,
+            }
+        }
+    }
+}
+
+impl<I: Iterator<Item = ITokenOrGroup>> TokenSource for BufferedSource<I> {
+    fn set_lookahead(&mut self, lookahead: usize) {
+        self.lookahead = lookahead;
+        self.fill();
+    }
+
+    fn peek(&self, k: usize) -> Option<&ITokenOrGroup> {
+        debug_assert!(k < self.lookahead, "Peeked past the lookahead");
+        self.buffer.get(k)
+    }
+
+    fn next(&mut self) -> Option<ITokenOrGroup> {
+        let item = self.buffer.pop_front().or_else(|| self.iter.next());
+        self.fill();
+        item
+    }
+}
@@ -0,0 +1,219 @@
+use crate::{
+    matches::{MatchId, Rule, Token},
+    span::Span,
+};
+
+use super::{RuleValue, Value};
+
+/// A finished node, as given to a tree sink.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeInfo {
+    pub rule: Rule,
+    pub match_id: MatchId,
+    /// Whether the node was parsed using a recovery match.
+    pub is_error: bool,
+    pub span: Span,
+}
+
+/// Receives the tree from the interpreter as it's being parsed, so that any kind of tree can
+/// be built from it.
+///
+/// Nodes are started before their contents and finished after them. A node's rule is only
+/// known once it's finished, since the interpreter may change its mind about the match
+/// while parsing it. Finished nodes go into the innermost open node, or become the root.
+///
+/// The interpreter sometimes only finds out that a node belongs inside of a new node after
+/// finishing it, e.g. when `1` is followed by `+` and becomes the left side of an addition.
+/// This is sent as a deferred wrap: `wrap_last_node` takes the last finished node out of
+/// its parent and starts a new node in its place, and `insert_wrapped_node` later puts it
+/// back inside of the new node. Empty nodes that come before it are sent in between.
+/// `wrap_last_node` and `collapse_node` only ever act on the node that was finished or
+/// inserted right before them.
+pub trait TreeSink {
+    fn start_node(&mut self);
+    fn token(&mut self, token: Token, span: Span);
+    /// Input that couldn't be parsed, which stands in for a missing value.
+    fn error(&mut self, span: Span);
+    fn finish_node(&mut self, node: NodeInfo);
+    /// Finish the open node by replacing it with its only child, which is a node. The child
+    /// takes the rule and span of the replaced node, but keeps its own match. This hides
+    /// chains of rules with a single child, such as `Expr -> Add -> Mul`.
+    fn collapse_node(&mut self, rule: Rule, span: Span);
+    /// Discard the open node along with everything in it, during error recovery.
+    fn abandon_node(&mut self);
+    fn wrap_last_node(&mut self);
+    fn insert_wrapped_node(&mut self);
+    /// A promise that the innermost open node won't be abandoned or collapsed, and that no
+    /// node it gets wrapped into will be abandoned, so that a sink can pass on what's inside
+    /// of it before the node is done.
+    fn pin_node(&mut self) {}
+}
+
+/// A call to a tree sink, so that parts of a tree can be recorded and sent again later.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeEvent {
+    StartNode,
+    Token(Token, Span),
+    Error(Span),
+    FinishNode(NodeInfo),
+    CollapseNode(Rule, Span),
+    AbandonNode,
+    WrapLastNode,
+    InsertWrappedNode,
+    PinNode,
+}
+
+impl TreeEvent {
+    pub fn send_to(self, sink: &mut dyn TreeSink) {
+        match self {
+            TreeEvent::StartNode => sink.start_node(),
+            TreeEvent::Token(token, span) => sink.token(token, span),
+            TreeEvent::Error(span) => sink.error(span),
+            TreeEvent::FinishNode(node) => sink.finish_node(node),
+            TreeEvent::CollapseNode(rule, span) => sink.collapse_node(rule, span),
+            TreeEvent::AbandonNode => sink.abandon_node(),
+            TreeEvent::WrapLastNode => sink.wrap_last_node(),
+            TreeEvent::InsertWrappedNode => sink.insert_wrapped_node(),
+            TreeEvent::PinNode => sink.pin_node(),
+        }
+    }
+}
+
+/// A sink that passes everything on to another sink, while keeping a copy of the events.
+pub struct RecordingSink<'a> {
+    pub inner: &'a mut dyn TreeSink,
+    pub events: Vec<TreeEvent>,
+}
+
+impl RecordingSink<'_> {
+    fn send(&mut self, event: TreeEvent) {
+        self.events.push(event.clone());
+        event.send_to(self.inner);
+    }
+}
+
+impl TreeSink for RecordingSink<'_> {
+    fn start_node(&mut self) {
+        self.send(TreeEvent::StartNode);
+    }
+
+    fn token(&mut self, token: Token, span: Span) {
+        self.send(TreeEvent::Token(token, span));
+    }
+
+    fn error(&mut self, span: Span) {
+        self.send(TreeEvent::Error(span));
+    }
+
+    fn finish_node(&mut self, node: NodeInfo) {
+        self.send(TreeEvent::FinishNode(node));
+    }
+
+    fn collapse_node(&mut self, rule: Rule, span: Span) {
+        self.send(TreeEvent::CollapseNode(rule, span));
+    }
+
+    fn abandon_node(&mut self) {
+        self.send(TreeEvent::AbandonNode);
+    }
+
+    fn wrap_last_node(&mut self) {
+        self.send(TreeEvent::WrapLastNode);
+    }
+
+    fn insert_wrapped_node(&mut self) {
+        self.send(TreeEvent::InsertWrappedNode);
+    }
+
+    fn pin_node(&mut self) {
+        self.send(TreeEvent::PinNode);
+    }
+}
+
+/// The default sink, which builds a tree of rule values.
+#[derive(Debug, Clone, Default)]
+pub struct ValueBuilder {
+    /// The values of each open node, from the outermost.
+    open: Vec<Vec<Value>>,
+    /// Finished nodes that were taken out of their parent by a deferred wrap.
+    wrapped: Vec<Value>,
+    root: Option<RuleValue>,
+}
+
+impl ValueBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn finish(self) -> RuleValue {
+        self.root.expect("The root node wasn't finished")
+    }
+
+    fn push_value(&mut self, value: Value) {
+        match self.open.last_mut() {
+            Some(values) => values.push(value),
+            None => match value {
+                Value::Rule(rule) => self.root = Some(rule),
+                _ => panic!("Expected the root to be a rule"),
+            },
+        }
+    }
+}
+
+impl TreeSink for ValueBuilder {
+    fn start_node(&mut self) {
+        self.open.push(Vec::new());
+    }
+
+    fn token(&mut self, token: Token, span: Span) {
+        self.push_value(Value::Token(token, span));
+    }
+
+    fn error(&mut self, span: Span) {
+        self.push_value(Value::Error(span));
+    }
+
+    fn finish_node(&mut self, node: NodeInfo) {
+        let values = self.open.pop().expect("No open node to finish");
+        self.push_value(Value::Rule(RuleValue {
+            rule: node.rule,
+            match_id: node.match_id,
+            values,
+            is_error: node.is_error,
+            span: node.span,
+        }));
+    }
+
+    fn collapse_node(&mut self, rule: Rule, span: Span) {
+        let mut values = self.open.pop().expect("No open node to collapse");
+        let inner = match values.pop() {
+            Some(Value::Rule(inner)) if values.is_empty() => inner,
+            _ => panic!("Expected a single rule to collapse into"),
+        };
+
+        self.push_value(Value::Rule(RuleValue {
+            rule,
+            span,
+            ..inner
+        }));
+    }
+
+    fn abandon_node(&mut self) {
+        self.open.pop().expect("No open node to abandon");
+    }
+
+    fn wrap_last_node(&mut self) {
+        let last = match self.open.last_mut() {
+            Some(values) => values.pop(),
+            None => self.root.take().map(Value::Rule),
+        };
+
+        self.wrapped.push(last.expect("No finished node to wrap"));
+        self.open.push(Vec::new());
+    }
+
+    fn insert_wrapped_node(&mut self) {
+        let value = self.wrapped.pop().expect("No wrapped node to insert");
+        self.push_value(value);
+    }
+}
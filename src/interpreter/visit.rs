@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+
+use crate::{
+    matches::{Rule, Token},
+    span::Span,
+};
+
+use super::{RuleValue, Value};
+
+/// Walks a tree of rule values. Each method's default walks into the children, so an
+/// override only has to handle the nodes it cares about, and calls `walk_rule` to keep
+/// going further down (or doesn't, to skip the node's children).
+pub trait Visitor {
+    fn visit_rule(&mut self, node: &RuleValue) {
+        walk_rule(self, node);
+    }
+
+    fn visit_token(&mut self, _token: Token, _span: &Span) {}
+
+    fn visit_error(&mut self, _span: &Span) {}
+}
+
+pub fn walk_rule<V: Visitor + ?Sized>(visitor: &mut V, node: &RuleValue) {
+    for value in &node.values {
+        walk_value(visitor, value);
+    }
+}
+
+pub fn walk_value<V: Visitor + ?Sized>(visitor: &mut V, value: &Value) {
+    match value {
+        Value::Token(token, span) => visitor.visit_token(*token, span),
+        Value::Rule(node) => visitor.visit_rule(node),
+        Value::Error(span) => visitor.visit_error(span),
+    }
+}
+
+/// Like `Visitor`, but able to change the tree in place. `visit_value_mut` sees every value
+/// before the more specific methods, so it can replace a value entirely.
+pub trait VisitorMut {
+    fn visit_value_mut(&mut self, value: &mut Value) {
+        walk_value_mut(self, value);
+    }
+
+    fn visit_rule_mut(&mut self, node: &mut RuleValue) {
+        walk_rule_mut(self, node);
+    }
+
+    fn visit_token_mut(&mut self, _token: &mut Token, _span: &mut Span) {}
+
+    fn visit_error_mut(&mut self, _span: &mut Span) {}
+}
+
+pub fn walk_rule_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut RuleValue) {
+    for value in &mut node.values {
+        visitor.visit_value_mut(value);
+    }
+}
+
+pub fn walk_value_mut<V: VisitorMut + ?Sized>(visitor: &mut V, value: &mut Value) {
+    match value {
+        Value::Token(token, span) => visitor.visit_token_mut(token, span),
+        Value::Rule(node) => visitor.visit_rule_mut(node),
+        Value::Error(span) => visitor.visit_error_mut(span),
+    }
+}
+
+/// Computes a result for a tree bottom up, e.g. to evaluate an expression. Each node gets
+/// the results of its children, in order.
+pub trait Fold {
+    type Output;
+
+    fn fold_rule(&mut self, node: &RuleValue, children: Vec<Self::Output>) -> Self::Output;
+    fn fold_token(&mut self, token: Token, span: &Span) -> Self::Output;
+    fn fold_error(&mut self, span: &Span) -> Self::Output;
+}
+
+pub fn fold<F: Fold + ?Sized>(folder: &mut F, node: &RuleValue) -> F::Output {
+    let children = node
+        .values
+        .iter()
+        .map(|value| fold_value(folder, value))
+        .collect();
+
+    folder.fold_rule(node, children)
+}
+
+pub fn fold_value<F: Fold + ?Sized>(folder: &mut F, value: &Value) -> F::Output {
+    match value {
+        Value::Token(token, span) => folder.fold_token(*token, span),
+        Value::Rule(node) => fold(folder, node),
+        Value::Error(span) => folder.fold_error(span),
+    }
+}
+
+type RuleCallback<'a> = Box<dyn FnMut(&RuleValue) + 'a>;
+type TokenCallback<'a> = Box<dyn FnMut(Token, &Span) + 'a>;
+type ErrorCallback<'a> = Box<dyn FnMut(&Span) + 'a>;
+
+/// A visitor made of a callback per rule, for when implementing `Visitor` is more than
+/// needed. The children of every node are walked after its callback.
+#[derive(Default)]
+pub struct RuleCallbacks<'a> {
+    rules: HashMap<Rule, RuleCallback<'a>>,
+    token: Option<TokenCallback<'a>>,
+    error: Option<ErrorCallback<'a>>,
+}
+
+impl<'a> RuleCallbacks<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `callback` for every node of `rule`. This replaces any earlier callback for it.
+    pub fn on(&mut self, rule: Rule, callback: impl FnMut(&RuleValue) + 'a) -> &mut Self {
+        self.rules.insert(rule, Box::new(callback));
+        self
+    }
+
+    pub fn on_token(&mut self, callback: impl FnMut(Token, &Span) + 'a) -> &mut Self {
+        self.token = Some(Box::new(callback));
+        self
+    }
+
+    pub fn on_error(&mut self, callback: impl FnMut(&Span) + 'a) -> &mut Self {
+        self.error = Some(Box::new(callback));
+        self
+    }
+
+    pub fn run(&mut self, node: &RuleValue) {
+        self.visit_rule(node);
+    }
+}
+
+impl Visitor for RuleCallbacks<'_> {
+    fn visit_rule(&mut self, node: &RuleValue) {
+        if let Some(callback) = self.rules.get_mut(&node.rule) {
+            callback(node);
+        }
+
+        walk_rule(self, node);
+    }
+
+    fn visit_token(&mut self, token: Token, span: &Span) {
+        if let Some(callback) = &mut self.token {
+            callback(token, span);
+        }
+    }
+
+    fn visit_error(&mut self, span: &Span) {
+        if let Some(callback) = &mut self.error {
+            callback(span);
+        }
+    }
+}
@@ -0,0 +1,22 @@
+pub mod analysis;
+pub mod counterexample;
+pub mod cst;
+pub mod demos;
+pub mod diagnostics;
+pub mod differential;
+pub mod earley;
+pub mod format;
+pub mod generate;
+pub mod glr;
+pub mod interpreter;
+pub mod lr;
+pub mod matches;
+pub mod peg;
+pub mod query;
+pub mod ref_list;
+pub mod rewrite;
+pub mod serialize;
+pub mod solver;
+pub mod span;
+pub mod structures;
+pub mod unparse;
@@ -0,0 +1,249 @@
+//! A deterministic LR(1) parser, as a second backend to compare against the interpreter.
+//!
+//! The parse table is either LALR(1) or canonical LR(1), and building it finds every
+//! shift/reduce and reduce/reduce conflict, which shows whether a grammar is in one of the
+//! deterministic LR classes when the solver behaves unexpectedly. The parser still runs on
+//! grammars with conflicts, resolving them like yacc by shifting over reducing and reducing
+//! the earlier match, but its trees are then only one of the possible parses.
+//!
+//! Groups are parsed separately, as the rule of the first group term that the state can take
+//! and that the contents of the group parse as. Error recovery isn't supported, and parsing
+//! stops at the first item that has no action.
+
+use std::{collections::HashMap, ops::Range};
+
+use crate::{
+    diagnostics::{Diagnostic, DiagnosticKind, ErrorCounts, Found},
+    glr::automaton::StateId,
+    interpreter::{ITokenOrGroup, ParseResult, RuleValue, Value},
+    matches::{Grammar, Rule, Symbol, Term},
+    span::{check_byte_ranges, ByteRangesError, Span},
+};
+
+use table::{Action, Lookahead, LrTable};
+
+pub mod table;
+
+/// A parser that follows an LR(1) table.
+pub struct LrParser {
+    grammar: Grammar,
+    table: LrTable,
+}
+
+impl LrParser {
+    /// A parser using the LALR(1) table of the grammar.
+    pub fn new(grammar: Grammar) -> Self {
+        let table = LrTable::lalr(&grammar);
+        Self { grammar, table }
+    }
+
+    /// A parser using a table that was built for the grammar, e.g. the canonical table.
+    pub fn with_table(grammar: Grammar, table: LrTable) -> Self {
+        Self { grammar, table }
+    }
+
+    pub fn grammar(&self) -> &Grammar {
+        &self.grammar
+    }
+
+    pub fn table(&self) -> &LrTable {
+        &self.table
+    }
+
+    pub fn parse(&self, tokens: Vec<ITokenOrGroup>) -> ParseResult {
+        self.parse_tokens(tokens, None)
+    }
+
+    /// Parse, with the byte range of each token in the flattened token stream, like
+    /// `solve_with_spans`.
+    pub fn parse_with_spans(
+        &self,
+        tokens: Vec<ITokenOrGroup>,
+        byte_ranges: &[Range<usize>],
+    ) -> Result<ParseResult, ByteRangesError> {
+        check_byte_ranges(&tokens, byte_ranges)?;
+        Ok(self.parse_tokens(tokens, Some(byte_ranges)))
+    }
+
+    fn parse_tokens(
+        &self,
+        tokens: Vec<ITokenOrGroup>,
+        byte_ranges: Option<&[Range<usize>]>,
+    ) -> ParseResult {
+        let rule = self.grammar.get(self.grammar.root_id()).rule;
+
+        let (value, diagnostics) = match self.parse_sequence(rule, &tokens, 0, byte_ranges) {
+            Ok(value) => (value, Vec::new()),
+            Err(diagnostic) => {
+                let len = tokens.iter().map(|item| item.flat_len()).sum();
+                let span = Span::new(0..len, byte_ranges);
+                (RuleValue::root_error(&self.grammar, span), vec![diagnostic])
+            }
+        };
+
+        ParseResult {
+            value,
+            error_counts: ErrorCounts {
+                reported: diagnostics.len(),
+                ..Default::default()
+            },
+            diagnostics,
+        }
+    }
+
+    /// Parse a sequence of tokens that starts at the position as the rule, which has to
+    /// match all of them.
+    fn parse_sequence(
+        &self,
+        rule: Rule,
+        tokens: &[ITokenOrGroup],
+        start: usize,
+        byte_ranges: Option<&[Range<usize>]>,
+    ) -> Result<RuleValue, Diagnostic> {
+        let start_state = self
+            .table
+            .start(rule)
+            .expect("Only the root rule and group rules are parsed");
+
+        // Each state on the stack, with the position where its symbol starts. The values of
+        // the symbols are kept separately, since the start state doesn't have one.
+        let mut stack: Vec<(StateId, usize)> = vec![(start_state, start)];
+        let mut values: Vec<Value> = Vec::new();
+        let mut position = start;
+        let mut index = 0;
+        // The groups parsed at the current item, by rule
+        let mut groups: HashMap<Rule, Result<RuleValue, Diagnostic>> = HashMap::new();
+
+        loop {
+            let (state, _) = *stack.last().unwrap();
+            let item = tokens.get(index);
+
+            let (lookahead, group) = match item {
+                None => (Lookahead::End, None),
+                Some(ITokenOrGroup::Token(token)) => (Lookahead::Term(Term::Token(*token)), None),
+                Some(ITokenOrGroup::Group(inner)) => {
+                    let (term, value) =
+                        self.group(state, inner, position, byte_ranges, &mut groups)?;
+                    (Lookahead::Term(term), Some(value))
+                }
+            };
+
+            match self.table.action(state, lookahead) {
+                Some(Action::Shift(target)) => {
+                    let end = position + item.map_or(0, |item| item.flat_len());
+                    let value = match (item, group) {
+                        (Some(ITokenOrGroup::Token(token)), _) => {
+                            Value::Token(*token, Span::new(position..end, byte_ranges))
+                        }
+                        (_, Some(value)) => Value::Rule(value),
+                        _ => unreachable!("Only tokens and groups are shifted"),
+                    };
+
+                    values.push(value);
+                    stack.push((target, position));
+                    position = end;
+                    index += 1;
+                    groups.clear();
+                }
+                Some(Action::Reduce(id)) => {
+                    let len = self.grammar.get(id).terms.len();
+                    let base = stack.len() - len;
+                    let from = if len == 0 { position } else { stack[base].1 };
+
+                    let children = values.split_off(values.len() - len);
+                    stack.truncate(base);
+
+                    let span = Span::new(from..position, byte_ranges);
+                    let value = RuleValue::from_match(&self.grammar, id, children, span);
+                    if stack.len() == 1 && lookahead == Lookahead::End && value.rule == rule {
+                        return Ok(value);
+                    }
+
+                    let (below, _) = *stack.last().unwrap();
+                    let target = self.table.state(below).gotos[&value.rule];
+                    values.push(Value::Rule(value));
+                    stack.push((target, from));
+                }
+                None => {
+                    let found = match item {
+                        Some(ITokenOrGroup::Token(token)) => Found::Token(*token),
+                        Some(ITokenOrGroup::Group(_)) => Found::Group,
+                        None => Found::End,
+                    };
+                    return Err(self.unexpected(state, found, position));
+                }
+            }
+        }
+    }
+
+    /// Find the group term that the state can take the group as, by parsing its contents as
+    /// the rule of each group term in order until one of them works.
+    fn group(
+        &self,
+        state: StateId,
+        inner: &[ITokenOrGroup],
+        position: usize,
+        byte_ranges: Option<&[Range<usize>]>,
+        groups: &mut HashMap<Rule, Result<RuleValue, Diagnostic>>,
+    ) -> Result<(Term, RuleValue), Diagnostic> {
+        let mut terms: Vec<Term> = self
+            .table
+            .state(state)
+            .actions
+            .keys()
+            .filter_map(|lookahead| match lookahead {
+                Lookahead::Term(term @ Term::Group(_, _)) => Some(*term),
+                _ => None,
+            })
+            .collect();
+        terms.sort();
+
+        let mut error = None;
+        for term in terms {
+            let Term::Group(_, rule) = term else {
+                continue;
+            };
+
+            let result = groups
+                .entry(rule)
+                .or_insert_with(|| self.parse_sequence(rule, inner, position, byte_ranges));
+
+            match result {
+                Ok(value) => return Ok((term, value.clone())),
+                Err(diagnostic) => {
+                    error.get_or_insert_with(|| diagnostic.clone());
+                }
+            }
+        }
+
+        // Errors inside of a group are more precise than the group not fitting
+        Err(error.unwrap_or_else(|| self.unexpected(state, Found::Group, position)))
+    }
+
+    /// A diagnostic for an item that the state has no action for, expecting the tokens and
+    /// groups that it does have actions for.
+    fn unexpected(&self, state: StateId, found: Found, position: usize) -> Diagnostic {
+        let mut lookaheads: Vec<_> = self.table.state(state).actions.keys().collect();
+        lookaheads.sort();
+
+        let mut expected = Vec::new();
+        for lookahead in lookaheads {
+            let symbol = match lookahead {
+                Lookahead::Term(Term::Token(token)) => Symbol::Token(*token),
+                Lookahead::Term(Term::Group(group, _)) => Symbol::Group(*group),
+                _ => continue,
+            };
+
+            if !expected.contains(&symbol) {
+                expected.push(symbol);
+            }
+        }
+
+        let kind = DiagnosticKind::Unexpected {
+            expected,
+            found,
+            context: None,
+        };
+        Diagnostic::new(kind, position)
+    }
+}
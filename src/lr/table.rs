@@ -0,0 +1,562 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::{
+    glr::automaton::{Automaton, Item, StateId},
+    matches::{Grammar, MatchId, Rule, Term},
+    solver::EmptyRuleSolver,
+};
+
+/// What a parser can see next: a token or a group, or the end of the sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Lookahead {
+    /// A token or group term. Rules are never lookaheads.
+    Term(Term),
+    End,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Shift(StateId),
+    Reduce(MatchId),
+}
+
+impl Action {
+    /// Shifts come before reductions, and earlier matches before later ones, which is the
+    /// order that conflicts are resolved in.
+    fn priority(self) -> (usize, usize) {
+        match self {
+            Action::Shift(state) => (0, state),
+            Action::Reduce(id) => (1, id.index()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableKind {
+    /// The states of the LR(0) automaton, with the lookaheads of states with the same items
+    /// merged.
+    Lalr1,
+    /// A state for every distinct set of items and lookaheads.
+    Canonical,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    ShiftReduce,
+    ReduceReduce,
+}
+
+/// A state and lookahead with more than one action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub state: StateId,
+    pub lookahead: Lookahead,
+    /// The actions, with the one that the parser takes first.
+    pub actions: Vec<Action>,
+}
+
+impl Conflict {
+    pub fn kind(&self) -> ConflictKind {
+        if self
+            .actions
+            .iter()
+            .any(|action| matches!(action, Action::Shift(_)))
+        {
+            ConflictKind::ShiftReduce
+        } else {
+            ConflictKind::ReduceReduce
+        }
+    }
+
+    /// Display the conflict with the matches from the grammar.
+    pub fn display<'a>(&'a self, grammar: &'a Grammar) -> ConflictDisplay<'a> {
+        ConflictDisplay {
+            conflict: self,
+            grammar,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TableState {
+    /// The items that the state was reached with, and the lookaheads of each.
+    pub kernel: Vec<(Item, Vec<Lookahead>)>,
+    /// The actions for each lookahead, sorted so that the first is the one the parser takes.
+    pub actions: HashMap<Lookahead, Vec<Action>>,
+    /// The state to go to after reducing to each rule.
+    pub gotos: HashMap<Rule, StateId>,
+}
+
+/// The LR(1) parse table of a grammar, with a start state for the root rule and for the rule
+/// of every group, like the LR(0) automaton. Recovery matches aren't included.
+#[derive(Debug, Clone)]
+pub struct LrTable {
+    kind: TableKind,
+    states: Vec<TableState>,
+    starts: HashMap<Rule, StateId>,
+    conflicts: Vec<Conflict>,
+}
+
+impl LrTable {
+    /// Build the LALR(1) table, from the LR(0) automaton. The lookaheads of each kernel item
+    /// are either generated inside of a state or propagated from the item it came from.
+    pub fn lalr(grammar: &Grammar) -> Self {
+        let automaton = Automaton::new(grammar);
+        let sets = LookaheadSets::new(grammar);
+
+        let mut lookaheads: Vec<BTreeMap<Item, BTreeSet<Lookahead>>> = automaton
+            .states()
+            .iter()
+            .map(|state| {
+                state
+                    .kernel
+                    .iter()
+                    .map(|&item| (item, BTreeSet::new()))
+                    .collect()
+            })
+            .collect();
+
+        let mut starts = HashMap::new();
+        for (id, state) in automaton.states().iter().enumerate() {
+            // Only start states have kernel items before their first term
+            if let Some(item) = state.kernel.first().filter(|item| item.dot == 0) {
+                starts.insert(grammar.get(item.match_id).rule, id);
+                for set in lookaheads[id].values_mut() {
+                    set.insert(Lookahead::End);
+                }
+            }
+        }
+
+        let mut links = Vec::new();
+        for (id, state) in automaton.states().iter().enumerate() {
+            for &from in &state.kernel {
+                for (item, lookahead) in sets.closure(&[(from, None)]) {
+                    let Some(term) = item.next_term(grammar) else {
+                        continue;
+                    };
+
+                    let target = state.gotos[&term];
+                    let moved = Item {
+                        match_id: item.match_id,
+                        dot: item.dot + 1,
+                    };
+                    match lookahead {
+                        Some(lookahead) => {
+                            lookaheads[target]
+                                .get_mut(&moved)
+                                .unwrap()
+                                .insert(lookahead);
+                        }
+                        None => links.push(((id, from), (target, moved))),
+                    }
+                }
+            }
+        }
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for &((state, from), (target, to)) in &links {
+                let set = lookaheads[state][&from].clone();
+                let target = lookaheads[target].get_mut(&to).unwrap();
+                let len = target.len();
+                target.extend(set);
+                changed |= target.len() != len;
+            }
+        }
+
+        let states = automaton
+            .states()
+            .iter()
+            .zip(lookaheads)
+            .map(|(state, kernel)| {
+                let kernel: Vec<_> = kernel
+                    .into_iter()
+                    .map(|(item, set)| (item, set.into_iter().collect()))
+                    .collect();
+                sets.table_state(kernel, &state.gotos)
+            })
+            .collect();
+
+        Self::new(TableKind::Lalr1, states, starts)
+    }
+
+    /// Build the canonical LR(1) table, which can have many more states than the LALR(1)
+    /// table, but doesn't have the reduce/reduce conflicts that merging states can cause.
+    pub fn canonical(grammar: &Grammar) -> Self {
+        let sets = LookaheadSets::new(grammar);
+        let mut builder = CanonicalBuilder {
+            grammar,
+            sets: &sets,
+            kernels: Vec::new(),
+            gotos: Vec::new(),
+            by_kernel: HashMap::new(),
+        };
+
+        let mut starts = HashMap::new();
+        for rule in start_rules(grammar) {
+            let kernel = grammar
+                .get_matches_from_rule(rule)
+                .iter()
+                .map(|&match_id| (Item { match_id, dot: 0 }, Lookahead::End))
+                .collect();
+            starts.insert(rule, builder.add_state(kernel));
+        }
+
+        builder.build();
+
+        let states = builder
+            .kernels
+            .into_iter()
+            .zip(&builder.gotos)
+            .map(|(kernel, gotos)| {
+                let mut items: Vec<(Item, Vec<Lookahead>)> = Vec::new();
+                for (item, lookahead) in kernel {
+                    match items.last_mut() {
+                        Some((last, set)) if *last == item => set.push(lookahead),
+                        _ => items.push((item, vec![lookahead])),
+                    }
+                }
+                sets.table_state(items, gotos)
+            })
+            .collect();
+
+        Self::new(TableKind::Canonical, states, starts)
+    }
+
+    fn new(kind: TableKind, states: Vec<TableState>, starts: HashMap<Rule, StateId>) -> Self {
+        let mut conflicts = Vec::new();
+        for (id, state) in states.iter().enumerate() {
+            let mut lookaheads: Vec<_> = state
+                .actions
+                .iter()
+                .filter(|(_, actions)| actions.len() > 1)
+                .collect();
+            lookaheads.sort_by_key(|(lookahead, _)| **lookahead);
+
+            for (lookahead, actions) in lookaheads {
+                conflicts.push(Conflict {
+                    state: id,
+                    lookahead: *lookahead,
+                    actions: actions.clone(),
+                });
+            }
+        }
+
+        Self {
+            kind,
+            states,
+            starts,
+            conflicts,
+        }
+    }
+
+    pub fn kind(&self) -> TableKind {
+        self.kind
+    }
+
+    /// The state to start parsing a rule from, if the rule is the root or the rule of a group.
+    pub fn start(&self, rule: Rule) -> Option<StateId> {
+        self.starts.get(&rule).copied()
+    }
+
+    pub fn state(&self, id: StateId) -> &TableState {
+        &self.states[id]
+    }
+
+    pub fn states(&self) -> &[TableState] {
+        &self.states
+    }
+
+    /// Every state and lookahead with more than one action, in state order.
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
+    /// Whether the table has no conflicts, so that the grammar is in the class of the table.
+    pub fn is_deterministic(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+
+    /// The action that the parser takes in a state for a lookahead, if there is one.
+    pub fn action(&self, state: StateId, lookahead: Lookahead) -> Option<Action> {
+        self.states[state]
+            .actions
+            .get(&lookahead)
+            .and_then(|actions| actions.first())
+            .copied()
+    }
+}
+
+/// Which of the deterministic LR classes a grammar is in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrammarClass {
+    Lalr1,
+    /// LR(1), but the LALR(1) table has reduce/reduce conflicts from merging states.
+    Lr1,
+    /// Neither, which includes every ambiguous grammar.
+    NotLr1,
+}
+
+/// Find the class of a grammar, building the canonical LR(1) table only if the LALR(1)
+/// table has conflicts.
+pub fn classify(grammar: &Grammar) -> GrammarClass {
+    if LrTable::lalr(grammar).is_deterministic() {
+        GrammarClass::Lalr1
+    } else if LrTable::canonical(grammar).is_deterministic() {
+        GrammarClass::Lr1
+    } else {
+        GrammarClass::NotLr1
+    }
+}
+
+/// The rules that parsing can start from: the root, and the rules of groups.
+fn start_rules(grammar: &Grammar) -> Vec<Rule> {
+    let mut rules = vec![grammar.get(grammar.root_id()).rule];
+    for (_, m) in grammar.iter_matches() {
+        for term in &m.terms {
+            if let Term::Group(_, rule) = term {
+                if !rules.contains(rule) {
+                    rules.push(*rule);
+                }
+            }
+        }
+    }
+    rules
+}
+
+/// The tokens and groups that each rule can start with, for finding lookaheads.
+struct LookaheadSets<'a> {
+    grammar: &'a Grammar,
+    empty_rules: EmptyRuleSolver,
+    first: HashMap<Rule, BTreeSet<Term>>,
+}
+
+/// An item with its lookahead. A missing lookahead is a placeholder for the lookaheads that
+/// the item gets from the kernel item it came from.
+type LrItem = (Item, Option<Lookahead>);
+
+impl<'a> LookaheadSets<'a> {
+    fn new(grammar: &'a Grammar) -> Self {
+        let mut sets = Self {
+            grammar,
+            empty_rules: EmptyRuleSolver::new(grammar),
+            first: HashMap::new(),
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for (_, m) in grammar.iter_matches() {
+                let (first, _) = sets.first_of(&m.terms);
+                let set = sets.first.entry(m.rule).or_default();
+                let len = set.len();
+                set.extend(first);
+                changed |= set.len() != len;
+            }
+        }
+
+        sets
+    }
+
+    /// The tokens and groups that the terms can start with, and whether they can be empty.
+    fn first_of(&self, terms: &[Term]) -> (BTreeSet<Term>, bool) {
+        let mut first = BTreeSet::new();
+        for term in terms {
+            match term {
+                Term::Token(_) | Term::Group(_, _) => {
+                    first.insert(*term);
+                    return (first, false);
+                }
+                Term::Rule(rule) => {
+                    if let Some(set) = self.first.get(rule) {
+                        first.extend(set.iter().copied());
+                    }
+                    if !self.empty_rules.is_empty(*rule) {
+                        return (first, false);
+                    }
+                }
+            }
+        }
+
+        (first, true)
+    }
+
+    /// The items, along with the start of every match that can come next and the lookaheads
+    /// that follow it.
+    fn closure(&self, kernel: &[LrItem]) -> BTreeSet<LrItem> {
+        let mut items: BTreeSet<LrItem> = kernel.iter().copied().collect();
+        let mut queue = kernel.to_vec();
+
+        while let Some((item, lookahead)) = queue.pop() {
+            let Some(Term::Rule(rule)) = item.next_term(self.grammar) else {
+                continue;
+            };
+
+            let terms = &self.grammar.get(item.match_id).terms;
+            let (first, is_empty) = self.first_of(&terms[item.dot + 1..]);
+            let mut lookaheads: Vec<_> = first
+                .into_iter()
+                .map(|t| Some(Lookahead::Term(t)))
+                .collect();
+            if is_empty {
+                lookaheads.push(lookahead);
+            }
+
+            for &match_id in self.grammar.get_matches_from_rule(rule) {
+                for &lookahead in &lookaheads {
+                    let next = (Item { match_id, dot: 0 }, lookahead);
+                    if items.insert(next) {
+                        queue.push(next);
+                    }
+                }
+            }
+        }
+
+        items
+    }
+
+    /// The actions and gotos of a state, from its kernel and the gotos of its items.
+    fn table_state(
+        &self,
+        kernel: Vec<(Item, Vec<Lookahead>)>,
+        gotos: &HashMap<Term, StateId>,
+    ) -> TableState {
+        let mut actions: HashMap<Lookahead, Vec<Action>> = HashMap::new();
+        let mut rule_gotos = HashMap::new();
+
+        for (&term, &target) in gotos {
+            match term {
+                Term::Rule(rule) => {
+                    rule_gotos.insert(rule, target);
+                }
+                Term::Token(_) | Term::Group(_, _) => {
+                    actions
+                        .entry(Lookahead::Term(term))
+                        .or_default()
+                        .push(Action::Shift(target));
+                }
+            }
+        }
+
+        let items: Vec<LrItem> = kernel
+            .iter()
+            .flat_map(|(item, set)| set.iter().map(|&lookahead| (*item, Some(lookahead))))
+            .collect();
+        for (item, lookahead) in self.closure(&items) {
+            if let (true, Some(lookahead)) = (item.is_complete(self.grammar), lookahead) {
+                let action = Action::Reduce(item.match_id);
+                let list = actions.entry(lookahead).or_default();
+                if !list.contains(&action) {
+                    list.push(action);
+                }
+            }
+        }
+
+        for list in actions.values_mut() {
+            list.sort_by_key(|action| action.priority());
+        }
+
+        TableState {
+            kernel,
+            actions,
+            gotos: rule_gotos,
+        }
+    }
+}
+
+struct CanonicalBuilder<'a> {
+    grammar: &'a Grammar,
+    sets: &'a LookaheadSets<'a>,
+    /// The kernel of each state, sorted.
+    kernels: Vec<Vec<(Item, Lookahead)>>,
+    gotos: Vec<HashMap<Term, StateId>>,
+    by_kernel: HashMap<Vec<(Item, Lookahead)>, StateId>,
+}
+
+impl CanonicalBuilder<'_> {
+    /// Get the state with the kernel, adding it if it's new. Its gotos are filled in by
+    /// `build`.
+    fn add_state(&mut self, mut kernel: Vec<(Item, Lookahead)>) -> StateId {
+        kernel.sort();
+        kernel.dedup();
+
+        if let Some(&id) = self.by_kernel.get(&kernel) {
+            return id;
+        }
+
+        let id = self.kernels.len();
+        self.by_kernel.insert(kernel.clone(), id);
+        self.kernels.push(kernel);
+        self.gotos.push(HashMap::new());
+        id
+    }
+
+    fn build(&mut self) {
+        let mut next = 0;
+        while next < self.kernels.len() {
+            let kernel: Vec<LrItem> = self.kernels[next]
+                .iter()
+                .map(|&(item, lookahead)| (item, Some(lookahead)))
+                .collect();
+
+            // Group the items of the state by the term after them, in order
+            let mut moves: Vec<(Term, Vec<(Item, Lookahead)>)> = Vec::new();
+            for (item, lookahead) in self.sets.closure(&kernel) {
+                let (Some(term), Some(lookahead)) = (item.next_term(self.grammar), lookahead)
+                else {
+                    continue;
+                };
+
+                let moved = Item {
+                    match_id: item.match_id,
+                    dot: item.dot + 1,
+                };
+                match moves.iter_mut().find(|(t, _)| *t == term) {
+                    Some((_, kernel)) => kernel.push((moved, lookahead)),
+                    None => moves.push((term, vec![(moved, lookahead)])),
+                }
+            }
+
+            for (term, kernel) in moves {
+                let target = self.add_state(kernel);
+                self.gotos[next].insert(term, target);
+            }
+
+            next += 1;
+        }
+    }
+}
+
+pub struct ConflictDisplay<'a> {
+    conflict: &'a Conflict,
+    grammar: &'a Grammar,
+}
+
+impl std::fmt::Display for ConflictDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.conflict.kind() {
+            ConflictKind::ShiftReduce => "shift/reduce",
+            ConflictKind::ReduceReduce => "reduce/reduce",
+        };
+        write!(f, "{} conflict in state {} on ", kind, self.conflict.state)?;
+        match self.conflict.lookahead {
+            Lookahead::Term(Term::Token(token)) => write!(f, "{:?}", token)?,
+            Lookahead::Term(Term::Group(group, rule)) => write!(f, "{:?}({:?})", group, rule)?,
+            Lookahead::Term(Term::Rule(rule)) => write!(f, "{:?}", rule)?,
+            Lookahead::End => write!(f, "the end")?,
+        }
+        write!(f, ":")?;
+
+        for action in &self.conflict.actions {
+            match action {
+                Action::Shift(state) => write!(f, "\n  shift to state {}", state)?,
+                Action::Reduce(id) => write!(f, "\n  reduce {}", self.grammar.get(*id))?,
+            }
+        }
+
+        Ok(())
+    }
+}
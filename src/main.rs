@@ -1,165 +1,79 @@
-use interpreter::{solve, ITokenOrGroup};
-
-use crate::matches::*;
-
-mod analysis;
-mod interpreter;
-mod matches;
-mod ref_list;
-mod solver;
-mod structures;
-
-fn make_calc_grammar() -> Grammar {
-    let mut grammar = Grammar::new();
-    grammar.add(
-        Rule::S,
-        vec![
-            Term::Token(Token::Start),
-            Term::Rule(Rule::Expr),
-            Term::Token(Token::Eof),
-        ],
-    );
-    grammar.add(Rule::Expr, vec![Term::Rule(Rule::Add)]);
-    grammar.add(
-        Rule::Add,
-        vec![
-            Term::Rule(Rule::Add),
-            Term::Token(Token::Plus),
-            Term::Rule(Rule::Mul),
-        ],
-    );
-    grammar.add(Rule::Add, vec![Term::Rule(Rule::Mul)]);
-    grammar.add(
-        Rule::Mul,
-        vec![
-            Term::Rule(Rule::Mul),
-            Term::Token(Token::Star),
-            Term::Rule(Rule::Term),
-        ],
-    );
-    grammar.add(Rule::Mul, vec![Term::Rule(Rule::Term)]);
-    grammar.add(Rule::Term, vec![Term::Token(Token::Num)]);
-
-    grammar.add(Rule::Term, vec![Term::Group(Group::Parens, Rule::S)]);
-    grammar.add(
-        Rule::Term,
-        vec![
-            Term::Token(Token::LParen),
-            Term::Rule(Rule::Expr),
-            Term::Token(Token::RParen),
-        ],
-    );
-
-    grammar
-}
-
-fn make_calc2_grammar() -> Grammar {
-    let mut grammar = Grammar::new();
-    grammar.add(
-        Rule::S,
-        vec![
-            Term::Token(Token::Start),
-            Term::Rule(Rule::Expr),
-            Term::Token(Token::Eof),
-        ],
-    );
-    grammar.add(Rule::Expr, vec![Term::Rule(Rule::Add)]);
-    grammar.add(
-        Rule::Add,
-        vec![
-            Term::Rule(Rule::Add),
-            Term::Rule(Rule::Op2),
-            Term::Rule(Rule::Mul),
-        ],
-    );
-    grammar.add(Rule::Add, vec![Term::Rule(Rule::Mul)]);
-    grammar.add(
-        Rule::Mul,
-        vec![
-            Term::Rule(Rule::Mul),
-            Term::Rule(Rule::Op1),
-            Term::Rule(Rule::Term),
-        ],
-    );
-    grammar.add(Rule::Mul, vec![Term::Rule(Rule::Term)]);
-    grammar.add(Rule::Term, vec![Term::Token(Token::Num)]);
-
-    grammar.add(Rule::Op1, vec![Term::Token(Token::Star)]);
-    grammar.add(Rule::Op1, vec![Term::Token(Token::Slash)]);
-
-    grammar.add(Rule::Op2, vec![Term::Token(Token::Plus)]);
-    grammar.add(Rule::Op2, vec![Term::Token(Token::Minus)]);
-
-    grammar.add(Rule::Term, vec![Term::Group(Group::Parens, Rule::S)]);
-    grammar.add(
-        Rule::Term,
-        vec![
-            Term::Token(Token::LParen),
-            Term::Rule(Rule::Expr),
-            Term::Token(Token::RParen),
-        ],
-    );
-
-    grammar
-}
-
-fn make_struct_fn_grammar() -> Grammar {
-    let mut grammar = Grammar::new();
-    grammar.add(
-        Rule::S,
-        vec![
-            Term::Token(Token::Start),
-            Term::Rule(Rule::Expr),
-            Term::Token(Token::Eof),
-        ],
-    );
-    grammar.add(Rule::Expr, vec![Term::Rule(Rule::Struct)]);
-    grammar.add(Rule::Expr, vec![Term::Rule(Rule::Fn)]);
-    grammar.add(
-        Rule::Struct,
-        vec![Term::Rule(Rule::Vis), Term::Token(Token::Struct)],
-    );
-    grammar.add(
-        Rule::Fn,
-        vec![Term::Rule(Rule::Vis), Term::Token(Token::Fn)],
-    );
-    grammar.add(Rule::Vis, vec![]);
-    grammar.add(
-        Rule::Vis,
-        vec![Term::Token(Token::Pub), Term::Rule(Rule::VisModifier)],
-    );
-    grammar.add(Rule::VisModifier, vec![Term::Token(Token::Star)]);
-    grammar.add(Rule::VisModifier, vec![]);
-
-    grammar
-}
-
-fn make_array_grammar() -> Grammar {
-    let mut grammar = Grammar::new();
-    grammar.add(
-        Rule::S,
-        vec![
-            Term::Token(Token::Start),
-            Term::Rule(Rule::Expr),
-            Term::Token(Token::Eof),
-        ],
-    );
-    grammar.add(
-        Rule::Expr,
-        vec![Term::Rule(Rule::Expr), Term::Rule(Rule::Term)],
-    );
-    grammar.add(Rule::Expr, vec![Term::Rule(Rule::Term)]);
-    grammar.add(Rule::Expr, vec![]);
-    grammar.add(Rule::Term, vec![Term::Token(Token::Num)]);
+use msyntax::{
+    counterexample,
+    demos::*,
+    differential, generate,
+    interpreter::{solve, ITokenOrGroup},
+    lr,
+    matches::*,
+    solver,
+};
+
+/// Check the interpreter against the Earley parser, over the demo grammars and some random
+/// grammars. The interpreter traces its stack to stdout, so reports go to stderr.
+fn check_grammars() {
+    let options = differential::DiffOptions::default();
+    let demos = [
+        ("calc", make_calc_grammar()),
+        ("calc2", make_calc2_grammar()),
+        ("struct_fn", make_struct_fn_grammar()),
+        ("array", make_array_grammar()),
+        ("statements", make_statements_grammar()),
+    ];
 
-    grammar
+    for (name, grammar) in demos {
+        let report = differential::check_grammar(&grammar, &options);
+        eprintln!("{}: {}", name, report);
+
+        let table = lr::table::LrTable::lalr(&grammar);
+        eprintln!("{}: {:?}", name, lr::table::classify(&grammar));
+        for conflict in table.conflicts() {
+            eprintln!("{}", conflict.display(&grammar));
+        }
+
+        let conflicts = solver::GrammarSolver::new(grammar.clone()).conflicts();
+        let counterexamples = counterexample::counterexamples(
+            &grammar,
+            &conflicts,
+            &counterexample::CounterexampleOptions::default(),
+        );
+        for id in conflicts.ids() {
+            match counterexamples
+                .iter()
+                .find(|example| example.conflict == id)
+            {
+                Some(example) => eprint!("{}", example.display(&grammar, &conflicts)),
+                None => eprintln!("{}", conflicts.display_conflict(id, &grammar)),
+            }
+        }
+    }
+
+    let mut rng = generate::Rng::new(0);
+    let random_options = differential::RandomGrammarOptions::default();
+    for i in 0..20 {
+        let grammar = differential::random_grammar(&mut rng, &random_options);
+        let report = differential::check_grammar(&grammar, &options);
+        eprintln!("random {}: {}", i, report);
+        if !report.is_ok() {
+            eprintln!("{}", grammar);
+        }
+    }
 }
 
 fn main() {
+    if std::env::args().any(|arg| arg == "--check") {
+        check_grammars();
+        return;
+    }
+
     let grammar = make_struct_fn_grammar();
 
     let solver = solver::GrammarSolver::new(grammar);
 
+    if let Some(path) = std::env::args().skip_while(|arg| arg != "--explain").nth(1) {
+        std::fs::write(&path, solver.explain().to_string()).expect("Failed to write the tables");
+        return;
+    }
+
     // let tokens = vec![
     //     ITokenOrGroup::Token(Token::Start),
     //     ITokenOrGroup::Token(Token::Num),
@@ -210,7 +124,10 @@ fn main() {
     // ];
 
     let result = solve(&solver, tokens);
-    println!("{}", result);
+    println!("{}", result.value);
+    for diagnostic in &result.diagnostics {
+        println!("{}", diagnostic.display(solver.grammar()));
+    }
 
     // dbg!(&grammar);
 }
@@ -1,6 +1,10 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
 
-#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Rule {
     S,
     Expr,
@@ -18,9 +22,12 @@ pub enum Rule {
     VisModifier,
     Struct,
     Fn,
+    Stmts,
+    Stmt,
 }
 
-#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Token {
     Num,
     Name,
@@ -33,20 +40,27 @@ pub enum Token {
     Struct,
     Crate,
     Arrow,
+    Eq,
+    Semi,
 
     LParen,
     RParen,
 
     Eof,
     Start,
+
+    /// A placeholder for a whole value of any rule, used in templates such as `$x + $y`.
+    /// Only grammars from `Grammar::with_holes` accept it.
+    Hole,
 }
 
-#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Group {
     Parens,
 }
 
-#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, PartialOrd, Ord)]
 pub enum Term {
     Rule(Rule),
     Token(Token),
@@ -62,6 +76,77 @@ impl Term {
     }
 }
 
+/// Anything within a grammar that can be given a human readable name.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Symbol {
+    Rule(Rule),
+    Token(Token),
+    Group(Group),
+}
+
+impl From<Rule> for Symbol {
+    fn from(rule: Rule) -> Self {
+        Symbol::Rule(rule)
+    }
+}
+
+impl From<Token> for Symbol {
+    fn from(token: Token) -> Self {
+        Symbol::Token(token)
+    }
+}
+
+impl From<Group> for Symbol {
+    fn from(group: Group) -> Self {
+        Symbol::Group(group)
+    }
+}
+
+/// The name of a symbol when it's displayed in diagnostics, e.g. "expression" or "`)`".
+/// The description replaces the name when the symbol is the only thing expected, e.g.
+/// "an expression, such as `1 + 2`".
+#[derive(Debug, Clone, Default)]
+pub struct SymbolName {
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+/// A position within a match after which the parser is committed to it, e.g. right after
+/// the `fn` keyword. Errors past this point are reported in the context of the match
+/// (e.g. "invalid function"), and error recovery keeps the match instead of discarding it.
+#[derive(Debug, Clone)]
+pub struct CommitPoint {
+    /// The number of terms that need to be parsed for the match to be committed.
+    pub index: usize,
+    pub label: String,
+}
+
+/// A place where the formatter can break a line, before one of the terms of a match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Break {
+    /// No space, regardless of the spacing rules.
+    None,
+    /// A single space, regardless of the spacing rules.
+    Space,
+    /// Nothing, or a new line if the match doesn't fit on the line.
+    SoftLine,
+    /// A space, or a new line if the match doesn't fit on the line.
+    Line,
+    /// Always a new line.
+    HardLine,
+}
+
+/// How the formatter lays out a match.
+#[derive(Debug, Clone, Default)]
+pub struct Layout {
+    /// The break before each term, if it has one. Tokens without a break in between are
+    /// separated by the spacing rules.
+    pub breaks: Vec<Option<Break>>,
+    /// The terms that are indented when they start on a new line.
+    pub indent: Option<Range<usize>>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Match {
     pub rule: Rule,
@@ -74,9 +159,17 @@ impl Match {
     }
 }
 
-#[derive(Clone, Copy, Hash, Eq, PartialEq)]
+#[derive(Clone, Copy, Hash, Eq, PartialEq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MatchId(u32);
 
+impl MatchId {
+    /// The position of the match within its grammar.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
 impl std::fmt::Debug for MatchId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "MatchId({})", self.0)
@@ -89,8 +182,31 @@ pub struct Grammar {
     matches: Vec<Match>,
     /// A map from a rule to all the matches that the rule has.
     rule_matches: HashMap<Rule, Vec<MatchId>>,
-    /// A map from a match to its index within the rule_matches map.
-    rule_match_index: HashMap<MatchId, usize>,
+    /// A map from a rule to all the matches that the rule has, including recovery matches.
+    rule_matches_with_recovery: HashMap<Rule, Vec<MatchId>>,
+    /// The diagnostic messages of recovery matches. Recovery matches describe common
+    /// mistakes, and are only tried when the parser is recovering from an error.
+    recovery_messages: HashMap<MatchId, String>,
+    /// Whether recovery matches are visible when iterating over the grammar.
+    include_recovery: bool,
+    /// The matches that were added by `with_holes`, which consist of a single hole.
+    hole_matches: HashSet<MatchId>,
+    /// Human readable names of rules, tokens and groups, used in diagnostics.
+    names: HashMap<Symbol, SymbolName>,
+    /// The commit points of matches, if they have one.
+    commit_points: HashMap<MatchId, CommitPoint>,
+    /// Names of the terms of matches, e.g. `lhs` and `rhs` in an addition.
+    field_names: HashMap<MatchId, Vec<Option<String>>>,
+    /// The tokens that open and close each group in the source, e.g. `(` and `)`.
+    group_delimiters: HashMap<Group, (Token, Token)>,
+    /// Formatting annotations of matches.
+    layouts: HashMap<MatchId, Layout>,
+}
+
+impl Default for Grammar {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Grammar {
@@ -98,7 +214,15 @@ impl Grammar {
         Self {
             matches: Vec::new(),
             rule_matches: HashMap::new(),
-            rule_match_index: HashMap::new(),
+            rule_matches_with_recovery: HashMap::new(),
+            recovery_messages: HashMap::new(),
+            include_recovery: false,
+            hole_matches: HashSet::new(),
+            names: HashMap::new(),
+            commit_points: HashMap::new(),
+            field_names: HashMap::new(),
+            group_delimiters: HashMap::new(),
+            layouts: HashMap::new(),
         }
     }
 
@@ -107,12 +231,34 @@ impl Grammar {
         self.matches.push(Match::new(rule, terms));
 
         let match_id = MatchId(id as u32);
-        self.rule_matches
+        self.rule_matches.entry(rule).or_default().push(match_id);
+        self.rule_matches_with_recovery
             .entry(rule)
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(match_id);
-        self.rule_match_index
-            .insert(match_id, self.rule_matches[&rule].len() - 1);
+
+        match_id
+    }
+
+    /// Add a recovery match, which describes a common mistake (e.g. a missing semicolon).
+    /// Recovery matches are ignored by the regular first and follow sets, and are only
+    /// tried when the parser fails to continue. When one is used, the resulting rule value
+    /// is flagged as an error and the message is reported as a diagnostic.
+    pub fn add_recovery(
+        &mut self,
+        rule: Rule,
+        terms: Vec<Term>,
+        message: impl Into<String>,
+    ) -> MatchId {
+        let id = self.matches.len();
+        self.matches.push(Match::new(rule, terms));
+
+        let match_id = MatchId(id as u32);
+        self.rule_matches_with_recovery
+            .entry(rule)
+            .or_default()
+            .push(match_id);
+        self.recovery_messages.insert(match_id, message.into());
 
         match_id
     }
@@ -127,11 +273,17 @@ impl Grammar {
         &self.matches[id.0 as usize]
     }
 
+    /// Get the id of the match at the index, if the grammar has that many matches.
+    pub fn get_match_id(&self, index: usize) -> Option<MatchId> {
+        (index < self.matches.len()).then_some(MatchId(index as u32))
+    }
+
     pub fn iter_matches(&self) -> impl Iterator<Item = (MatchId, &Match)> {
         self.matches
             .iter()
             .enumerate()
             .map(|(i, m)| (MatchId(i as u32), m))
+            .filter(|(id, _)| self.include_recovery || !self.is_recovery_match(*id))
     }
 
     pub fn get_rule_from_match(&self, id: MatchId) -> Rule {
@@ -139,21 +291,242 @@ impl Grammar {
     }
 
     pub fn get_matches_from_rule(&self, rule: Rule) -> &[MatchId] {
-        self.rule_matches
+        self.visible_rule_matches()
             .get(&rule)
             .map(|v| v.as_slice())
             .unwrap_or(&[])
     }
 
+    /// The index of the match among the matches of its rule that are visible in this grammar,
+    /// so that it matches the list that `get_matches_from_rule` returns.
     pub fn get_rule_match_index(&self, id: MatchId) -> usize {
-        self.rule_match_index[&id]
+        let rule = self.get(id).rule;
+        self.get_matches_from_rule(rule)
+            .iter()
+            .position(|&other| other == id)
+            .expect("The match isn't visible in this grammar")
     }
 
     pub fn iter_rules(&self) -> impl '_ + Iterator<Item = Rule> {
-        self.rule_matches.keys().cloned()
+        self.visible_rule_matches().keys().cloned()
+    }
+
+    fn visible_rule_matches(&self) -> &HashMap<Rule, Vec<MatchId>> {
+        if self.include_recovery {
+            &self.rule_matches_with_recovery
+        } else {
+            &self.rule_matches
+        }
+    }
+
+    /// Find a rule of the grammar by the name that it has in code, e.g. `Expr`.
+    pub fn find_rule(&self, name: &str) -> Option<Rule> {
+        self.matches
+            .iter()
+            .flat_map(|m| {
+                let terms = m.terms.iter().filter_map(|term| match term {
+                    Term::Rule(rule) | Term::Group(_, rule) => Some(*rule),
+                    Term::Token(_) => None,
+                });
+                std::iter::once(m.rule).chain(terms)
+            })
+            .find(|rule| format!("{:?}", rule) == name)
+    }
+
+    /// Find a token used by the grammar by the name that it has in code, e.g. `Num`.
+    pub fn find_token(&self, name: &str) -> Option<Token> {
+        self.matches
+            .iter()
+            .flat_map(|m| &m.terms)
+            .find_map(|term| match term {
+                Term::Token(token) if format!("{:?}", token) == name => Some(*token),
+                _ => None,
+            })
+    }
+
+    pub fn is_recovery_match(&self, id: MatchId) -> bool {
+        self.recovery_messages.contains_key(&id)
+    }
+
+    pub fn get_recovery_message(&self, id: MatchId) -> Option<&str> {
+        self.recovery_messages.get(&id).map(|m| m.as_str())
+    }
+
+    pub fn has_recovery_matches(&self) -> bool {
+        !self.recovery_messages.is_empty()
+    }
+
+    pub fn includes_recovery_matches(&self) -> bool {
+        self.include_recovery
+    }
+
+    /// Returns a copy of the grammar where recovery matches are visible like regular matches.
+    /// Match ids are shared between both grammars.
+    pub fn with_recovery_matches(&self) -> Self {
+        let mut grammar = self.clone();
+        grammar.include_recovery = true;
+        grammar
+    }
+
+    /// Returns a copy of the grammar where every rule can also be a single hole token. Match
+    /// ids of the original grammar stay the same.
+    pub fn with_holes(&self) -> Self {
+        let mut grammar = self.clone();
+
+        let mut rules: Vec<_> = self.rule_matches.keys().copied().collect();
+        rules.sort_by_key(|rule| self.rule_matches[rule][0].0);
+
+        for rule in rules {
+            let id = grammar.add(rule, vec![Term::Token(Token::Hole)]);
+            grammar.hole_matches.insert(id);
+        }
+
+        grammar
+    }
+
+    pub fn is_hole_match(&self, id: MatchId) -> bool {
+        self.hole_matches.contains(&id)
+    }
+
+    pub fn has_holes(&self) -> bool {
+        !self.hole_matches.is_empty()
+    }
+
+    pub fn set_name(&mut self, symbol: impl Into<Symbol>, name: impl Into<String>) {
+        let entry = self.names.entry(symbol.into()).or_default();
+        entry.name = Some(name.into());
+    }
+
+    pub fn set_description(&mut self, symbol: impl Into<Symbol>, description: impl Into<String>) {
+        let entry = self.names.entry(symbol.into()).or_default();
+        entry.description = Some(description.into());
+    }
+
+    /// Whether the symbol was given a custom name.
+    pub fn has_name(&self, symbol: impl Into<Symbol>) -> bool {
+        self.names
+            .get(&symbol.into())
+            .is_some_and(|n| n.name.is_some())
+    }
+
+    /// Get the display name of a symbol, falling back to its debug name.
+    pub fn get_name(&self, symbol: impl Into<Symbol>) -> String {
+        let symbol = symbol.into();
+        let name = self.names.get(&symbol).and_then(|n| n.name.clone());
+
+        name.unwrap_or_else(|| match symbol {
+            Symbol::Rule(rule) => format!("{:?}", rule),
+            Symbol::Token(token) => format!("{:?}", token),
+            Symbol::Group(group) => format!("{:?}", group),
+        })
+    }
+
+    pub fn get_description(&self, symbol: impl Into<Symbol>) -> Option<&str> {
+        self.names
+            .get(&symbol.into())
+            .and_then(|n| n.description.as_deref())
+    }
+
+    /// Mark the match as committed once `index` of its terms have been parsed.
+    pub fn set_commit_point(&mut self, id: MatchId, index: usize, label: impl Into<String>) {
+        let label = label.into();
+        self.commit_points.insert(id, CommitPoint { index, label });
+    }
+
+    pub fn get_commit_point(&self, id: MatchId) -> Option<&CommitPoint> {
+        self.commit_points.get(&id)
+    }
+
+    /// Name the term at `index` within the match, so that it can be referred to by name.
+    pub fn set_field_name(&mut self, id: MatchId, index: usize, name: impl Into<String>) {
+        let len = self.get(id).terms.len();
+        assert!(index < len, "Field index {} is out of bounds", index);
+
+        let names = self
+            .field_names
+            .entry(id)
+            .or_insert_with(|| vec![None; len]);
+        names[index] = Some(name.into());
+    }
+
+    pub fn get_field_name(&self, id: MatchId, index: usize) -> Option<&str> {
+        self.field_names.get(&id)?.get(index)?.as_deref()
+    }
+
+    /// The index of the term with the given name within the match.
+    pub fn get_field_index(&self, id: MatchId, name: &str) -> Option<usize> {
+        self.field_names
+            .get(&id)?
+            .iter()
+            .position(|n| n.as_deref() == Some(name))
+    }
+
+    /// Set the tokens that a group is written with in the source. Groups are parsed from
+    /// their contents alone, so this is only used when printing trees back out.
+    pub fn set_group_delimiters(&mut self, group: Group, open: Token, close: Token) {
+        self.group_delimiters.insert(group, (open, close));
+    }
+
+    pub fn get_group_delimiters(&self, group: Group) -> Option<(Token, Token)> {
+        self.group_delimiters.get(&group).copied()
+    }
+
+    /// Let the formatter break the line before the term at `index` within the match.
+    pub fn set_break(&mut self, id: MatchId, index: usize, kind: Break) {
+        let len = self.get(id).terms.len();
+        assert!(index < len, "Break index {} is out of bounds", index);
+
+        let layout = self.layouts.entry(id).or_default();
+        layout.breaks.resize(len, None);
+        layout.breaks[index] = Some(kind);
+    }
+
+    /// Indent the terms of the match within the range, when they start on a new line.
+    pub fn set_indent(&mut self, id: MatchId, terms: Range<usize>) {
+        let len = self.get(id).terms.len();
+        assert!(
+            terms.end <= len,
+            "Indent range {:?} is out of bounds",
+            terms
+        );
+
+        self.layouts.entry(id).or_default().indent = Some(terms);
+    }
+
+    pub fn get_layout(&self, id: MatchId) -> Option<&Layout> {
+        self.layouts.get(&id)
     }
 
     pub fn root_id(&self) -> MatchId {
         MatchId(0)
     }
 }
+
+impl std::fmt::Display for Match {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} ->", self.rule)?;
+        if self.terms.is_empty() {
+            write!(f, " ε")?;
+        }
+
+        for term in &self.terms {
+            match term {
+                Term::Rule(rule) => write!(f, " {:?}", rule)?,
+                Term::Token(token) => write!(f, " {:?}", token)?,
+                Term::Group(group, rule) => write!(f, " {:?}({:?})", group, rule)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Grammar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (id, m) in self.iter_matches() {
+            writeln!(f, "{}: {}", id.index(), m)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,363 @@
+//! A PEG parser, for grammars that are meant to be read as parsing expression grammars.
+//!
+//! The matches of a rule are tried in the order that they were added to the grammar, and the
+//! first one that matches wins, even if a later one would have let the rest of the input
+//! parse. Results are memoized for each rule and position, so nothing is parsed twice, apart
+//! from left recursive rules while they grow.
+//! Left recursion is supported in the style of Warth et al: a left recursive rule first
+//! fails where it recurses, giving a seed parse from its other matches, which is then grown
+//! by parsing the rule again until it stops getting longer.
+//!
+//! The trees are the same rule values that the interpreter makes, but they follow PEG
+//! semantics, so they can differ for grammars where the interpreter would pick a later match.
+//! Groups have to be matched completely by the rule of the group.
+
+use std::{collections::HashMap, ops::Range};
+
+use crate::{
+    diagnostics::{Diagnostic, DiagnosticKind, ErrorCounts, Found},
+    interpreter::{ITokenOrGroup, ParseResult, RuleValue, Value},
+    matches::{Grammar, MatchId, Rule, Symbol, Term},
+    span::{check_byte_ranges, ByteRangesError, Span},
+};
+
+/// A parser that reads the grammar with ordered choice.
+pub struct PegParser {
+    grammar: Grammar,
+}
+
+impl PegParser {
+    pub fn new(grammar: Grammar) -> Self {
+        Self { grammar }
+    }
+
+    pub fn grammar(&self) -> &Grammar {
+        &self.grammar
+    }
+
+    pub fn parse(&self, tokens: Vec<ITokenOrGroup>) -> ParseResult {
+        self.parse_tokens(tokens, None)
+    }
+
+    /// Parse, with the byte range of each token in the flattened token stream, like
+    /// `solve_with_spans`.
+    pub fn parse_with_spans(
+        &self,
+        tokens: Vec<ITokenOrGroup>,
+        byte_ranges: &[Range<usize>],
+    ) -> Result<ParseResult, ByteRangesError> {
+        check_byte_ranges(&tokens, byte_ranges)?;
+        Ok(self.parse_tokens(tokens, Some(byte_ranges)))
+    }
+
+    fn parse_tokens(
+        &self,
+        tokens: Vec<ITokenOrGroup>,
+        byte_ranges: Option<&[Range<usize>]>,
+    ) -> ParseResult {
+        let root_id = self.grammar.root_id();
+        let rule = self.grammar.get(root_id).rule;
+
+        let (value, diagnostics) = match self.parse_sequence(rule, &tokens, 0, byte_ranges) {
+            Ok(value) => (value, Vec::new()),
+            Err(diagnostic) => {
+                let len = tokens.iter().map(|item| item.flat_len()).sum();
+                let span = Span::new(0..len, byte_ranges);
+                let value = RuleValue::root_error(&self.grammar, span);
+                (value, vec![diagnostic])
+            }
+        };
+
+        ParseResult {
+            value,
+            error_counts: ErrorCounts {
+                reported: diagnostics.len(),
+                ..Default::default()
+            },
+            diagnostics,
+        }
+    }
+
+    /// Parse a sequence of tokens that starts at the position as the rule, which has to
+    /// match all of them.
+    fn parse_sequence(
+        &self,
+        rule: Rule,
+        tokens: &[ITokenOrGroup],
+        start: usize,
+        byte_ranges: Option<&[Range<usize>]>,
+    ) -> Result<RuleValue, Diagnostic> {
+        let mut positions = vec![start];
+        for item in tokens {
+            positions.push(positions.last().unwrap() + item.flat_len());
+        }
+
+        let mut sequence = Sequence {
+            parser: self,
+            tokens,
+            positions,
+            byte_ranges,
+            memo: HashMap::new(),
+            calls: Vec::new(),
+            involved: HashMap::new(),
+            groups: HashMap::new(),
+            error: None,
+        };
+
+        match sequence.apply(rule, 0) {
+            Some((end, value)) if end == tokens.len() => Ok(value),
+            Some((end, _)) => {
+                // The rule matched, but left some of the input
+                sequence.fail(end, None);
+                Err(sequence.error.unwrap())
+            }
+            None => {
+                // Rules without any matches fail without expecting anything
+                if sequence.error.is_none() {
+                    sequence.fail(0, None);
+                }
+                Err(sequence.error.unwrap())
+            }
+        }
+    }
+}
+
+/// The result of a rule at a position: the item index where it ends, and its value.
+type Memo = Option<(usize, RuleValue)>;
+
+#[derive(Debug, Clone)]
+enum MemoEntry {
+    /// The rule is being parsed at the position, and recursing into it again fails. The
+    /// flag is set if that happened, which means the rule is left recursive there.
+    InProgress(bool),
+    Done(Memo),
+}
+
+struct Sequence<'p, 't> {
+    parser: &'p PegParser,
+    tokens: &'t [ITokenOrGroup],
+    /// The position of each item in the flattened token stream, and of the end.
+    positions: Vec<usize>,
+    byte_ranges: Option<&'t [Range<usize>]>,
+    memo: HashMap<(Rule, usize), MemoEntry>,
+    /// The rules being parsed, outermost first.
+    calls: Vec<(Rule, usize)>,
+    /// The rules that a left recursive rule goes through to reach itself. Their entries are
+    /// cleared before each attempt to grow it, so that they use the longer seed.
+    involved: HashMap<(Rule, usize), Vec<(Rule, usize)>>,
+    /// The values of the groups that were parsed, by item index and rule.
+    groups: HashMap<(usize, Rule), Option<RuleValue>>,
+    /// The failure furthest into the input, which is reported if the parse fails.
+    error: Option<Diagnostic>,
+}
+
+impl Sequence<'_, '_> {
+    fn grammar(&self) -> &Grammar {
+        &self.parser.grammar
+    }
+
+    fn span(&self, items: Range<usize>) -> Span {
+        Span::new(
+            self.positions[items.start]..self.positions[items.end],
+            self.byte_ranges,
+        )
+    }
+
+    fn found(&self, index: usize) -> Found {
+        match self.tokens.get(index) {
+            Some(ITokenOrGroup::Token(token)) => Found::Token(*token),
+            Some(ITokenOrGroup::Group(_)) => Found::Group,
+            None => Found::End,
+        }
+    }
+
+    /// Parse a rule at an item index, using the memo.
+    fn apply(&mut self, rule: Rule, index: usize) -> Memo {
+        let key = (rule, index);
+        match self.memo.get_mut(&key) {
+            Some(MemoEntry::Done(memo)) => return memo.clone(),
+            Some(MemoEntry::InProgress(is_recursive)) => {
+                *is_recursive = true;
+
+                let head = self.calls.iter().rposition(|call| *call == key).unwrap();
+                let involved = self.involved.entry(key).or_default();
+                for call in &self.calls[head + 1..] {
+                    if !involved.contains(call) {
+                        involved.push(*call);
+                    }
+                }
+                return None;
+            }
+            None => {}
+        }
+
+        self.memo.insert(key, MemoEntry::InProgress(false));
+        self.calls.push(key);
+        let mut result = self.choose(rule, index);
+
+        let is_recursive = matches!(self.memo.get(&key), Some(MemoEntry::InProgress(true)));
+        if is_recursive && result.is_some() {
+            result = self.grow(rule, index, result);
+        }
+
+        self.calls.pop();
+        self.memo.insert(key, MemoEntry::Done(result.clone()));
+        result
+    }
+
+    /// Grow the seed of a left recursive rule, until parsing it again doesn't get further.
+    fn grow(&mut self, rule: Rule, index: usize, seed: Memo) -> Memo {
+        let key = (rule, index);
+        let involved = self.involved.get(&key).cloned().unwrap_or_default();
+        let mut best = seed;
+
+        loop {
+            self.memo.insert(key, MemoEntry::Done(best.clone()));
+            for key in &involved {
+                if let Some(MemoEntry::Done(_)) = self.memo.get(key) {
+                    self.memo.remove(key);
+                }
+            }
+
+            let next = self.choose(rule, index);
+            let best_end = best.as_ref().map(|(end, _)| *end);
+            match next {
+                Some((end, _)) if Some(end) > best_end => best = next,
+                _ => break,
+            }
+        }
+
+        best
+    }
+
+    /// Try the matches of the rule in order, returning the first that matches.
+    fn choose(&mut self, rule: Rule, index: usize) -> Memo {
+        let matches = self.grammar().get_matches_from_rule(rule).to_vec();
+        for id in matches {
+            if let Some(result) = self.sequence(id, index) {
+                return Some(result);
+            }
+        }
+
+        None
+    }
+
+    fn sequence(&mut self, id: MatchId, start: usize) -> Memo {
+        let terms = self.grammar().get(id).terms.clone();
+        let mut values = Vec::new();
+        let mut index = start;
+
+        for term in &terms {
+            match *term {
+                Term::Token(token) => match self.tokens.get(index) {
+                    Some(ITokenOrGroup::Token(found)) if *found == token => {
+                        values.push(Value::Token(token, self.span(index..index + 1)));
+                        index += 1;
+                    }
+                    _ => {
+                        self.fail(index, Some(Symbol::Token(token)));
+                        return None;
+                    }
+                },
+                Term::Group(group, group_rule) => match self.group(index, group_rule) {
+                    Some(value) => {
+                        values.push(Value::Rule(value));
+                        index += 1;
+                    }
+                    None => {
+                        // Groups that didn't parse inside have already reported why
+                        if !matches!(self.tokens.get(index), Some(ITokenOrGroup::Group(_))) {
+                            self.fail(index, Some(Symbol::Group(group)));
+                        }
+                        return None;
+                    }
+                },
+                Term::Rule(inner) => {
+                    let (end, value) = self.apply(inner, index)?;
+                    values.push(Value::Rule(value));
+                    index = end;
+                }
+            }
+        }
+
+        let span = self.span(start..index);
+        let value = RuleValue::from_match(self.grammar(), id, values, span);
+        Some((index, value))
+    }
+
+    /// Parse the group at the index as the rule, if there's a group there.
+    fn group(&mut self, index: usize, rule: Rule) -> Option<RuleValue> {
+        let Some(ITokenOrGroup::Group(inner)) = self.tokens.get(index) else {
+            return None;
+        };
+
+        if let Some(value) = self.groups.get(&(index, rule)) {
+            return value.clone();
+        }
+
+        let result =
+            self.parser
+                .parse_sequence(rule, inner, self.positions[index], self.byte_ranges);
+
+        let value = match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.merge_error(error);
+                None
+            }
+        };
+
+        self.groups.insert((index, rule), value.clone());
+        value
+    }
+
+    /// Record that a symbol was expected at an item index, if it's at least as far as the
+    /// furthest failure so far.
+    fn fail(&mut self, index: usize, expected: Option<Symbol>) {
+        let kind = DiagnosticKind::Unexpected {
+            expected: expected.into_iter().collect(),
+            found: self.found(index),
+            context: None,
+        };
+        self.merge_error(Diagnostic::new(kind, self.positions[index]));
+    }
+
+    fn merge_error(&mut self, error: Diagnostic) {
+        let Some(current) = &mut self.error else {
+            self.error = Some(error);
+            return;
+        };
+
+        if error.token_index > current.token_index {
+            *current = error;
+            return;
+        }
+
+        if error.token_index < current.token_index {
+            return;
+        }
+
+        if let (
+            DiagnosticKind::Unexpected {
+                expected, found, ..
+            },
+            DiagnosticKind::Unexpected {
+                expected: more,
+                found: other,
+                ..
+            },
+        ) = (&mut current.kind, error.kind)
+        {
+            // The start of a group and the first token inside of it are at the same position
+            if *found != other {
+                return;
+            }
+
+            for symbol in more {
+                if !expected.contains(&symbol) {
+                    expected.push(symbol);
+                }
+            }
+        }
+    }
+}
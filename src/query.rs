@@ -0,0 +1,469 @@
+//! Patterns for finding nodes in a tree of rule values, e.g. `(Add lhs:(Num) op:Plus
+//! rhs:_ @rhs)`.
+//!
+//! A pattern is one of:
+//! - `(Rule child...)`: a node of the rule, whose children match the child patterns. A
+//!   child is either `field:pattern`, which matches the term with that name, or a plain
+//!   pattern. Plain patterns match the node's children in order, but other children may
+//!   be in between them. `(_ child...)` matches a node of any rule.
+//! - `Token`: a single token. Within another pattern, this also matches a node that only
+//!   consists of the token (e.g. `Term -> Num`). `(Token)` also works, as long as there's
+//!   no rule of the same name.
+//! - `Rule`: a node of the rule, with any children.
+//! - `ERROR`: input that couldn't be parsed.
+//! - `_`: anything.
+//!
+//! Any pattern can be followed by captures such as `@name`, which return the node that
+//! was matched. Rules and tokens are written with the names that they have in code. Since
+//! rules that only contain another rule are collapsed, a node also matches the rule of its
+//! match, e.g. `(Add)` matches an addition even when it's only seen as an `Expr`.
+
+use std::collections::HashMap;
+
+use crate::{
+    interpreter::{RuleValue, Value},
+    matches::{Grammar, MatchId, Rule, Token},
+    span::Span,
+};
+
+/// A compiled set of patterns.
+#[derive(Debug, Clone)]
+pub struct Query {
+    patterns: Vec<Pattern>,
+    capture_names: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Pattern {
+    kind: PatternKind,
+    /// Indexes into the query's capture names.
+    captures: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+enum PatternKind {
+    Any,
+    Error,
+    Token(Token),
+    Node {
+        /// The rule of the node, along with every match that belongs to it. `None` matches
+        /// any rule.
+        rule: Option<(Rule, Vec<MatchId>)>,
+        fields: Vec<FieldPattern>,
+        children: Vec<Pattern>,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct FieldPattern {
+    /// The index of the field within each match that has it.
+    indexes: HashMap<MatchId, usize>,
+    pattern: Pattern,
+}
+
+/// A node of a parse tree, which is what patterns are matched against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeRef<'t> {
+    Rule(&'t RuleValue),
+    Token(Token, &'t Span),
+    Error(&'t Span),
+}
+
+impl<'t> NodeRef<'t> {
+    pub fn span(&self) -> &'t Span {
+        match self {
+            NodeRef::Rule(rule) => &rule.span,
+            NodeRef::Token(_, span) => span,
+            NodeRef::Error(span) => span,
+        }
+    }
+}
+
+impl<'t> From<&'t Value> for NodeRef<'t> {
+    fn from(value: &'t Value) -> Self {
+        match value {
+            Value::Token(token, span) => NodeRef::Token(*token, span),
+            Value::Rule(rule) => NodeRef::Rule(rule),
+            Value::Error(span) => NodeRef::Error(span),
+        }
+    }
+}
+
+/// A node that one of the query's patterns matched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryMatch<'t> {
+    /// The index of the pattern within the query.
+    pub pattern: usize,
+    pub node: NodeRef<'t>,
+    pub captures: Vec<Capture<'t>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capture<'t> {
+    /// The index of the capture's name within the query.
+    pub index: usize,
+    pub node: NodeRef<'t>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryError {
+    pub message: String,
+    /// The byte offset of the error within the query.
+    pub offset: usize,
+}
+
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+impl Query {
+    /// Compile one or more patterns, resolving names against the grammar.
+    pub fn new(grammar: &Grammar, source: &str) -> Result<Self, QueryError> {
+        let mut parser = QueryParser::new(grammar, source);
+        let mut patterns = Vec::new();
+
+        parser.skip_whitespace();
+        while parser.peek().is_some() {
+            patterns.push(parser.parse_pattern()?);
+            parser.skip_whitespace();
+        }
+
+        if patterns.is_empty() {
+            return Err(parser.error("Expected a pattern"));
+        }
+
+        Ok(Self {
+            patterns,
+            capture_names: parser.capture_names,
+        })
+    }
+
+    pub fn pattern_count(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn capture_names(&self) -> &[String] {
+        &self.capture_names
+    }
+
+    pub fn capture_index(&self, name: &str) -> Option<usize> {
+        self.capture_names.iter().position(|n| n == name)
+    }
+
+    /// Match every pattern against every node of the tree, in preorder.
+    pub fn matches<'t>(&self, root: &'t RuleValue) -> Vec<QueryMatch<'t>> {
+        let mut matches = Vec::new();
+        self.collect_matches(NodeRef::Rule(root), &mut matches);
+        matches
+    }
+
+    /// Match the patterns against a single node, without looking at its descendants.
+    pub fn matches_at<'t>(&self, node: NodeRef<'t>) -> Vec<QueryMatch<'t>> {
+        let mut matches = Vec::new();
+        for (index, pattern) in self.patterns.iter().enumerate() {
+            let mut captures = Vec::new();
+            if pattern.matches(node, false, &mut captures) {
+                matches.push(QueryMatch {
+                    pattern: index,
+                    node,
+                    captures,
+                });
+            }
+        }
+
+        matches
+    }
+
+    fn collect_matches<'t>(&self, node: NodeRef<'t>, matches: &mut Vec<QueryMatch<'t>>) {
+        matches.extend(self.matches_at(node));
+
+        if let NodeRef::Rule(rule) = node {
+            for value in &rule.values {
+                self.collect_matches(value.into(), matches);
+            }
+        }
+    }
+}
+
+impl Pattern {
+    /// Match the pattern against a node. Token patterns only see through nodes when
+    /// `is_child` is set, so that a token isn't matched again at each node around it.
+    fn matches<'t>(
+        &self,
+        node: NodeRef<'t>,
+        is_child: bool,
+        captures: &mut Vec<Capture<'t>>,
+    ) -> bool {
+        let start = captures.len();
+        captures.extend(self.captures.iter().map(|&index| Capture { index, node }));
+
+        let matched = match (&self.kind, node) {
+            (PatternKind::Any, _) => true,
+            (PatternKind::Error, NodeRef::Error(_)) => true,
+            (PatternKind::Token(expected), NodeRef::Token(token, _)) => *expected == token,
+            (PatternKind::Token(expected), NodeRef::Rule(value)) if is_child => {
+                single_token(value) == Some(*expected)
+            }
+            (
+                PatternKind::Node {
+                    rule,
+                    fields,
+                    children,
+                },
+                NodeRef::Rule(value),
+            ) => {
+                let rule_matches = match rule {
+                    Some((rule, ids)) => value.rule == *rule || ids.contains(&value.match_id),
+                    None => true,
+                };
+
+                rule_matches
+                    && fields.iter().all(|field| field.matches(value, captures))
+                    && children_match(children, &value.values, captures)
+            }
+            _ => false,
+        };
+
+        if !matched {
+            captures.truncate(start);
+        }
+
+        matched
+    }
+}
+
+impl FieldPattern {
+    fn matches<'t>(&self, node: &'t RuleValue, captures: &mut Vec<Capture<'t>>) -> bool {
+        let Some(&index) = self.indexes.get(&node.match_id) else {
+            return false;
+        };
+
+        match node.values.get(index) {
+            Some(value) => self.pattern.matches(value.into(), true, captures),
+            None => false,
+        }
+    }
+}
+
+/// The token that a node consists of, if that's all it has, e.g. `Term -> Num`.
+fn single_token(node: &RuleValue) -> Option<Token> {
+    match node.values.as_slice() {
+        [Value::Token(token, _)] => Some(*token),
+        [Value::Rule(inner)] => single_token(inner),
+        _ => None,
+    }
+}
+
+/// Match the patterns against the values in order, skipping values in between. Taking the
+/// first value that matches each pattern is always enough to find a match if there is one.
+fn children_match<'t>(
+    patterns: &[Pattern],
+    values: &'t [Value],
+    captures: &mut Vec<Capture<'t>>,
+) -> bool {
+    let mut values = values.iter();
+    patterns
+        .iter()
+        .all(|pattern| values.any(|value| pattern.matches(value.into(), true, captures)))
+}
+
+struct QueryParser<'a> {
+    grammar: &'a Grammar,
+    source: &'a str,
+    offset: usize,
+    capture_names: Vec<String>,
+}
+
+impl<'a> QueryParser<'a> {
+    fn new(grammar: &'a Grammar, source: &'a str) -> Self {
+        Self {
+            grammar,
+            source,
+            offset: 0,
+            capture_names: Vec::new(),
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> QueryError {
+        QueryError {
+            message: message.into(),
+            offset: self.offset,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source[self.offset..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.source[self.offset..];
+        self.offset += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.offset += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_name(&mut self) -> Result<&'a str, QueryError> {
+        self.skip_whitespace();
+        let rest = &self.source[self.offset..];
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+
+        if len == 0 {
+            return Err(self.error("Expected a name"));
+        }
+
+        self.offset += len;
+        Ok(&rest[..len])
+    }
+
+    fn parse_pattern(&mut self) -> Result<Pattern, QueryError> {
+        let kind = if self.eat('(') {
+            self.parse_node()?
+        } else {
+            let start = self.offset;
+            match self.parse_name()? {
+                "_" => PatternKind::Any,
+                "ERROR" => PatternKind::Error,
+                name => match self.grammar.find_token(name) {
+                    Some(token) => PatternKind::Token(token),
+                    None => PatternKind::Node {
+                        rule: Some(self.resolve_rule(name, start)?),
+                        fields: Vec::new(),
+                        children: Vec::new(),
+                    },
+                },
+            }
+        };
+
+        let mut captures = Vec::new();
+        while self.eat('@') {
+            let name = self.parse_name()?;
+            let index = match self.capture_names.iter().position(|n| n == name) {
+                Some(index) => index,
+                None => {
+                    self.capture_names.push(name.to_string());
+                    self.capture_names.len() - 1
+                }
+            };
+            captures.push(index);
+        }
+
+        Ok(Pattern { kind, captures })
+    }
+
+    /// Parse the inside of a parenthesized pattern, after the `(`.
+    fn parse_node(&mut self) -> Result<PatternKind, QueryError> {
+        self.skip_whitespace();
+        let start = self.offset;
+        let name = self.parse_name()?;
+
+        let rule = match name {
+            "_" => None,
+            "ERROR" => {
+                self.expect_close()?;
+                return Ok(PatternKind::Error);
+            }
+            _ if self.grammar.find_rule(name).is_none()
+                && self.grammar.find_token(name).is_some() =>
+            {
+                let token = self.grammar.find_token(name).unwrap();
+                self.expect_close()?;
+                return Ok(PatternKind::Token(token));
+            }
+            _ => Some(self.resolve_rule(name, start)?),
+        };
+
+        let mut fields = Vec::new();
+        let mut children = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(')') => {
+                    self.offset += 1;
+                    break;
+                }
+                None => return Err(self.error("Expected `)`")),
+                _ => {}
+            }
+
+            let start = self.offset;
+            if self.peek() != Some('(') {
+                let name = self.parse_name()?;
+                if self.eat(':') {
+                    let indexes = self.resolve_field(name, start)?;
+                    let pattern = self.parse_pattern()?;
+                    fields.push(FieldPattern { indexes, pattern });
+                    continue;
+                }
+
+                self.offset = start;
+            }
+
+            children.push(self.parse_pattern()?);
+        }
+
+        Ok(PatternKind::Node {
+            rule,
+            fields,
+            children,
+        })
+    }
+
+    fn expect_close(&mut self) -> Result<(), QueryError> {
+        if self.eat(')') {
+            Ok(())
+        } else {
+            Err(self.error("Expected `)`"))
+        }
+    }
+
+    fn resolve_rule(&self, name: &str, offset: usize) -> Result<(Rule, Vec<MatchId>), QueryError> {
+        let Some(rule) = self.grammar.find_rule(name) else {
+            return Err(QueryError {
+                message: format!("Unknown rule or token `{}`", name),
+                offset,
+            });
+        };
+
+        let ids = self
+            .grammar
+            .iter_matches()
+            .filter(|(_, m)| m.rule == rule)
+            .map(|(id, _)| id)
+            .collect();
+
+        Ok((rule, ids))
+    }
+
+    fn resolve_field(
+        &self,
+        name: &str,
+        offset: usize,
+    ) -> Result<HashMap<MatchId, usize>, QueryError> {
+        let indexes: HashMap<_, _> = self
+            .grammar
+            .iter_matches()
+            .filter_map(|(id, _)| Some((id, self.grammar.get_field_index(id, name)?)))
+            .collect();
+
+        if indexes.is_empty() {
+            return Err(QueryError {
+                message: format!("Unknown field `{}`", name),
+                offset,
+            });
+        }
+
+        Ok(indexes)
+    }
+}
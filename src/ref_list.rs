@@ -61,6 +61,12 @@ pub struct ERefList<'a, T> {
     list: Option<RefList<'a, T>>,
 }
 
+impl<T> Default for ERefList<'_, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<'a, T> ERefList<'a, T> {
     pub fn new() -> Self {
         Self { list: None }
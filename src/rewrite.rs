@@ -0,0 +1,388 @@
+//! Structural search and replace, using templates written in the grammar's own language.
+//!
+//! A template is parsed like any other input, except that it can contain holes (e.g. `$x`),
+//! which stand for a whole value. Holes are `Token::Hole` tokens, and are named in the order
+//! that they appear in. The template is parsed with a solver for `Grammar::with_holes`, so
+//! its values have the same matches as values parsed with the regular grammar.
+//!
+//! A pattern template matches a value when both have the same matches and tokens, with any
+//! value in place of each hole. A hole that appears more than once has to match equal values
+//! each time. A replacement template is then filled in with the values of the holes.
+
+use std::collections::HashMap;
+
+use crate::{
+    interpreter::{solve, ITokenOrGroup, RuleValue, Value},
+    matches::{MatchId, Rule, Token},
+    solver::GrammarSolver,
+    span::Span,
+};
+
+/// A parsed template.
+#[derive(Debug, Clone)]
+pub struct Template {
+    root: TemplateNode,
+    /// The distinct names of the holes in the template.
+    names: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+enum TemplateNode {
+    /// A hole, with the index of its name and the rule of the place it's in.
+    Hole {
+        name: usize,
+        rule: Rule,
+    },
+    Token(Token),
+    Rule {
+        rule: Rule,
+        match_id: MatchId,
+        children: Vec<TemplateNode>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateError {
+    pub message: String,
+}
+
+impl TemplateError {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// A value that a pattern matched, along with the values of its holes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateMatch<'t> {
+    pub value: &'t Value,
+    pub bindings: HashMap<String, &'t Value>,
+}
+
+impl Template {
+    /// Parse a template, where `names` are the names of the holes in order. The solver has
+    /// to be for a grammar with holes. The template has to be a whole input of the root
+    /// rule, and stands for the one value inside of the root (e.g. the `Expr` in
+    /// `S -> Start Expr Eof`).
+    pub fn new(
+        solver: &GrammarSolver,
+        tokens: Vec<ITokenOrGroup>,
+        names: &[&str],
+    ) -> Result<Self, TemplateError> {
+        let grammar = solver.grammar();
+        if !grammar.has_holes() {
+            return Err(TemplateError::new(
+                "Templates need a grammar from `Grammar::with_holes`",
+            ));
+        }
+
+        let result = solve(solver, tokens);
+        if let Some(diagnostic) = result.diagnostics.first() {
+            return Err(TemplateError::new(format!(
+                "Invalid template: {}",
+                diagnostic.display(grammar)
+            )));
+        }
+
+        let mut rules = result.value.values.iter().filter_map(|value| match value {
+            Value::Rule(rule) => Some(rule),
+            _ => None,
+        });
+
+        let (Some(body), None) = (rules.next(), rules.next()) else {
+            return Err(TemplateError::new("A template has to be a single value"));
+        };
+
+        let mut builder = TemplateBuilder {
+            solver,
+            names,
+            next_hole: 0,
+            distinct: Vec::new(),
+        };
+        let root = builder.build_rule(body)?;
+
+        if builder.next_hole != names.len() {
+            return Err(TemplateError::new(format!(
+                "The template has {} holes, but {} names were given",
+                builder.next_hole,
+                names.len()
+            )));
+        }
+
+        Ok(Self {
+            root,
+            names: builder.distinct,
+        })
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Find every value in the tree that the template matches, in preorder. Values inside
+    /// of a match are searched too.
+    pub fn find<'t>(&self, root: &'t RuleValue) -> Vec<TemplateMatch<'t>> {
+        let mut matches = Vec::new();
+        self.find_in(root, &mut matches);
+        matches
+    }
+
+    fn find_in<'t>(&self, node: &'t RuleValue, matches: &mut Vec<TemplateMatch<'t>>) {
+        for value in &node.values {
+            if let Some(found) = self.match_value(value) {
+                matches.push(found);
+            }
+
+            if let Value::Rule(rule) = value {
+                self.find_in(rule, matches);
+            }
+        }
+    }
+
+    /// Match the template against a single value.
+    pub fn match_value<'t>(&self, value: &'t Value) -> Option<TemplateMatch<'t>> {
+        let mut bindings = vec![None; self.names.len()];
+
+        // The rule of the outermost value depends on where it is, so only its match counts
+        if !match_node(&self.root, value, true, &mut bindings) {
+            return None;
+        }
+
+        let bindings = self
+            .names
+            .iter()
+            .cloned()
+            .zip(bindings)
+            .filter_map(|(name, value)| Some((name, value?)))
+            .collect();
+
+        Some(TemplateMatch { value, bindings })
+    }
+
+    /// Fill in the template, using the rule and span of the value that it replaces. Values
+    /// from the template get the span of the replaced value too.
+    fn instantiate(&self, replaced: &Value, bindings: &HashMap<String, Value>) -> Value {
+        let span = replaced.span();
+        let mut value = self.instantiate_node(&self.root, span, bindings);
+
+        if let (Value::Rule(value), Value::Rule(replaced)) = (&mut value, replaced) {
+            value.rule = replaced.rule;
+        }
+
+        value
+    }
+
+    fn instantiate_node(
+        &self,
+        node: &TemplateNode,
+        span: &Span,
+        bindings: &HashMap<String, Value>,
+    ) -> Value {
+        match node {
+            TemplateNode::Hole { name, rule } => {
+                let mut value = bindings[&self.names[*name]].clone();
+                if let Value::Rule(value) = &mut value {
+                    value.rule = *rule;
+                }
+
+                value
+            }
+            TemplateNode::Token(token) => Value::Token(*token, span.clone()),
+            TemplateNode::Rule {
+                rule,
+                match_id,
+                children,
+            } => Value::Rule(RuleValue {
+                rule: *rule,
+                match_id: *match_id,
+                values: children
+                    .iter()
+                    .map(|child| self.instantiate_node(child, span, bindings))
+                    .collect(),
+                is_error: false,
+                span: span.clone(),
+            }),
+        }
+    }
+}
+
+fn match_node<'t>(
+    node: &TemplateNode,
+    value: &'t Value,
+    is_root: bool,
+    bindings: &mut [Option<&'t Value>],
+) -> bool {
+    match (node, value) {
+        (TemplateNode::Hole { .. }, Value::Error(_)) => false,
+        (TemplateNode::Hole { name, .. }, _) => match bindings[*name] {
+            Some(bound) => same_structure(bound, value),
+            None => {
+                bindings[*name] = Some(value);
+                true
+            }
+        },
+        (TemplateNode::Token(expected), Value::Token(token, _)) => expected == token,
+        (
+            TemplateNode::Rule {
+                rule,
+                match_id,
+                children,
+            },
+            Value::Rule(value),
+        ) => {
+            (is_root || *rule == value.rule)
+                && *match_id == value.match_id
+                && children.len() == value.values.len()
+                && children
+                    .iter()
+                    .zip(&value.values)
+                    .all(|(child, value)| match_node(child, value, false, bindings))
+        }
+        _ => false,
+    }
+}
+
+/// Whether two values are the same, apart from where they are. The rule of a value depends
+/// on where it is, so only its match is compared.
+fn same_structure(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Token(a, _), Value::Token(b, _)) => a == b,
+        (Value::Rule(a), Value::Rule(b)) => {
+            a.match_id == b.match_id
+                && a.values.len() == b.values.len()
+                && a.values
+                    .iter()
+                    .zip(&b.values)
+                    .all(|(a, b)| same_structure(a, b))
+        }
+        (Value::Error(_), Value::Error(_)) => true,
+        _ => false,
+    }
+}
+
+struct TemplateBuilder<'a> {
+    solver: &'a GrammarSolver,
+    names: &'a [&'a str],
+    /// The number of holes seen so far.
+    next_hole: usize,
+    distinct: Vec<String>,
+}
+
+impl TemplateBuilder<'_> {
+    fn build_rule(&mut self, value: &RuleValue) -> Result<TemplateNode, TemplateError> {
+        if value.is_error {
+            return Err(TemplateError::new(
+                "Templates can't contain recovery matches",
+            ));
+        }
+
+        if self.solver.grammar().is_hole_match(value.match_id) {
+            let Some(name) = self.names.get(self.next_hole) else {
+                return Err(TemplateError::new("Not every hole was given a name"));
+            };
+            self.next_hole += 1;
+
+            let index = match self.distinct.iter().position(|n| n == name) {
+                Some(index) => index,
+                None => {
+                    self.distinct.push(name.to_string());
+                    self.distinct.len() - 1
+                }
+            };
+
+            return Ok(TemplateNode::Hole {
+                name: index,
+                rule: value.rule,
+            });
+        }
+
+        let children = value
+            .values
+            .iter()
+            .map(|child| match child {
+                Value::Token(token, _) => Ok(TemplateNode::Token(*token)),
+                Value::Rule(rule) => self.build_rule(rule),
+                Value::Error(_) => Err(TemplateError::new("Templates can't contain errors")),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(TemplateNode::Rule {
+            rule: value.rule,
+            match_id: value.match_id,
+            children,
+        })
+    }
+}
+
+/// Rewrites every value that matches a pattern template into a replacement template.
+#[derive(Debug, Clone)]
+pub struct SearchReplace {
+    pattern: Template,
+    replacement: Template,
+}
+
+impl SearchReplace {
+    /// Every hole in the replacement has to be in the pattern too.
+    pub fn new(pattern: Template, replacement: Template) -> Result<Self, TemplateError> {
+        if let Some(name) = replacement
+            .names
+            .iter()
+            .find(|name| !pattern.names.contains(name))
+        {
+            return Err(TemplateError::new(format!(
+                "The hole `${}` isn't in the pattern",
+                name
+            )));
+        }
+
+        Ok(Self {
+            pattern,
+            replacement,
+        })
+    }
+
+    pub fn pattern(&self) -> &Template {
+        &self.pattern
+    }
+
+    /// Rewrite the values inside of the root. The values of holes are rewritten before
+    /// they're put into the replacement, so nested matches are rewritten too.
+    pub fn rewrite(&self, root: &RuleValue) -> RuleValue {
+        RuleValue {
+            rule: root.rule,
+            match_id: root.match_id,
+            values: root
+                .values
+                .iter()
+                .map(|value| self.rewrite_value(value))
+                .collect(),
+            is_error: root.is_error,
+            span: root.span.clone(),
+        }
+    }
+
+    fn rewrite_value(&self, value: &Value) -> Value {
+        if let Some(found) = self.pattern.match_value(value) {
+            let bindings = found
+                .bindings
+                .into_iter()
+                .map(|(name, value)| (name, self.rewrite_value(value)))
+                .collect();
+
+            return self.replacement.instantiate(value, &bindings);
+        }
+
+        match value {
+            Value::Rule(rule) => Value::Rule(self.rewrite(rule)),
+            _ => value.clone(),
+        }
+    }
+}
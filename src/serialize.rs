@@ -0,0 +1,72 @@
+//! Machine readable dumps of parse trees, as S-expressions and JSON, along with readers
+//! that load them back into rule values.
+//!
+//! Rules and tokens are written with the names that they have in code, and matches with
+//! their index in the grammar. Reading a dump needs the grammar that it was made with, to
+//! turn those back into rules, tokens and match ids.
+
+use std::ops::Range;
+
+use crate::{
+    matches::{Grammar, MatchId, Rule, Token},
+    span::Span,
+};
+
+pub mod json;
+pub mod sexpr;
+
+/// A problem with a dump that's being read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadError {
+    pub message: String,
+    /// The byte offset of the error within the dump.
+    pub offset: usize,
+}
+
+impl ReadError {
+    fn new(message: impl Into<String>, offset: usize) -> Self {
+        Self {
+            message: message.into(),
+            offset,
+        }
+    }
+}
+
+impl std::fmt::Display for ReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+fn read_rule(grammar: &Grammar, name: &str, offset: usize) -> Result<Rule, ReadError> {
+    grammar
+        .find_rule(name)
+        .ok_or_else(|| ReadError::new(format!("Unknown rule `{}`", name), offset))
+}
+
+fn read_token(grammar: &Grammar, name: &str, offset: usize) -> Result<Token, ReadError> {
+    grammar
+        .find_token(name)
+        .ok_or_else(|| ReadError::new(format!("Unknown token `{}`", name), offset))
+}
+
+fn read_match_id(grammar: &Grammar, index: usize, offset: usize) -> Result<MatchId, ReadError> {
+    grammar
+        .get_match_id(index)
+        .ok_or_else(|| ReadError::new(format!("Unknown match {}", index), offset))
+}
+
+fn check_range(range: Range<usize>, offset: usize) -> Result<Range<usize>, ReadError> {
+    if range.start <= range.end {
+        Ok(range)
+    } else {
+        Err(ReadError::new("Ranges can't end before they start", offset))
+    }
+}
+
+fn check_span(span: Span, offset: usize) -> Result<Span, ReadError> {
+    Ok(Span {
+        tokens: check_range(span.tokens, offset)?,
+        bytes: span.bytes.map(|b| check_range(b, offset)).transpose()?,
+    })
+}
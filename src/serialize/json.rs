@@ -0,0 +1,561 @@
+//! Trees and parse results as JSON. Each value is an object with a `kind` of `rule`, `token`
+//! or `error`, e.g.
+//!
+//! ```text
+//! {
+//!   "kind": "rule",
+//!   "rule": "Add",
+//!   "match": 6,
+//!   "recovered": false,
+//!   "span": {"tokens": [1, 2], "bytes": [2, 3]},
+//!   "values": [{"kind": "token", "token": "Num", "span": {"tokens": [1, 2]}}]
+//! }
+//! ```
+//!
+//! A parse result is an object with the `value`, its `diagnostics` and the `error_counts`.
+//! This doesn't need any dependencies, but values can also be serialized with serde by
+//! enabling the `serde` feature.
+
+use crate::{
+    diagnostics::{Diagnostic, DiagnosticKind, ErrorCounts, Found},
+    interpreter::{ParseResult, RuleValue, Value},
+    matches::{Grammar, Symbol},
+    span::Span,
+};
+
+use super::{check_span, read_match_id, read_rule, read_token, ReadError};
+
+/// Lines that are longer than this are split up, with one entry on each line.
+const MAX_WIDTH: usize = 80;
+
+pub fn to_json(value: &RuleValue) -> String {
+    let mut out = String::new();
+    write_json(&mut out, &rule_json(value), 0);
+    out.push('\n');
+    out
+}
+
+/// Write a whole parse result, with messages for the diagnostics from the grammar.
+pub fn result_to_json(result: &ParseResult, grammar: &Grammar) -> String {
+    let json = Json::Object(vec![
+        ("value".into(), rule_json(&result.value)),
+        (
+            "diagnostics".into(),
+            Json::Array(
+                result
+                    .diagnostics
+                    .iter()
+                    .map(|diagnostic| diagnostic_json(diagnostic, grammar))
+                    .collect(),
+            ),
+        ),
+        ("error_counts".into(), counts_json(&result.error_counts)),
+    ]);
+
+    let mut out = String::new();
+    write_json(&mut out, &json, 0);
+    out.push('\n');
+    out
+}
+
+/// Read a tree written by `to_json`. The `value` of a parse result from `result_to_json`
+/// can be read too.
+pub fn read_json(grammar: &Grammar, source: &str) -> Result<RuleValue, ReadError> {
+    let mut parser = Parser { source, offset: 0 };
+    let json = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.offset < source.len() {
+        return Err(ReadError::new(
+            "Expected the end of the input",
+            parser.offset,
+        ));
+    }
+
+    let reader = Reader { grammar };
+    match json.field("value") {
+        Some(value) if json.field("kind").is_none() => reader.read_rule(value),
+        _ => reader.read_rule(&json),
+    }
+}
+
+/// A parsed JSON value, along with its offset in the source.
+struct Node {
+    offset: usize,
+    json: ParsedJson,
+}
+
+/// A value to write.
+enum Json {
+    Null,
+    Bool(bool),
+    Number(usize),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+enum ParsedJson {
+    Null,
+    Bool(bool),
+    Number(usize),
+    String(String),
+    Array(Vec<Node>),
+    Object(Vec<(String, Node)>),
+}
+
+fn rule_json(value: &RuleValue) -> Json {
+    Json::Object(vec![
+        ("kind".into(), Json::String("rule".into())),
+        ("rule".into(), Json::String(format!("{:?}", value.rule))),
+        ("match".into(), Json::Number(value.match_id.index())),
+        ("recovered".into(), Json::Bool(value.is_error)),
+        ("span".into(), span_json(&value.span)),
+        (
+            "values".into(),
+            Json::Array(value.values.iter().map(value_json).collect()),
+        ),
+    ])
+}
+
+fn value_json(value: &Value) -> Json {
+    match value {
+        Value::Token(token, span) => Json::Object(vec![
+            ("kind".into(), Json::String("token".into())),
+            ("token".into(), Json::String(format!("{:?}", token))),
+            ("span".into(), span_json(span)),
+        ]),
+        Value::Rule(rule) => rule_json(rule),
+        Value::Error(span) => Json::Object(vec![
+            ("kind".into(), Json::String("error".into())),
+            ("span".into(), span_json(span)),
+        ]),
+    }
+}
+
+fn span_json(span: &Span) -> Json {
+    let range = |start, end| Json::Array(vec![Json::Number(start), Json::Number(end)]);
+
+    let mut fields = vec![("tokens".into(), range(span.tokens.start, span.tokens.end))];
+    if let Some(bytes) = &span.bytes {
+        fields.push(("bytes".into(), range(bytes.start, bytes.end)));
+    }
+
+    Json::Object(fields)
+}
+
+fn diagnostic_json(diagnostic: &Diagnostic, grammar: &Grammar) -> Json {
+    let mut fields = match &diagnostic.kind {
+        DiagnosticKind::RecoveryMatch { match_id, message } => vec![
+            ("kind".into(), Json::String("recovery_match".into())),
+            ("match".into(), Json::Number(match_id.index())),
+            ("recovery_message".into(), Json::String(message.clone())),
+        ],
+        DiagnosticKind::Unexpected {
+            expected,
+            found,
+            context,
+        } => vec![
+            ("kind".into(), Json::String("unexpected".into())),
+            (
+                "expected".into(),
+                Json::Array(expected.iter().map(symbol_json).collect()),
+            ),
+            ("found".into(), found_json(*found)),
+            (
+                "context".into(),
+                match context {
+                    Some(context) => Json::String(context.clone()),
+                    None => Json::Null,
+                },
+            ),
+        ],
+        DiagnosticKind::Trailing { found } => vec![
+            ("kind".into(), Json::String("trailing".into())),
+            ("found".into(), found_json(*found)),
+        ],
+    };
+
+    fields.push(("token_index".into(), Json::Number(diagnostic.token_index)));
+    fields.push((
+        "message".into(),
+        Json::String(diagnostic.display(grammar).to_string()),
+    ));
+    Json::Object(fields)
+}
+
+fn found_json(found: Found) -> Json {
+    match found {
+        Found::Token(token) => Json::String(format!("{:?}", token)),
+        Found::Group => Json::String("group".into()),
+        Found::End => Json::String("end".into()),
+    }
+}
+
+fn symbol_json(symbol: &Symbol) -> Json {
+    let (kind, name) = match symbol {
+        Symbol::Rule(rule) => ("rule", format!("{:?}", rule)),
+        Symbol::Token(token) => ("token", format!("{:?}", token)),
+        Symbol::Group(group) => ("group", format!("{:?}", group)),
+    };
+
+    Json::Object(vec![
+        ("kind".into(), Json::String(kind.into())),
+        ("name".into(), Json::String(name)),
+    ])
+}
+
+fn counts_json(counts: &ErrorCounts) -> Json {
+    Json::Object(vec![
+        ("reported".into(), Json::Number(counts.reported)),
+        ("suppressed".into(), Json::Number(counts.suppressed)),
+        ("over_limit".into(), Json::Number(counts.over_limit)),
+    ])
+}
+
+/// Write a value on one line if it fits, or with one entry on each line otherwise.
+fn write_json(out: &mut String, json: &Json, indent: usize) {
+    let mut inline = String::new();
+    write_inline(&mut inline, json);
+    if indent + inline.len() <= MAX_WIDTH {
+        out.push_str(&inline);
+        return;
+    }
+
+    let (open, close, len) = match json {
+        Json::Array(items) => ('[', ']', items.len()),
+        Json::Object(fields) => ('{', '}', fields.len()),
+        _ => {
+            out.push_str(&inline);
+            return;
+        }
+    };
+
+    out.push(open);
+    for i in 0..len {
+        out.push('\n');
+        out.push_str(&" ".repeat(indent + 2));
+        match json {
+            Json::Array(items) => write_json(out, &items[i], indent + 2),
+            Json::Object(fields) => {
+                let (key, value) = &fields[i];
+                write_string(out, key);
+                out.push_str(": ");
+                write_json(out, value, indent + 2);
+            }
+            _ => unreachable!(),
+        }
+
+        if i + 1 < len {
+            out.push(',');
+        }
+    }
+
+    out.push('\n');
+    out.push_str(&" ".repeat(indent));
+    out.push(close);
+}
+
+fn write_inline(out: &mut String, json: &Json) {
+    match json {
+        Json::Null => out.push_str("null"),
+        Json::Bool(value) => out.push_str(if *value { "true" } else { "false" }),
+        Json::Number(value) => out.push_str(&value.to_string()),
+        Json::String(value) => write_string(out, value),
+        Json::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_inline(out, item);
+            }
+            out.push(']');
+        }
+        Json::Object(fields) => {
+            out.push('{');
+            for (i, (key, value)) in fields.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_string(out, key);
+                out.push_str(": ");
+                write_inline(out, value);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn write_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// A JSON parser for the subset that dumps use. Numbers have to be whole and positive.
+struct Parser<'a> {
+    source: &'a str,
+    offset: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.source[self.offset..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.offset += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, text: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(text) {
+            self.offset += text.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), ReadError> {
+        if self.eat(text) {
+            Ok(())
+        } else {
+            Err(ReadError::new(format!("Expected `{}`", text), self.offset))
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Node, ReadError> {
+        self.skip_whitespace();
+        let offset = self.offset;
+
+        let json = match self.rest().chars().next() {
+            Some('{') => {
+                self.offset += 1;
+                let mut fields = Vec::new();
+                if !self.eat("}") {
+                    loop {
+                        self.skip_whitespace();
+                        let key = self.parse_string()?;
+                        self.expect(":")?;
+                        fields.push((key, self.parse_value()?));
+                        if self.eat("}") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                ParsedJson::Object(fields)
+            }
+            Some('[') => {
+                self.offset += 1;
+                let mut items = Vec::new();
+                if !self.eat("]") {
+                    loop {
+                        items.push(self.parse_value()?);
+                        if self.eat("]") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                ParsedJson::Array(items)
+            }
+            Some('"') => ParsedJson::String(self.parse_string()?),
+            Some(c) if c.is_ascii_digit() => {
+                let len = self
+                    .rest()
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(self.rest().len());
+                let number = self.rest()[..len]
+                    .parse()
+                    .map_err(|_| ReadError::new("Number is too large", offset))?;
+                self.offset += len;
+                ParsedJson::Number(number)
+            }
+            _ if self.eat("null") => ParsedJson::Null,
+            _ if self.eat("true") => ParsedJson::Bool(true),
+            _ if self.eat("false") => ParsedJson::Bool(false),
+            _ => return Err(ReadError::new("Expected a value", offset)),
+        };
+
+        Ok(Node { offset, json })
+    }
+
+    fn parse_string(&mut self) -> Result<String, ReadError> {
+        if !self.rest().starts_with('"') {
+            return Err(ReadError::new("Expected a string", self.offset));
+        }
+        self.offset += 1;
+
+        let mut value = String::new();
+        loop {
+            let mut chars = self.source[self.offset..].chars();
+            let Some(c) = chars.next() else {
+                return Err(ReadError::new("Unterminated string", self.offset));
+            };
+
+            let escape_offset = self.offset;
+            self.offset += c.len_utf8();
+            match c {
+                '"' => return Ok(value),
+                '\\' => {
+                    let escaped = match chars.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => {
+                            let code = chars
+                                .as_str()
+                                .get(..4)
+                                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| {
+                                    ReadError::new("Invalid unicode escape", escape_offset)
+                                })?;
+                            self.offset += 4;
+                            code
+                        }
+                        _ => return Err(ReadError::new("Invalid escape", escape_offset)),
+                    };
+                    self.offset += 1;
+                    value.push(escaped);
+                }
+                c => value.push(c),
+            }
+        }
+    }
+}
+
+impl Node {
+    fn field(&self, name: &str) -> Option<&Node> {
+        match &self.json {
+            ParsedJson::Object(fields) => fields
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn expect_field(&self, name: &str) -> Result<&Node, ReadError> {
+        match &self.json {
+            ParsedJson::Object(_) => self.field(name).ok_or_else(|| {
+                ReadError::new(format!("Missing the field `{}`", name), self.offset)
+            }),
+            _ => Err(ReadError::new("Expected an object", self.offset)),
+        }
+    }
+
+    fn as_str(&self) -> Result<&str, ReadError> {
+        match &self.json {
+            ParsedJson::String(value) => Ok(value),
+            _ => Err(ReadError::new("Expected a string", self.offset)),
+        }
+    }
+
+    fn as_number(&self) -> Result<usize, ReadError> {
+        match &self.json {
+            ParsedJson::Number(value) => Ok(*value),
+            _ => Err(ReadError::new("Expected a number", self.offset)),
+        }
+    }
+
+    fn as_bool(&self) -> Result<bool, ReadError> {
+        match &self.json {
+            ParsedJson::Bool(value) => Ok(*value),
+            _ => Err(ReadError::new("Expected `true` or `false`", self.offset)),
+        }
+    }
+
+    fn as_array(&self) -> Result<&[Node], ReadError> {
+        match &self.json {
+            ParsedJson::Array(items) => Ok(items),
+            _ => Err(ReadError::new("Expected an array", self.offset)),
+        }
+    }
+}
+
+struct Reader<'a> {
+    grammar: &'a Grammar,
+}
+
+impl Reader<'_> {
+    fn read_value(&self, node: &Node) -> Result<Value, ReadError> {
+        let kind = node.expect_field("kind")?;
+        match kind.as_str()? {
+            "rule" => Ok(Value::Rule(self.read_rule(node)?)),
+            "token" => {
+                let token = node.expect_field("token")?;
+                Ok(Value::Token(
+                    read_token(self.grammar, token.as_str()?, token.offset)?,
+                    read_span(node.expect_field("span")?)?,
+                ))
+            }
+            "error" => Ok(Value::Error(read_span(node.expect_field("span")?)?)),
+            _ => Err(ReadError::new(
+                "Expected a kind of `rule`, `token` or `error`",
+                kind.offset,
+            )),
+        }
+    }
+
+    fn read_rule(&self, node: &Node) -> Result<RuleValue, ReadError> {
+        let kind = node.expect_field("kind")?;
+        if kind.as_str()? != "rule" {
+            return Err(ReadError::new("Expected a rule", kind.offset));
+        }
+
+        let rule = node.expect_field("rule")?;
+        let match_id = node.expect_field("match")?;
+
+        Ok(RuleValue {
+            rule: read_rule(self.grammar, rule.as_str()?, rule.offset)?,
+            match_id: read_match_id(self.grammar, match_id.as_number()?, match_id.offset)?,
+            values: node
+                .expect_field("values")?
+                .as_array()?
+                .iter()
+                .map(|value| self.read_value(value))
+                .collect::<Result<_, _>>()?,
+            is_error: match node.field("recovered") {
+                Some(recovered) => recovered.as_bool()?,
+                None => false,
+            },
+            span: read_span(node.expect_field("span")?)?,
+        })
+    }
+}
+
+fn read_span(node: &Node) -> Result<Span, ReadError> {
+    let read_range = |node: &Node| match node.as_array()? {
+        [start, end] => Ok(start.as_number()?..end.as_number()?),
+        _ => Err(ReadError::new(
+            "Expected a range, such as `[0, 5]`",
+            node.offset,
+        )),
+    };
+
+    let span = Span {
+        tokens: read_range(node.expect_field("tokens")?)?,
+        bytes: node.field("bytes").map(read_range).transpose()?,
+    };
+
+    check_span(span, node.offset)
+}
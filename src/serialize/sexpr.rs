@@ -0,0 +1,215 @@
+//! Trees as S-expressions, e.g.
+//!
+//! ```text
+//! (S #0 0..5
+//!   Start@0..1
+//!   (Expr #2 1..4
+//!     (Add #6 1..2
+//!       Num@1..2)
+//!     Plus@2..3
+//!     ERROR@3..4)
+//!   Eof@4..5)
+//! ```
+//!
+//! Each rule value has its match after the `#`, then its span, then `:recovered` if it was
+//! parsed by a recovery match, and then its values. Spans are written as `0..5`, or as
+//! `0..5/0..9` when they have a byte range too.
+
+use std::fmt::Write;
+
+use crate::{
+    interpreter::{RuleValue, Value},
+    matches::Grammar,
+    span::Span,
+};
+
+use super::{check_span, read_match_id, read_rule, read_token, ReadError};
+
+pub fn to_sexpr(value: &RuleValue) -> String {
+    let mut out = String::new();
+    write_rule(&mut out, value, 0);
+    out.push('\n');
+    out
+}
+
+fn write_rule(out: &mut String, value: &RuleValue, depth: usize) {
+    write!(out, "({:?} #{} ", value.rule, value.match_id.index()).unwrap();
+    write_span(out, &value.span);
+    if value.is_error {
+        out.push_str(" :recovered");
+    }
+
+    for child in &value.values {
+        out.push('\n');
+        out.push_str(&"  ".repeat(depth + 1));
+        match child {
+            Value::Token(token, span) => {
+                write!(out, "{:?}@", token).unwrap();
+                write_span(out, span);
+            }
+            Value::Rule(rule) => write_rule(out, rule, depth + 1),
+            Value::Error(span) => {
+                out.push_str("ERROR@");
+                write_span(out, span);
+            }
+        }
+    }
+
+    out.push(')');
+}
+
+fn write_span(out: &mut String, span: &Span) {
+    write!(out, "{}..{}", span.tokens.start, span.tokens.end).unwrap();
+    if let Some(bytes) = &span.bytes {
+        write!(out, "/{}..{}", bytes.start, bytes.end).unwrap();
+    }
+}
+
+/// Read a tree written by `to_sexpr`.
+pub fn read_sexpr(grammar: &Grammar, source: &str) -> Result<RuleValue, ReadError> {
+    let mut reader = Reader {
+        grammar,
+        source,
+        offset: 0,
+    };
+
+    let value = match reader.next_item()? {
+        Item::Open => reader.read_rule()?,
+        _ => return Err(ReadError::new("Expected a rule", reader.offset)),
+    };
+
+    match reader.next_item()? {
+        Item::End => Ok(value),
+        _ => Err(ReadError::new(
+            "Expected the end of the input",
+            reader.offset,
+        )),
+    }
+}
+
+enum Item<'a> {
+    Open,
+    Close,
+    Atom(&'a str, usize),
+    End,
+}
+
+struct Reader<'a> {
+    grammar: &'a Grammar,
+    source: &'a str,
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn next_item(&mut self) -> Result<Item<'a>, ReadError> {
+        let rest = &self.source[self.offset..];
+        self.offset += rest.len() - rest.trim_start().len();
+
+        let rest = &self.source[self.offset..];
+        match rest.chars().next() {
+            None => Ok(Item::End),
+            Some('(') => {
+                self.offset += 1;
+                Ok(Item::Open)
+            }
+            Some(')') => {
+                self.offset += 1;
+                Ok(Item::Close)
+            }
+            Some(_) => {
+                let len = rest
+                    .find(|c: char| c.is_whitespace() || c == '(' || c == ')')
+                    .unwrap_or(rest.len());
+                let start = self.offset;
+                self.offset += len;
+                Ok(Item::Atom(&rest[..len], start))
+            }
+        }
+    }
+
+    fn next_atom(&mut self, expected: &str) -> Result<(&'a str, usize), ReadError> {
+        match self.next_item()? {
+            Item::Atom(atom, offset) => Ok((atom, offset)),
+            _ => Err(ReadError::new(
+                format!("Expected {}", expected),
+                self.offset,
+            )),
+        }
+    }
+
+    /// Read a rule value, after its `(`.
+    fn read_rule(&mut self) -> Result<RuleValue, ReadError> {
+        let (name, offset) = self.next_atom("a rule")?;
+        let rule = read_rule(self.grammar, name, offset)?;
+
+        let (id, offset) = self.next_atom("a match")?;
+        let index = id
+            .strip_prefix('#')
+            .and_then(|index| index.parse().ok())
+            .ok_or_else(|| ReadError::new("Expected a match, such as `#2`", offset))?;
+        let match_id = read_match_id(self.grammar, index, offset)?;
+
+        let (span, offset) = self.next_atom("a span")?;
+        let span = read_span(span, offset)?;
+
+        let mut is_error = false;
+        let mut values = Vec::new();
+        loop {
+            match self.next_item()? {
+                Item::Close => break,
+                Item::Open => values.push(Value::Rule(self.read_rule()?)),
+                Item::Atom(":recovered", _) if values.is_empty() => is_error = true,
+                Item::Atom(atom, offset) => values.push(self.read_leaf(atom, offset)?),
+                Item::End => return Err(ReadError::new("Expected `)`", self.offset)),
+            }
+        }
+
+        Ok(RuleValue {
+            rule,
+            match_id,
+            values,
+            is_error,
+            span,
+        })
+    }
+
+    fn read_leaf(&self, atom: &str, offset: usize) -> Result<Value, ReadError> {
+        let Some((name, span)) = atom.split_once('@') else {
+            return Err(ReadError::new(
+                "Expected a token, such as `Num@0..1`",
+                offset,
+            ));
+        };
+
+        let span = read_span(span, offset + name.len() + 1)?;
+        match name {
+            "ERROR" => Ok(Value::Error(span)),
+            _ => Ok(Value::Token(read_token(self.grammar, name, offset)?, span)),
+        }
+    }
+}
+
+fn read_span(text: &str, offset: usize) -> Result<Span, ReadError> {
+    let read_range = |text: &str| {
+        let (start, end) = text.split_once("..")?;
+        Some(start.parse().ok()?..end.parse().ok()?)
+    };
+
+    let (tokens, bytes) = match text.split_once('/') {
+        Some((tokens, bytes)) => (tokens, Some(bytes)),
+        None => (text, None),
+    };
+
+    let span = (|| {
+        Some(Span {
+            tokens: read_range(tokens)?,
+            bytes: match bytes {
+                Some(bytes) => Some(read_range(bytes)?),
+                None => None,
+            },
+        })
+    })()
+    .ok_or_else(|| ReadError::new("Expected a span, such as `0..5`", offset))?;
+
+    check_span(span, offset)
+}
@@ -1,26 +1,32 @@
-use crate::matches::{Grammar, Match, MatchId, Rule};
+use crate::matches::{Grammar, Match, MatchId, Rule, Symbol};
 
 use self::{
-    empty_rules::EmptyRuleSolver, first_sets::FirstSets, follow_sets::FollowSets,
-    seal_rules::SealRules, wrap_sets::WrapSets,
+    explain::Explanation, first_sets::FirstSets, follow_sets::FollowSets, seal_rules::SealRules,
+    wrap_sets::WrapSets,
 };
 
+mod conflicts;
 mod empty_rules;
+mod explain;
 mod first_sets;
 mod follow_sets;
+mod hole_sets;
 mod path;
 mod seal_rules;
 mod structure;
 mod token_sets;
 mod wrap_sets;
 
+pub use conflicts::{ConflictId, ConflictReport, FollowOverlapWith};
+pub use empty_rules::EmptyRuleSolver;
 pub use first_sets::FirstSet;
 pub use follow_sets::FollowSet;
+pub use hole_sets::HoleSets;
 pub use path::MatchIndex;
 pub use seal_rules::SealAction;
 pub use structure::EmptySolverRuleValue;
 pub use token_sets::TokenOrGroup;
-pub use wrap_sets::{EmptyWrapAction, InsertAction, WrapAction, WrapContext, WrapData};
+pub use wrap_sets::{EmptyWrapAction, WrapContext, WrapData};
 
 pub struct GrammarSolver {
     grammar: Grammar,
@@ -28,6 +34,12 @@ pub struct GrammarSolver {
     follow_sets: FollowSets,
     wrap_sets: WrapSets,
     seal_rules: SealRules,
+    /// The solver for the grammar with recovery matches included, if it has any.
+    recovery: Option<Box<GrammarSolver>>,
+    /// The tables for picking the rule of a hole, if the grammar has holes.
+    hole_sets: Option<HoleSets>,
+    /// The most tokens that any first or follow set needs to look ahead.
+    max_lookahead: usize,
 }
 
 impl GrammarSolver {
@@ -38,24 +50,58 @@ impl GrammarSolver {
         let wrap_sets = WrapSets::new(&grammar, &empty_rules, &first_sets);
         let seal_rules = SealRules::new(&grammar, &empty_rules);
 
+        // Recovery matches get their own set of tables, so they don't affect the regular ones.
+        let recovery = if grammar.has_recovery_matches() && !grammar.includes_recovery_matches() {
+            Some(Box::new(GrammarSolver::new(
+                grammar.with_recovery_matches(),
+            )))
+        } else {
+            None
+        };
+
+        let mut max_lookahead = get_max_lookahead(&first_sets, &follow_sets, recovery.as_deref());
+
+        // Holes look at the token after them to pick the rule that they stand for
+        let hole_sets = grammar
+            .has_holes()
+            .then(|| HoleSets::new(&grammar, &empty_rules));
+        if hole_sets.is_some() {
+            max_lookahead = max_lookahead.max(2);
+        }
+
         Self {
             grammar,
             first_sets,
             follow_sets,
             wrap_sets,
             seal_rules,
+            recovery,
+            hole_sets,
+            max_lookahead,
         }
     }
 
     pub fn first_set_for_rule(&self, rule: Rule) -> &[FirstSet] {
-        &self
-            .first_sets
+        self.first_sets
             .first_sets_per_rule
             .get(&rule)
             .map(|s| s.as_slice())
             .unwrap_or(&[])
     }
 
+    /// The tokens and groups that a rule can start with, sorted so that they don't depend on
+    /// the order of the first sets.
+    pub fn first_symbols_for_rule(&self, rule: Rule) -> Vec<Symbol> {
+        let mut symbols: Vec<Symbol> = self
+            .first_set_for_rule(rule)
+            .iter()
+            .filter_map(|set| set.tokens.first().map(|first| first.as_symbol()))
+            .collect();
+        symbols.sort();
+        symbols.dedup();
+        symbols
+    }
+
     pub fn follow_set_for_match(&self, mi: MatchIndex) -> &[FollowSet] {
         self.follow_sets
             .sets
@@ -80,7 +126,70 @@ impl GrammarSolver {
         self.seal_rules.rules.get(&id)
     }
 
+    pub fn grammar(&self) -> &Grammar {
+        &self.grammar
+    }
+
+    /// The solver to use while recovering from an error, if the grammar has recovery matches.
+    pub fn recovery_solver(&self) -> Option<&GrammarSolver> {
+        self.recovery.as_deref()
+    }
+
+    /// The tables for picking the rule that a hole stands for, if the grammar has holes.
+    pub fn hole_sets(&self) -> Option<&HoleSets> {
+        self.hole_sets.as_ref()
+    }
+
+    /// The number of upcoming tokens the interpreter needs to see at any point. This is
+    /// always at least 1.
+    pub fn max_lookahead(&self) -> usize {
+        self.max_lookahead
+    }
+
     pub fn get_wrap_data(&self, parent: Rule, child: Rule) -> Option<&WrapData> {
         self.wrap_sets.sets.get(&WrapContext { parent, child })
     }
+
+    /// Every choice that the tables leave to the interpreter, naming the rules and matches
+    /// involved. Recovery matches aren't included, since they're meant to overlap.
+    pub fn conflicts(&self) -> ConflictReport {
+        ConflictReport::new(
+            &self.grammar,
+            &self.first_sets,
+            &self.follow_sets,
+            &self.wrap_sets,
+        )
+    }
+
+    /// A readable dump of every table, with the names of the rules and matches, followed by
+    /// the conflicts and the tables for recovery. Use `to_string` for the text, e.g. to write
+    /// it to a file.
+    pub fn explain(&self) -> Explanation<'_> {
+        Explanation::new(self)
+    }
+}
+
+fn get_max_lookahead(
+    first_sets: &FirstSets,
+    follow_sets: &FollowSets,
+    recovery: Option<&GrammarSolver>,
+) -> usize {
+    let first = first_sets
+        .first_sets_per_rule
+        .values()
+        .flatten()
+        .map(|set| set.tokens.len());
+
+    let follow = follow_sets
+        .sets
+        .values()
+        .flatten()
+        .filter_map(|set| match set {
+            FollowSet::Direct(direct) => Some(direct.tokens.len()),
+            FollowSet::Enter(_) => None,
+        });
+
+    let recovery = recovery.map(|r| r.max_lookahead);
+
+    first.chain(follow).chain(recovery).fold(1, usize::max)
 }
@@ -0,0 +1,305 @@
+use crate::matches::{Grammar, MatchId, Term};
+
+use super::{
+    first_sets::{FirstSetOverlap, FirstSets, OverlapKind},
+    follow_sets::{FollowSet, FollowSets},
+    path::MatchIndex,
+    token_sets::{token_sets_overlap, TokenOrGroup, TokenSetDisplay},
+    wrap_sets::{CompetingActions, WrapContext, WrapSets},
+};
+
+/// What a wrap action's follow set overlaps with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FollowOverlapWith {
+    /// A later wrap action of the same context, into this match index.
+    Wrap(MatchIndex),
+    /// Inserting the child into a match of the parent, where this match index follows it.
+    Insert(MatchIndex),
+}
+
+/// A wrap action whose follow set can match the same tokens as something that's tried after
+/// it. The interpreter tries the wrap actions of a context in order and inserting last, so the
+/// other one is never taken on those tokens.
+#[derive(Debug, Clone)]
+pub struct FollowOverlap {
+    pub context: WrapContext,
+    /// The match index that the wrap action that's tried first wraps into.
+    pub taken: MatchIndex,
+    pub other: FollowOverlapWith,
+    /// Tokens that both can start with.
+    pub tokens: Vec<TokenOrGroup>,
+}
+
+/// A conflict of a report, by its kind and its index in the list of that kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictId {
+    FirstSet(usize),
+    Competing(usize),
+    FollowSet(usize),
+}
+
+/// Every place where the tables of the solver leave the interpreter a choice, which it makes
+/// without telling anyone: first sets that match the same tokens, wraps and inserts that had
+/// more than one candidate, and wrap actions whose follow sets overlap.
+#[derive(Debug, Clone, Default)]
+pub struct ConflictReport {
+    pub first_sets: Vec<FirstSetOverlap>,
+    pub competing: Vec<CompetingActions>,
+    pub follow_sets: Vec<FollowOverlap>,
+}
+
+impl ConflictReport {
+    pub fn new(
+        grammar: &Grammar,
+        first_sets: &FirstSets,
+        follow_sets: &FollowSets,
+        wrap_sets: &WrapSets,
+    ) -> Self {
+        let mut contexts: Vec<_> = wrap_sets.sets.iter().collect();
+        contexts.sort_by_key(|(ctx, _)| (ctx.parent, ctx.child));
+
+        let starts = |mi: MatchIndex| follow_tokens(first_sets, follow_sets, mi);
+
+        let mut follow_overlaps = Vec::new();
+        for (ctx, data) in contexts {
+            let wraps: Vec<_> = data.wrap_actions.iter().map(|a| a.if_matches).collect();
+
+            for (i, &taken) in wraps.iter().enumerate() {
+                let taken_starts = starts(taken);
+
+                let mut others: Vec<_> = wraps[i + 1..]
+                    .iter()
+                    .map(|&mi| FollowOverlapWith::Wrap(mi))
+                    .collect();
+                if data.insert_action.is_some() {
+                    others.extend(parent_follows(grammar, *ctx).map(FollowOverlapWith::Insert));
+                }
+
+                for other in others {
+                    // Inserting into a match at the index that the wrap leads to gives the
+                    // same tree as wrapping
+                    let (FollowOverlapWith::Wrap(mi) | FollowOverlapWith::Insert(mi)) = other;
+                    if mi == taken {
+                        continue;
+                    }
+
+                    if let Some(tokens) = first_overlap(&taken_starts, &starts(mi)) {
+                        follow_overlaps.push(FollowOverlap {
+                            context: *ctx,
+                            taken,
+                            other,
+                            tokens,
+                        });
+                    }
+                }
+            }
+        }
+
+        Self {
+            first_sets: first_sets.overlaps.clone(),
+            competing: wrap_sets.competing.clone(),
+            follow_sets: follow_overlaps,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.first_sets.len() + self.competing.len() + self.follow_sets.len()
+    }
+
+    /// Every conflict in the report, in the order that they're displayed.
+    pub fn ids(&self) -> impl Iterator<Item = ConflictId> {
+        let first_sets = (0..self.first_sets.len()).map(ConflictId::FirstSet);
+        let competing = (0..self.competing.len()).map(ConflictId::Competing);
+        let follow_sets = (0..self.follow_sets.len()).map(ConflictId::FollowSet);
+        first_sets.chain(competing).chain(follow_sets)
+    }
+
+    /// Display one conflict of the report, on a single line.
+    pub fn display_conflict<'a>(
+        &'a self,
+        id: ConflictId,
+        grammar: &'a Grammar,
+    ) -> ConflictDisplay<'a> {
+        ConflictDisplay {
+            report: self,
+            id,
+            grammar,
+        }
+    }
+
+    /// Display the report with the rules and matches from the grammar, one conflict per
+    /// line.
+    pub fn display<'a>(&'a self, grammar: &'a Grammar) -> ConflictReportDisplay<'a> {
+        ConflictReportDisplay {
+            report: self,
+            grammar,
+        }
+    }
+}
+
+/// The token sets that can come at a match index: its direct tokens, and the first sets of
+/// the rules it enters.
+fn follow_tokens(
+    first_sets: &FirstSets,
+    follow_sets: &FollowSets,
+    mi: MatchIndex,
+) -> Vec<Vec<TokenOrGroup>> {
+    let mut tokens = Vec::new();
+    for set in follow_sets.sets.get(&mi).into_iter().flatten() {
+        match set {
+            FollowSet::Direct(direct) => tokens.push(direct.tokens.clone()),
+            FollowSet::Enter(enter) => {
+                let sets = first_sets.first_sets_per_rule.get(&enter.rule);
+                tokens.extend(sets.into_iter().flatten().map(|s| s.tokens.clone()));
+            }
+        }
+    }
+    tokens
+}
+
+/// The match indexes that follow the parent rule of a context, wherever it's used.
+fn parent_follows(grammar: &Grammar, ctx: WrapContext) -> impl Iterator<Item = MatchIndex> + '_ {
+    grammar.iter_matches().flat_map(move |(id, m)| {
+        m.terms
+            .iter()
+            .enumerate()
+            .filter(move |(_, term)| **term == Term::Rule(ctx.parent))
+            .map(move |(i, _)| MatchIndex::new_at_index(id, i + 1))
+    })
+}
+
+/// The longer of the first pair of token sets that overlap.
+fn first_overlap(a: &[Vec<TokenOrGroup>], b: &[Vec<TokenOrGroup>]) -> Option<Vec<TokenOrGroup>> {
+    for a in a {
+        for b in b {
+            if token_sets_overlap(a, b) {
+                return Some(if a.len() >= b.len() { a } else { b }.clone());
+            }
+        }
+    }
+    None
+}
+
+pub struct ConflictReportDisplay<'a> {
+    report: &'a ConflictReport,
+    grammar: &'a Grammar,
+}
+
+impl std::fmt::Display for ConflictReportDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for id in self.report.ids() {
+            writeln!(f, "{}", self.report.display_conflict(id, self.grammar))?;
+        }
+
+        Ok(())
+    }
+}
+
+pub struct ConflictDisplay<'a> {
+    report: &'a ConflictReport,
+    id: ConflictId,
+    grammar: &'a Grammar,
+}
+
+impl ConflictDisplay<'_> {
+    fn write_wraps(&self, f: &mut std::fmt::Formatter<'_>, wraps: &[MatchId]) -> std::fmt::Result {
+        if wraps.is_empty() {
+            return write!(f, "no wraps");
+        }
+
+        write!(f, "wrapping with")?;
+        for (i, id) in wraps.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            write!(f, "{} `{}`", separator, self.grammar.get(*id))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for ConflictDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let grammar = self.grammar;
+
+        match self.id {
+            ConflictId::FirstSet(i) => {
+                let overlap = &self.report.first_sets[i];
+                let kind = match overlap.kind {
+                    OverlapKind::Duplicate => "duplicate",
+                    OverlapKind::Prefix => "prefix",
+                };
+                write!(
+                    f,
+                    "{} first sets of {:?}: `{}` for `{}` is tried before `{}` for `{}`",
+                    kind,
+                    overlap.rule,
+                    TokenSetDisplay(&overlap.taken_tokens),
+                    grammar.get(overlap.taken),
+                    TokenSetDisplay(&overlap.shadowed_tokens),
+                    grammar.get(overlap.shadowed)
+                )?;
+            }
+            ConflictId::Competing(i) => {
+                let competing = &self.report.competing[i];
+                let ctx = competing.context;
+                match competing.if_matches {
+                    Some(mi) => write!(
+                        f,
+                        "competing wraps of {:?} in {:?} into `{}`: ",
+                        ctx.child,
+                        ctx.parent,
+                        mi.display(grammar)
+                    )?,
+                    None => write!(
+                        f,
+                        "competing inserts of {:?} into {:?}: ",
+                        ctx.child, ctx.parent
+                    )?,
+                }
+
+                let (kept, score) = &competing.candidates[competing.kept];
+                write!(f, "kept ")?;
+                self.write_wraps(f, kept)?;
+                write!(f, " (score {})", score)?;
+
+                for (i, (wraps, score)) in competing.candidates.iter().enumerate() {
+                    if i != competing.kept {
+                        write!(f, " over ")?;
+                        self.write_wraps(f, wraps)?;
+                        write!(f, " (score {})", score)?;
+                    }
+                }
+
+                if competing.is_tie() {
+                    write!(f, ", a tie")?;
+                }
+            }
+            ConflictId::FollowSet(i) => {
+                let overlap = &self.report.follow_sets[i];
+                let ctx = overlap.context;
+                write!(
+                    f,
+                    "overlapping follow sets of {:?} in {:?} on `{}`: wrapping into `{}` is tried \
+                     before ",
+                    ctx.child,
+                    ctx.parent,
+                    TokenSetDisplay(&overlap.tokens),
+                    overlap.taken.display(grammar)
+                )?;
+                match overlap.other {
+                    FollowOverlapWith::Wrap(mi) => {
+                        write!(f, "wrapping into `{}`", mi.display(grammar))?
+                    }
+                    FollowOverlapWith::Insert(mi) => {
+                        write!(f, "inserting before `{}`", mi.display(grammar))?
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
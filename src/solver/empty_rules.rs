@@ -49,7 +49,7 @@ impl EmptyRuleSolver {
                     let mut is_empty = true;
                     for term in &match_.terms {
                         if let Some(rule) = term.as_rule() {
-                            if !empty_rules.contains_key(&rule) {
+                            if !empty_rules.contains_key(rule) {
                                 is_empty = false;
                                 break;
                             }
@@ -64,7 +64,7 @@ impl EmptyRuleSolver {
                         let mut fields = vec![];
                         for term in &match_.terms {
                             let rule = term.as_rule().unwrap();
-                            let rule_value = empty_rules.get(&rule).unwrap();
+                            let rule_value = empty_rules.get(rule).unwrap();
                             fields.push(rule_value.clone());
                         }
 
@@ -0,0 +1,253 @@
+//! A readable dump of the tables of the solver, like the `.output` file of yacc.
+//!
+//! Everything is written with the names of the rules and the matches of the grammar, and in
+//! the order that the interpreter tries it, so the dump shows what the interpreter does with
+//! each token without having to read the tables with `dbg!`.
+
+use crate::matches::{Grammar, Rule};
+
+use super::{
+    first_sets::PushItem, follow_sets::FollowSet, path::MatchIndex,
+    structure::EmptySolverRuleValue, token_sets::TokenSetDisplay, wrap_sets::EmptyWrapAction,
+    GrammarSolver,
+};
+
+pub struct Explanation<'a> {
+    solver: &'a GrammarSolver,
+}
+
+impl<'a> Explanation<'a> {
+    pub fn new(solver: &'a GrammarSolver) -> Self {
+        Self { solver }
+    }
+
+    fn grammar(&self) -> &'a Grammar {
+        &self.solver.grammar
+    }
+
+    fn write_first_sets(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "first sets")?;
+
+        let mut rules: Vec<Rule> = self.grammar().iter_rules().collect();
+        rules.sort();
+
+        for rule in rules {
+            writeln!(f, "  {:?}", rule)?;
+
+            let sets = self.solver.first_set_for_rule(rule);
+            if sets.is_empty() {
+                writeln!(f, "    none")?;
+            }
+
+            for set in sets {
+                write!(f, "    {}:", TokenSetDisplay(&set.tokens))?;
+                for (i, item) in set.then.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { "," };
+                    write!(f, "{} ", separator)?;
+                    self.write_push(f, item)?;
+                }
+                writeln!(f)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_push(&self, f: &mut std::fmt::Formatter<'_>, item: &PushItem) -> std::fmt::Result {
+        write!(f, "push `{}`", self.grammar().get(item.id))?;
+        self.write_empties(f, &item.append_empty_fields)?;
+        if item.linked_to_above {
+            write!(f, " (linked to above)")?;
+        }
+        Ok(())
+    }
+
+    fn write_follow_sets(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "follow sets")?;
+
+        for (id, m) in self.grammar().iter_matches() {
+            for index in 0..=m.terms.len() {
+                let mi = MatchIndex::new_at_index(id, index);
+                writeln!(f, "  {}", mi.display(self.grammar()))?;
+
+                let sets = self.solver.follow_set_for_match(mi);
+                if sets.is_empty() {
+                    writeln!(f, "    none")?;
+                }
+
+                for set in sets {
+                    match set {
+                        FollowSet::Direct(direct) => {
+                            write!(f, "    {}", TokenSetDisplay(&direct.tokens))?;
+                            self.write_empties(f, &direct.append_extra_emptys)?;
+                        }
+                        FollowSet::Enter(enter) => {
+                            write!(f, "    enter {:?}", enter.rule)?;
+                            self.write_empties(f, &enter.append_extra)?;
+                        }
+                    }
+                    writeln!(f)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_seal_actions(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "seal actions")?;
+
+        for (id, m) in self.grammar().iter_matches() {
+            for index in 0..=m.terms.len() {
+                let mi = MatchIndex::new_at_index(id, index);
+                let Some(action) = self.solver.get_seal_action_for_match(mi) else {
+                    continue;
+                };
+
+                write!(
+                    f,
+                    "  {}: seal into {:?}",
+                    mi.display(self.grammar()),
+                    action.into_rule
+                )?;
+                self.write_empties(f, &action.append_extra)?;
+                writeln!(f)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_wrap_sets(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "wrap sets")?;
+
+        let mut contexts: Vec<_> = self.solver.wrap_sets.sets.iter().collect();
+        contexts.sort_by_key(|(ctx, _)| (ctx.parent, ctx.child));
+
+        for (ctx, data) in contexts {
+            writeln!(f, "  {:?} in {:?}", ctx.child, ctx.parent)?;
+
+            if data.wrap_actions.is_empty() && data.insert_action.is_none() {
+                writeln!(f, "    none")?;
+            }
+
+            for action in &data.wrap_actions {
+                write!(
+                    f,
+                    "    at `{}`: ",
+                    action.if_matches.display(self.grammar())
+                )?;
+                self.write_wraps(f, &action.wrap_actions)?;
+                self.write_empties(f, &action.append_empty)?;
+                writeln!(f)?;
+            }
+
+            if let Some(insert) = &data.insert_action {
+                write!(f, "    insert: ")?;
+                self.write_wraps(f, &insert.wrap_actions)?;
+                writeln!(f)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_wraps(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        wraps: &[EmptyWrapAction],
+    ) -> std::fmt::Result {
+        if wraps.is_empty() {
+            return write!(f, "no wraps");
+        }
+
+        write!(f, "wrap with")?;
+        for (i, wrap) in wraps.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            write!(f, "{} `{}`", separator, self.grammar().get(wrap.match_id))?;
+
+            if !wrap.left_empty.is_empty() {
+                write!(f, " (")?;
+                self.write_matches(f, &wrap.left_empty)?;
+                write!(f, " on the left)")?;
+            }
+            if !wrap.right_empty.is_empty() {
+                write!(f, " (")?;
+                self.write_matches(f, &wrap.right_empty)?;
+                write!(f, " on the right)")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the empty values that an action appends, if it appends any.
+    fn write_empties(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        empties: &[EmptySolverRuleValue],
+    ) -> std::fmt::Result {
+        if empties.is_empty() {
+            return Ok(());
+        }
+
+        write!(f, " with empty ")?;
+        self.write_matches(f, empties)
+    }
+
+    fn write_matches(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        empties: &[EmptySolverRuleValue],
+    ) -> std::fmt::Result {
+        for (i, empty) in empties.iter().enumerate() {
+            let separator = if i == 0 { "" } else { ", " };
+            write!(
+                f,
+                "{}`{}`",
+                separator,
+                self.grammar().get(empty.match_value.id)
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write_conflicts(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "conflicts")?;
+
+        let report = self.solver.conflicts();
+        if report.is_empty() {
+            writeln!(f, "  none")?;
+        }
+
+        for id in report.ids() {
+            writeln!(f, "  {}", report.display_conflict(id, self.grammar()))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Explanation<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "max lookahead: {}", self.solver.max_lookahead())?;
+        writeln!(f)?;
+        self.write_first_sets(f)?;
+        writeln!(f)?;
+        self.write_follow_sets(f)?;
+        writeln!(f)?;
+        self.write_seal_actions(f)?;
+        writeln!(f)?;
+        self.write_wrap_sets(f)?;
+        writeln!(f)?;
+        self.write_conflicts(f)?;
+
+        if let Some(recovery) = self.solver.recovery_solver() {
+            writeln!(f)?;
+            writeln!(f, "recovery tables")?;
+            writeln!(f)?;
+            write!(f, "{}", recovery.explain())?;
+        }
+
+        Ok(())
+    }
+}
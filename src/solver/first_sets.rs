@@ -1,4 +1,7 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+};
 
 use crate::{
     matches::{Grammar, MatchId, Rule, Term},
@@ -10,7 +13,7 @@ use super::{
     empty_rules::EmptyRuleSolver,
     path::MatchIndex,
     structure::EmptySolverRuleValue,
-    token_sets::{get_match_set_start_index, TokenOrGroup},
+    token_sets::{get_match_set_start_index, token_sets_overlap, TokenOrGroup},
 };
 
 #[derive(Debug, Clone)]
@@ -26,7 +29,7 @@ pub struct FirstSet {
     pub then: Vec<PushItem>,
 }
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct StackDisconnect {
     /// The rule that begins the disconnect. In practice, this should be a list of MatchIndex
     /// that point to that rule instead.
@@ -36,19 +39,50 @@ pub struct StackDisconnect {
     pub child: Rule,
 }
 
+/// How two first sets of a rule overlap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlapKind {
+    /// Both sets have the same tokens, so the one that's tried second is never taken.
+    Duplicate,
+    /// The shorter set is a prefix of the longer one. The longer one is tried first, so the
+    /// shorter one is never taken when the rest of the longer one follows.
+    Prefix,
+}
+
+/// Two first sets of a rule that can match the same tokens. Groups match any other group.
+#[derive(Debug, Clone)]
+pub struct FirstSetOverlap {
+    pub rule: Rule,
+    pub kind: OverlapKind,
+    /// The match that the set that's tried first leads to, and its tokens.
+    pub taken: MatchId,
+    pub taken_tokens: Vec<TokenOrGroup>,
+    /// The match that the other set leads to, and its tokens.
+    pub shadowed: MatchId,
+    pub shadowed_tokens: Vec<TokenOrGroup>,
+}
+
 #[derive(Debug, Clone)]
 pub struct FirstSets {
     pub first_sets_per_rule: HashMap<Rule, Vec<FirstSet>>,
     pub potential_disconnects: Vec<StackDisconnect>,
+    /// Every pair of first sets of a rule that overlap, by rule.
+    pub overlaps: Vec<FirstSetOverlap>,
 }
 
 impl FirstSets {
     pub fn new(grammar: &Grammar, empty: &EmptyRuleSolver) -> Self {
         let mut rules = HashMap::new();
         let mut disconnects = HashSet::new();
+        let mut overlaps = Vec::new();
 
         for rule in grammar.iter_rules() {
-            let matches = calculate_all_destination_matches(grammar, empty, rule);
+            // Go through the matches in order, so that sets of the same length are tried in
+            // the order of their matches
+            let mut matches: Vec<_> = calculate_all_destination_matches(grammar, empty, rule)
+                .into_iter()
+                .collect();
+            matches.sort_by_key(|(id, _)| *id);
 
             let mut sets = Vec::new();
 
@@ -67,21 +101,58 @@ impl FirstSets {
             }
 
             // Sort the sets from largest token lengths to smallest
-            sets.sort_by_key(|f| f.tokens.len());
-            sets.reverse();
+            sets.sort_by_key(|f| Reverse(f.tokens.len()));
 
+            overlaps.extend(find_overlaps(rule, &sets));
             rules.insert(rule, sets);
         }
 
-        dbg!(&rules);
+        overlaps.sort_by_key(|o| (o.rule, o.taken, o.shadowed));
+
+        let mut potential_disconnects: Vec<_> = disconnects.into_iter().collect();
+        potential_disconnects.sort();
 
         Self {
             first_sets_per_rule: rules,
-            potential_disconnects: disconnects.into_iter().collect(),
+            potential_disconnects,
+            overlaps,
         }
+    }
+}
+
+/// Find the first sets of a rule that overlap, in the order that they're tried.
+fn find_overlaps(rule: Rule, sets: &[FirstSet]) -> Vec<FirstSetOverlap> {
+    let mut overlaps = Vec::new();
 
-        // TODO: Allow gathering duplicate first set warnings per rule
+    for (i, taken) in sets.iter().enumerate() {
+        for shadowed in &sets[i + 1..] {
+            if !token_sets_overlap(&taken.tokens, &shadowed.tokens) {
+                continue;
+            }
+
+            let (Some(taken_id), Some(shadowed_id)) = (taken.then.last(), shadowed.then.last())
+            else {
+                continue;
+            };
+
+            let kind = if taken.tokens.len() == shadowed.tokens.len() {
+                OverlapKind::Duplicate
+            } else {
+                OverlapKind::Prefix
+            };
+
+            overlaps.push(FirstSetOverlap {
+                rule,
+                kind,
+                taken: taken_id.id,
+                taken_tokens: taken.tokens.clone(),
+                shadowed: shadowed_id.id,
+                shadowed_tokens: shadowed.tokens.clone(),
+            });
+        }
     }
+
+    overlaps
 }
 
 /// Use a recursive function to calculate all possible matches that can be reached from the current match,
@@ -113,6 +184,7 @@ fn calculate_all_destination_matches(
 /// Because we use the possible paths to determine which paths we must keep (start and end segments)
 /// and which we should throw away (middle segments), we need to also iterate over all possible
 /// children too, not just the ones that are directly reachable from the starting rule.
+#[allow(clippy::only_used_in_recursion)]
 fn recursive_calculate_all_destination_matches(
     grammar: &Grammar,
     empty_rules: &EmptyRuleSolver,
@@ -127,10 +199,7 @@ fn recursive_calculate_all_destination_matches(
         let mut as_vec = full_list.iter().cloned().collect::<Vec<_>>();
         as_vec.reverse();
 
-        destinations
-            .entry(next_match)
-            .or_insert_with(HashSet::new)
-            .insert(as_vec);
+        destinations.entry(next_match).or_default().insert(as_vec);
     }
 
     // Check if next_match exists in prev_matches, if it does then we skip
@@ -145,7 +214,7 @@ fn recursive_calculate_all_destination_matches(
 
     for (i, term) in match_.terms.iter().enumerate() {
         let next_index = &MatchIndex::new_at_index(next_match, i);
-        let matches = prev_matches.push(&next_index);
+        let matches = prev_matches.push(next_index);
 
         match term {
             // We don't filter out non empty paths, they will be filtered out later.
@@ -176,6 +245,21 @@ fn calculate_push_instructions_from_paths(
     empty: &EmptyRuleSolver,
     paths: &HashSet<Vec<MatchIndex>>,
 ) -> (Option<Vec<PushItem>>, Option<StackDisconnect>) {
+    // Paths that go through a term with tokens or non-empty rules before it can't start
+    // with the destination's tokens. They'd otherwise only be caught when they end up in
+    // the common start or end, and not when they only differ in the middle.
+    let paths: Vec<&Vec<MatchIndex>> = paths
+        .iter()
+        .filter(|path| {
+            path.iter().all(|mi| {
+                convert_match_index_to_push_instruction(grammar, empty, mi, false).is_some()
+            })
+        })
+        .collect();
+    if paths.is_empty() {
+        return (None, None);
+    }
+
     // Instructions that all paths start with (until they diverge)
     let common_start_instructions =
         calculate_common_starts(paths.iter().map(|p| p.iter()).collect());
@@ -201,9 +285,8 @@ fn calculate_push_instructions_from_paths(
         *grammar.get(id.id).terms[id.index].as_rule().unwrap()
     } else {
         // If common start instructions is empty, then we can just use the first rule
-        grammar
-            .get(paths.iter().nth(0).unwrap().first().unwrap().id)
-            .rule
+        let first = paths.iter().filter_map(|path| path.first()).min().unwrap();
+        grammar.get(first.id).rule
     };
 
     let disconnect_child_rule = grammar
@@ -35,7 +35,8 @@ impl FollowSets {
         let mut sets = HashMap::new();
 
         for (id, match_) in grammar.iter_matches() {
-            for i in 0..match_.terms.len() {
+            // The end of a match has no follow sets, but it's kept like the other indexes
+            for i in 0..=match_.terms.len() {
                 let index = MatchIndex::new_at_index(id, i);
                 let match_sets = generate_set_for_match(index, grammar, empty);
                 sets.insert(index, match_sets);
@@ -100,7 +101,7 @@ fn generate_set_for_match(
         i += 1;
     }
 
-    if tokens.len() > 0 {
+    if !tokens.is_empty() {
         sets.push(FollowSet::Direct(DirectFollowSet {
             tokens,
             append_extra_emptys: emptys_to_append.clone(),
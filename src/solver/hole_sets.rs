@@ -0,0 +1,167 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    interpreter::ITokenOrGroup,
+    matches::{Grammar, Rule, Symbol, Term},
+};
+
+use super::empty_rules::EmptyRuleSolver;
+
+/// What the interpreter needs to know about each rule to pick the rule that a hole stands
+/// for. This is only built for grammars with holes.
+#[derive(Debug, Clone)]
+pub struct HoleSets {
+    follow: HashMap<Rule, HashSet<Symbol>>,
+    left_corners: HashMap<Rule, usize>,
+}
+
+impl HoleSets {
+    pub fn new(grammar: &Grammar, empty_rules: &EmptyRuleSolver) -> Self {
+        let rule_sets = RuleSets::new(grammar, empty_rules);
+        let mut follow = HashMap::new();
+        let mut left_corners = HashMap::new();
+
+        for (_, m) in grammar.iter_matches() {
+            follow
+                .entry(m.rule)
+                .or_insert_with(|| rule_sets.follow(grammar, m.rule));
+            left_corners
+                .entry(m.rule)
+                .or_insert_with(|| count_left_corners(grammar, m.rule));
+        }
+
+        Self {
+            follow,
+            left_corners,
+        }
+    }
+
+    /// Whether `next` can come right after a value of the rule anywhere in the grammar. The
+    /// end of the input can follow anything.
+    pub fn can_follow(&self, rule: Rule, next: Option<&ITokenOrGroup>) -> bool {
+        let Some(next) = next else {
+            return true;
+        };
+
+        let mut follow = self.follow.get(&rule).into_iter().flatten();
+        follow.any(|symbol| match (symbol, next) {
+            (Symbol::Token(token), ITokenOrGroup::Token(next)) => token == next,
+            (Symbol::Group(_), ITokenOrGroup::Group(_)) => true,
+            _ => false,
+        })
+    }
+
+    /// The number of rules that the rule can start with. Inner rules start with fewer rules.
+    pub fn left_corners(&self, rule: Rule) -> usize {
+        self.left_corners.get(&rule).copied().unwrap_or(0)
+    }
+}
+
+/// Count the rules that a rule can start with, e.g. `Add -> Add + Mul | Mul` starts with
+/// `Add`, `Mul` and whatever `Mul` starts with. Inner rules start with fewer rules.
+fn count_left_corners(grammar: &Grammar, rule: Rule) -> usize {
+    let mut seen = HashSet::new();
+    let mut queue = vec![rule];
+
+    while let Some(rule) = queue.pop() {
+        for &id in grammar.get_matches_from_rule(rule) {
+            if let Some(Term::Rule(first)) = grammar.get(id).terms.first() {
+                if seen.insert(*first) {
+                    queue.push(*first);
+                }
+            }
+        }
+    }
+
+    seen.len()
+}
+
+/// The classic first sets of each rule, with the empty rules from the solver.
+struct RuleSets<'a> {
+    empty_rules: &'a EmptyRuleSolver,
+    first: HashMap<Rule, HashSet<Symbol>>,
+}
+
+impl<'a> RuleSets<'a> {
+    fn new(grammar: &Grammar, empty_rules: &'a EmptyRuleSolver) -> Self {
+        let mut sets = Self {
+            empty_rules,
+            first: HashMap::new(),
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for (_, m) in grammar.iter_matches() {
+                let first = sets.first_of_terms(&m.terms);
+                let entry = sets.first.entry(m.rule).or_default();
+                for symbol in first {
+                    changed |= entry.insert(symbol);
+                }
+            }
+        }
+
+        sets
+    }
+
+    fn are_nullable(&self, terms: &[Term]) -> bool {
+        terms.iter().all(|term| match term {
+            Term::Rule(rule) => self.empty_rules.is_empty(*rule),
+            _ => false,
+        })
+    }
+
+    fn first_of_terms(&self, terms: &[Term]) -> HashSet<Symbol> {
+        let mut first = HashSet::new();
+        for term in terms {
+            match term {
+                Term::Token(token) => {
+                    first.insert(Symbol::Token(*token));
+                    break;
+                }
+                Term::Group(group, _) => {
+                    first.insert(Symbol::Group(*group));
+                    break;
+                }
+                Term::Rule(rule) => {
+                    first.extend(self.first.get(rule).into_iter().flatten().copied());
+                    if !self.empty_rules.is_empty(*rule) {
+                        break;
+                    }
+                }
+            }
+        }
+
+        first
+    }
+
+    fn follow(&self, grammar: &Grammar, rule: Rule) -> HashSet<Symbol> {
+        let mut follow = HashSet::new();
+        let mut seen = HashSet::new();
+        let mut queue = vec![rule];
+
+        // A rule at the end of a match is followed by whatever follows the match's rule
+        while let Some(rule) = queue.pop() {
+            if !seen.insert(rule) {
+                continue;
+            }
+
+            for (_, m) in grammar.iter_matches() {
+                for (i, term) in m.terms.iter().enumerate() {
+                    if term != &Term::Rule(rule) {
+                        continue;
+                    }
+
+                    let rest = &m.terms[i + 1..];
+                    follow.extend(self.first_of_terms(rest));
+                    if self.are_nullable(rest) {
+                        queue.push(m.rule);
+                    }
+                }
+            }
+        }
+
+        follow
+    }
+}
@@ -1,6 +1,6 @@
-use crate::matches::MatchId;
+use crate::matches::{Grammar, MatchId, Term};
 
-#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, PartialOrd, Ord)]
 pub struct MatchIndex {
     pub id: MatchId,
     pub index: usize,
@@ -21,4 +21,42 @@ impl MatchIndex {
     pub fn advance_by(&mut self, n: usize) {
         self.index += n;
     }
+
+    /// Display the match with a dot before the term at the index, e.g. `Add -> Add . Plus Mul`.
+    pub fn display<'a>(&'a self, grammar: &'a Grammar) -> MatchIndexDisplay<'a> {
+        MatchIndexDisplay {
+            index: self,
+            grammar,
+        }
+    }
+}
+
+pub struct MatchIndexDisplay<'a> {
+    index: &'a MatchIndex,
+    grammar: &'a Grammar,
+}
+
+impl std::fmt::Display for MatchIndexDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let m = self.grammar.get(self.index.id);
+        write!(f, "{:?} ->", m.rule)?;
+
+        for (i, term) in m.terms.iter().enumerate() {
+            if i == self.index.index {
+                write!(f, " .")?;
+            }
+
+            match term {
+                Term::Rule(rule) => write!(f, " {:?}", rule)?,
+                Term::Token(token) => write!(f, " {:?}", token)?,
+                Term::Group(group, rule) => write!(f, " {:?}({:?})", group, rule)?,
+            }
+        }
+
+        if self.index.index >= m.terms.len() {
+            write!(f, " .")?;
+        }
+
+        Ok(())
+    }
 }
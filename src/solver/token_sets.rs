@@ -1,4 +1,4 @@
-use crate::matches::{Grammar, Group, MatchId, Rule, Term, Token};
+use crate::matches::{Grammar, Group, MatchId, Rule, Symbol, Term, Token};
 
 #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
 pub enum TokenOrGroup {
@@ -6,6 +6,43 @@ pub enum TokenOrGroup {
     Group(Group, Rule),
 }
 
+impl TokenOrGroup {
+    pub fn as_symbol(&self) -> Symbol {
+        match self {
+            TokenOrGroup::Token(token) => Symbol::Token(*token),
+            TokenOrGroup::Group(group, _) => Symbol::Group(*group),
+        }
+    }
+}
+
+/// Display a token set on one line, e.g. `Num Plus Parens(Expr)`.
+pub struct TokenSetDisplay<'a>(pub &'a [TokenOrGroup]);
+
+impl std::fmt::Display for TokenSetDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, token) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            match token {
+                TokenOrGroup::Token(token) => write!(f, "{:?}", token)?,
+                TokenOrGroup::Group(group, rule) => write!(f, "{:?}({:?})", group, rule)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether some input can match both token sets, which is when the shorter one is a prefix
+/// of the longer one. The interpreter lets a group match any group.
+pub fn token_sets_overlap(a: &[TokenOrGroup], b: &[TokenOrGroup]) -> bool {
+    a.iter().zip(b).all(|pair| match pair {
+        (TokenOrGroup::Token(a), TokenOrGroup::Token(b)) => a == b,
+        (TokenOrGroup::Group(_, _), TokenOrGroup::Group(_, _)) => true,
+        _ => false,
+    })
+}
+
 pub fn get_set_for_match(
     grammar: &Grammar,
     match_: MatchId,
@@ -45,9 +82,5 @@ pub fn get_match_set_start_index(
             Term::Rule(_) => false,
         });
 
-    if let Some(empty_offset) = empty_offset {
-        Some(start_index + empty_offset)
-    } else {
-        None
-    }
+    empty_offset.map(|empty_offset| start_index + empty_offset)
 }
@@ -11,7 +11,7 @@ use super::{
 };
 
 /// Describes a single step in wrapping an item and bubbling it up into a parent rule.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct EmptyWrapAction {
     pub match_id: MatchId,
 
@@ -21,14 +21,14 @@ pub struct EmptyWrapAction {
     pub right_empty: Vec<EmptySolverRuleValue>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct InsertAction {
     // If the parent matches, then wrap using the following matches:
     pub wrap_actions: Vec<EmptyWrapAction>,
     // And then append the child rule too.
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct WrapAction {
     // If matches the following:
     pub if_matches: MatchIndex,
@@ -59,8 +59,36 @@ pub struct WrapData {
     pub insert_action: Option<InsertAction>,
 }
 
+/// Several ways of wrapping a child into the same parent match, or of inserting it into
+/// its parent, out of which the solver kept the one with the lowest score.
+#[derive(Debug, Clone)]
+pub struct CompetingActions {
+    pub context: WrapContext,
+    /// The match index that the child becomes, or `None` for insert actions.
+    pub if_matches: Option<MatchIndex>,
+    /// The matches that each candidate wraps the child with, innermost first, with the
+    /// score of the candidate.
+    pub candidates: Vec<(Vec<MatchId>, u32)>,
+    /// The index of the candidate that was kept.
+    pub kept: usize,
+}
+
+impl CompetingActions {
+    /// Whether another candidate has the same score as the kept one, so that it was only
+    /// kept for coming first.
+    pub fn is_tie(&self) -> bool {
+        let score = self.candidates[self.kept].1;
+        self.candidates
+            .iter()
+            .enumerate()
+            .any(|(i, (_, other))| i != self.kept && *other == score)
+    }
+}
+
 pub struct WrapSets {
     pub sets: HashMap<WrapContext, WrapData>,
+    /// Every context where more than one action competed for the same wrap or insert.
+    pub competing: Vec<CompetingActions>,
 }
 
 impl WrapSets {
@@ -79,6 +107,7 @@ impl WrapSets {
         }
 
         let mut sets = HashMap::new();
+        let mut competing = Vec::new();
 
         let mut changed = true;
         while changed {
@@ -96,7 +125,7 @@ impl WrapSets {
                     }
 
                     // If it's absent, then calculate and insert it
-                    let data = get_wrap_data_for(&ctx, grammar, empty);
+                    let data = get_wrap_data_for(&ctx, grammar, empty, &mut competing);
 
                     // Scan the generated wrap actions for any new potential disconnects
                     for wrap_action in &data.wrap_actions {
@@ -117,7 +146,12 @@ impl WrapSets {
             }
         }
 
-        Self { sets }
+        competing.sort_by_key(|c: &CompetingActions| {
+            let index = c.if_matches.map(|mi| (mi.id, mi.index));
+            (c.context.parent, c.context.child, index)
+        });
+
+        Self { sets, competing }
     }
 }
 
@@ -128,7 +162,13 @@ struct WrapDataBuilder {
 }
 
 /// Gets the wrap data, as well as other potential children to check for that parent.
-fn get_wrap_data_for(ctx: &WrapContext, grammar: &Grammar, empty: &EmptyRuleSolver) -> WrapData {
+/// Wraps and inserts that had more than one candidate are added to `competing`.
+fn get_wrap_data_for(
+    ctx: &WrapContext,
+    grammar: &Grammar,
+    empty: &EmptyRuleSolver,
+    competing: &mut Vec<CompetingActions>,
+) -> WrapData {
     let mut builder = WrapDataBuilder {
         wrap_actions: HashMap::new(),
         insert_actions: Vec::new(),
@@ -145,22 +185,69 @@ fn get_wrap_data_for(ctx: &WrapContext, grammar: &Grammar, empty: &EmptyRuleSolv
         grammar,
         empty,
         ERefList::new(),
-        ctx.clone(),
+        *ctx,
         ctx.parent,
         ctx.child,
         &mut builder,
     );
 
+    // The same action can be found through more than one path, which isn't a choice
+    let inserts = distinct(&builder.insert_actions);
+    if inserts.len() > 1 {
+        competing.push(CompetingActions {
+            context: *ctx,
+            if_matches: None,
+            candidates: inserts
+                .iter()
+                .map(|a| (wrapped_matches(&a.wrap_actions), insert_action_score(a)))
+                .collect(),
+            kept: best_index(&inserts, |a| insert_action_score(a)).unwrap(),
+        });
+    }
+
+    for (mi, actions) in &builder.wrap_actions {
+        let actions = distinct(actions);
+        if actions.len() > 1 {
+            competing.push(CompetingActions {
+                context: *ctx,
+                if_matches: Some(*mi),
+                candidates: actions
+                    .iter()
+                    .map(|a| (wrapped_matches(&a.wrap_actions), wrap_action_score(a)))
+                    .collect(),
+                kept: best_index(&actions, |a| wrap_action_score(a)).unwrap(),
+            });
+        }
+    }
+
+    // The interpreter takes the first wrap action whose follow set matches, so they're kept
+    // in the order of their matches
+    let mut wrap_actions: Vec<_> = builder.wrap_actions.into_iter().collect();
+    wrap_actions.sort_by_key(|(mi, _)| *mi);
+
     WrapData {
         insert_action: pick_best_insert_action(builder.insert_actions),
-        wrap_actions: builder
-            .wrap_actions
+        wrap_actions: wrap_actions
             .into_iter()
-            .map(|(_, a)| pick_best_wrap_action(a))
+            .map(|(_, actions)| pick_best_wrap_action(actions))
             .collect(),
     }
 }
 
+fn distinct<T: PartialEq>(items: &[T]) -> Vec<&T> {
+    let mut distinct: Vec<&T> = Vec::new();
+    for item in items {
+        if !distinct.contains(&item) {
+            distinct.push(item);
+        }
+    }
+    distinct
+}
+
+fn wrapped_matches(actions: &[EmptyWrapAction]) -> Vec<MatchId> {
+    actions.iter().map(|a| a.match_id).collect()
+}
+
 #[derive(Debug, Clone, Copy)]
 struct RecursiveWrap {
     index: MatchIndex,
@@ -226,6 +313,11 @@ fn recursive_calculate_all_destination_matches_for_match(
                 if *rule == target_rule {
                     extend_builder_from_matches(grammar, empty_rules, next_matches, data);
                 }
+
+                // Later terms can only start the match if this one can be empty
+                if !empty_rules.is_empty(*rule) {
+                    break;
+                }
             }
             Term::Group(_, _) | Term::Token(_) => break,
         }
@@ -286,7 +378,7 @@ fn extend_builder_from_matches(
 
             data.wrap_actions
                 .entry(wrap.if_matches)
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(wrap);
         }
 
@@ -331,28 +423,34 @@ fn extend_builder_from_matches(
 }
 
 pub fn pick_best_insert_action(actions: Vec<InsertAction>) -> Option<InsertAction> {
-    pick_best_item(actions.into_iter(), |a| a.wrap_actions.len() as u32)
+    pick_best_item(actions.into_iter(), insert_action_score)
 }
 
 pub fn pick_best_wrap_action(actions: Vec<WrapAction>) -> WrapAction {
-    pick_best_item(actions.into_iter(), |a| {
-        // We want the shallowest wrap actions.
-        let depth_score = a.wrap_actions.len() as u32 * 100;
+    pick_best_item(actions.into_iter(), wrap_action_score).unwrap()
+}
 
-        // For each wrap, we want the least amout of empty items to be added.
-        let wrap_empty_score: u32 = a
-            .wrap_actions
-            .iter()
-            .map(|w| w.left_empty.len() as u32 + w.right_empty.len() as u32)
-            .sum();
+/// We want the insert action with the fewest wraps.
+fn insert_action_score(action: &InsertAction) -> u32 {
+    action.wrap_actions.len() as u32
+}
 
-        // If there are multiple equal shallow wrap actions, we want the one with the least
-        // empty values inserted.
-        let append_score = a.append_empty.len() as u32;
+fn wrap_action_score(action: &WrapAction) -> u32 {
+    // We want the shallowest wrap actions.
+    let depth_score = action.wrap_actions.len() as u32 * 100;
 
-        depth_score + wrap_empty_score + append_score
-    })
-    .unwrap()
+    // For each wrap, we want the least amout of empty items to be added.
+    let wrap_empty_score: u32 = action
+        .wrap_actions
+        .iter()
+        .map(|w| w.left_empty.len() as u32 + w.right_empty.len() as u32)
+        .sum();
+
+    // If there are multiple equal shallow wrap actions, we want the one with the least
+    // empty values inserted.
+    let append_score = action.append_empty.len() as u32;
+
+    depth_score + wrap_empty_score + append_score
 }
 
 /// Pick the item with the smallest score.
@@ -374,3 +472,9 @@ pub fn pick_best_item<T>(
 
     best_item
 }
+
+/// The index of the item that `pick_best_item` would pick.
+fn best_index<T>(items: &[T], mut f: impl FnMut(&T) -> u32) -> Option<usize> {
+    let scored = items.iter().enumerate().map(|(i, item)| (i, f(item)));
+    pick_best_item(scored, |(_, score)| *score).map(|(i, _)| i)
+}
@@ -0,0 +1,81 @@
+use std::ops::Range;
+
+use crate::interpreter::ITokenOrGroup;
+
+/// The range of input that a value covers. Token ranges point into the flattened token
+/// stream, where each group is replaced by the tokens inside of it. Byte ranges are only
+/// known when the input tokens were given byte ranges.
+///
+/// Values that don't contain any tokens (e.g. empty rules) have zero width ranges.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub tokens: Range<usize>,
+    pub bytes: Option<Range<usize>>,
+}
+
+impl Span {
+    /// Create a span from a token range, looking up the bytes in the table of byte ranges
+    /// for each token, if there is one. Tokens past the end of the table have no bytes.
+    pub fn new(tokens: Range<usize>, byte_ranges: Option<&[Range<usize>]>) -> Self {
+        let bytes = byte_ranges.and_then(|ranges| {
+            if tokens.start < tokens.end {
+                let first = ranges.get(tokens.start)?;
+                let last = ranges.get(tokens.end - 1)?;
+                Some(first.start..last.end)
+            } else {
+                let offset = get_byte_offset_at(ranges, tokens.start);
+                Some(offset..offset)
+            }
+        });
+
+        Self { tokens, bytes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.start == self.tokens.end
+    }
+}
+
+/// The byte offset of a token position. Positions past the last token point at its end.
+fn get_byte_offset_at(ranges: &[Range<usize>], position: usize) -> usize {
+    if let Some(range) = ranges.get(position) {
+        range.start
+    } else {
+        ranges.last().map(|r| r.end).unwrap_or(0)
+    }
+}
+
+/// The byte ranges given for a parse don't cover every token of the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ByteRangesError {
+    /// The number of tokens in the flattened token stream.
+    pub tokens: usize,
+    pub byte_ranges: usize,
+}
+
+impl std::fmt::Display for ByteRangesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} byte ranges were given for {} tokens",
+            self.byte_ranges, self.tokens
+        )
+    }
+}
+
+/// Check that there's a byte range for each token in the flattened token stream.
+pub fn check_byte_ranges(
+    tokens: &[ITokenOrGroup],
+    byte_ranges: &[Range<usize>],
+) -> Result<(), ByteRangesError> {
+    let len = tokens.iter().map(|item| item.flat_len()).sum();
+    if byte_ranges.len() < len {
+        return Err(ByteRangesError {
+            tokens: len,
+            byte_ranges: byte_ranges.len(),
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,332 @@
+//! Printing trees back out as tokens and text, e.g. after editing them.
+//!
+//! Each rule value is printed by walking the terms of its match. Tokens come from the terms
+//! rather than from the values, and rule values are printed where the terms expect them.
+//! Empty rule values print nothing, and errors print nothing either, apart from their source
+//! text when printing to text.
+//!
+//! A rule value whose match has a single rule term is collapsed into that rule's value while
+//! parsing, so a value can stand in for a chain of such matches (e.g. `Term -> (S)` in the
+//! calc grammar). Any groups along the shortest such chain are printed around the value.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::{
+    interpreter::{ITokenOrGroup, RuleValue, Value},
+    matches::{Grammar, Group, MatchId, Rule, Term, Token},
+    span::Span,
+};
+
+/// Print a tree as the input that the parser takes, with the tokens of groups nested
+/// inside of them. Parsing the result gives the same tree, if the tree is valid.
+pub fn unparse(grammar: &Grammar, root: &RuleValue) -> Vec<ITokenOrGroup> {
+    let mut stack = vec![Vec::new()];
+    for piece in pieces(grammar, root) {
+        match piece {
+            Piece::Token(token, _) => stack.last_mut().unwrap().push(ITokenOrGroup::Token(token)),
+            Piece::Open(_) => stack.push(Vec::new()),
+            Piece::Close(_) => {
+                let tokens = stack.pop().unwrap();
+                stack.last_mut().unwrap().push(ITokenOrGroup::Group(tokens));
+            }
+            Piece::Error(_) | Piece::Enter(_) | Piece::Term(_) | Piece::Exit => {}
+        }
+    }
+
+    stack.pop().unwrap()
+}
+
+/// Print a tree as a flat sequence of tokens, with groups written as their delimiters. Groups
+/// without delimiters in the grammar only print their contents.
+pub fn unparse_tokens(grammar: &Grammar, root: &RuleValue) -> Vec<Token> {
+    pieces(grammar, root)
+        .into_iter()
+        .filter_map(|piece| match piece {
+            Piece::Token(token, _) => Some(token),
+            Piece::Open(group) => grammar.get_group_delimiters(group).map(|(open, _)| open),
+            Piece::Close(group) => grammar.get_group_delimiters(group).map(|(_, close)| close),
+            Piece::Error(_) | Piece::Enter(_) | Piece::Term(_) | Piece::Exit => None,
+        })
+        .collect()
+}
+
+/// Print a tree as text, with the spacing from the options between tokens.
+pub fn unparse_text(grammar: &Grammar, root: &RuleValue, options: &TextOptions) -> String {
+    let mut out = String::new();
+    let mut previous = None;
+
+    for piece in pieces(grammar, root) {
+        let (token, span) = match piece {
+            Piece::Token(token, span) => (token, span),
+            Piece::Open(group) | Piece::Close(group) => {
+                let Some((open, close)) = grammar.get_group_delimiters(group) else {
+                    continue;
+                };
+                let token = if matches!(piece, Piece::Open(_)) {
+                    open
+                } else {
+                    close
+                };
+                (token, None)
+            }
+            Piece::Error(span) => {
+                if let Some(text) = options.source_text(span) {
+                    if !out.is_empty() && !text.is_empty() {
+                        out.push_str(&options.spacing.default);
+                    }
+                    out.push_str(text);
+                }
+                continue;
+            }
+            Piece::Enter(_) | Piece::Term(_) | Piece::Exit => continue,
+        };
+
+        let text = options.text(grammar, token, span);
+        if text.is_empty() {
+            continue;
+        }
+
+        if let Some(previous) = previous {
+            out.push_str(options.spacing.get(previous, token));
+        }
+        out.push_str(&text);
+        previous = Some(token);
+    }
+
+    out
+}
+
+/// How to print tokens as text.
+#[derive(Debug, Clone, Default)]
+pub struct TextOptions<'a> {
+    /// The source that the tree was parsed from, if it was parsed with byte ranges. Tokens
+    /// with a byte range are printed as their source text.
+    pub source: Option<&'a str>,
+    pub spacing: Spacing,
+    /// Text for tokens that always look the same, e.g. keywords and punctuation.
+    texts: HashMap<Token, String>,
+}
+
+impl<'a> TextOptions<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the text that a token is always printed with. Tokens that are only there for the
+    /// parser (e.g. `Start` and `Eof`) can be given empty text to leave them out.
+    pub fn set_text(&mut self, token: Token, text: impl Into<String>) {
+        self.texts.insert(token, text.into());
+    }
+
+    /// The text of a token, from the fixed texts, then the source, and then the name of the
+    /// token in the grammar.
+    pub fn text(&self, grammar: &Grammar, token: Token, span: Option<&Span>) -> String {
+        if let Some(text) = self.texts.get(&token) {
+            return text.clone();
+        }
+
+        // Values from templates have the span of the whole value they replaced, so only
+        // spans of a single token are trusted
+        let text = span
+            .filter(|span| span.tokens.len() == 1)
+            .and_then(|span| self.source_text(span));
+
+        match text {
+            Some(text) => text.to_string(),
+            None => grammar.get_name(token),
+        }
+    }
+
+    /// The source text of a span, if it has a byte range.
+    pub fn source_text(&self, span: &Span) -> Option<&'a str> {
+        self.source?.get(span.bytes.clone()?)
+    }
+}
+
+/// The space to print between pairs of tokens.
+#[derive(Debug, Clone)]
+pub struct Spacing {
+    /// The space between tokens that no rule applies to.
+    pub default: String,
+    rules: Vec<SpacingRule>,
+}
+
+#[derive(Debug, Clone)]
+struct SpacingRule {
+    left: Option<Token>,
+    right: Option<Token>,
+    space: String,
+}
+
+impl Default for Spacing {
+    fn default() -> Self {
+        Self::new(" ")
+    }
+}
+
+impl Spacing {
+    pub fn new(default: impl Into<String>) -> Self {
+        Self {
+            default: default.into(),
+            rules: Vec::new(),
+        }
+    }
+
+    /// Set the space between two tokens. Rules that are set later take priority.
+    pub fn set_between(&mut self, left: Token, right: Token, space: impl Into<String>) {
+        self.add_rule(Some(left), Some(right), space.into());
+    }
+
+    /// Set the space after a token, e.g. nothing after `(`.
+    pub fn set_after(&mut self, left: Token, space: impl Into<String>) {
+        self.add_rule(Some(left), None, space.into());
+    }
+
+    /// Set the space before a token, e.g. nothing before `;`.
+    pub fn set_before(&mut self, right: Token, space: impl Into<String>) {
+        self.add_rule(None, Some(right), space.into());
+    }
+
+    fn add_rule(&mut self, left: Option<Token>, right: Option<Token>, space: String) {
+        self.rules.push(SpacingRule { left, right, space });
+    }
+
+    pub fn get(&self, left: Token, right: Token) -> &str {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| {
+                rule.left.is_none_or(|l| l == left) && rule.right.is_none_or(|r| r == right)
+            })
+            .map_or(&self.default, |rule| &rule.space)
+    }
+}
+
+/// A part of a printed tree.
+#[derive(Debug, Clone, Copy)]
+pub enum Piece<'t> {
+    /// A token, with the span of its value if the tree had one.
+    Token(Token, Option<&'t Span>),
+    Open(Group),
+    Close(Group),
+    Error(&'t Span),
+    /// The start of the terms of a rule value's match.
+    Enter(MatchId),
+    /// The start of the term at the index, within the innermost match.
+    Term(usize),
+    Exit,
+}
+
+/// Walk the terms of the matches in the tree, in the order that they're printed.
+pub fn pieces<'t>(grammar: &Grammar, root: &'t RuleValue) -> Vec<Piece<'t>> {
+    let mut unparser = Unparser {
+        grammar,
+        pieces: Vec::new(),
+    };
+    unparser.unparse_rule(root);
+    unparser.pieces
+}
+
+struct Unparser<'a, 't> {
+    grammar: &'a Grammar,
+    pieces: Vec<Piece<'t>>,
+}
+
+impl<'t> Unparser<'_, 't> {
+    fn unparse_rule(&mut self, value: &'t RuleValue) {
+        let terms = &self.grammar.get(value.match_id).terms;
+        let mut values = value.values.iter().peekable();
+        self.pieces.push(Piece::Enter(value.match_id));
+
+        for (i, term) in terms.iter().enumerate() {
+            self.pieces.push(Piece::Term(i));
+            while let Some(Value::Error(span)) = values.peek() {
+                self.pieces.push(Piece::Error(span));
+                values.next();
+            }
+
+            match term {
+                Term::Token(token) => {
+                    let span = match values.peek() {
+                        Some(Value::Token(found, span)) if found == token => {
+                            values.next();
+                            Some(span)
+                        }
+                        _ => None,
+                    };
+
+                    self.pieces.push(Piece::Token(*token, span));
+                }
+                Term::Rule(rule) => {
+                    if let Some(Value::Rule(child)) = values.peek() {
+                        values.next();
+                        self.unparse_in_place_of(child, *rule);
+                    }
+                }
+                Term::Group(group, rule) => {
+                    if let Some(Value::Rule(child)) = values.peek() {
+                        values.next();
+                        self.pieces.push(Piece::Open(*group));
+                        self.unparse_in_place_of(child, *rule);
+                        self.pieces.push(Piece::Close(*group));
+                    }
+                }
+            }
+        }
+
+        // Anything that didn't fit the terms, e.g. tokens skipped by error recovery
+        for value in values {
+            match value {
+                Value::Token(token, span) => self.pieces.push(Piece::Token(*token, Some(span))),
+                Value::Rule(child) => self.unparse_rule(child),
+                Value::Error(span) => self.pieces.push(Piece::Error(span)),
+            }
+        }
+
+        self.pieces.push(Piece::Exit);
+    }
+
+    /// Print a value where a term expects the rule, along with any groups that were
+    /// collapsed in between.
+    fn unparse_in_place_of(&mut self, value: &'t RuleValue, rule: Rule) {
+        let inner = self.grammar.get(value.match_id).rule;
+        let groups = collapsed_groups(self.grammar, rule, inner);
+
+        for group in &groups {
+            self.pieces.push(Piece::Open(*group));
+        }
+        self.unparse_rule(value);
+        for group in groups.iter().rev() {
+            self.pieces.push(Piece::Close(*group));
+        }
+    }
+}
+
+/// Find the groups along the shortest chain of single term matches from one rule to another,
+/// outermost first.
+fn collapsed_groups(grammar: &Grammar, from: Rule, to: Rule) -> Vec<Group> {
+    let mut seen = HashSet::from([from]);
+    let mut queue = VecDeque::from([(from, Vec::new())]);
+
+    while let Some((rule, groups)) = queue.pop_front() {
+        if rule == to {
+            return groups;
+        }
+
+        for &id in grammar.get_matches_from_rule(rule) {
+            let (next, group) = match grammar.get(id).terms.as_slice() {
+                [Term::Rule(next)] => (*next, None),
+                [Term::Group(group, next)] => (*next, Some(*group)),
+                _ => continue,
+            };
+
+            if seen.insert(next) {
+                let mut groups = groups.clone();
+                groups.extend(group);
+                queue.push_back((next, groups));
+            }
+        }
+    }
+
+    Vec::new()
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use common::input;
+use msyntax::{
+    counterexample::{counterexamples, CounterexampleOptions},
+    demos::*,
+    lr::table::{classify, GrammarClass},
+    matches::Token,
+    solver::GrammarSolver,
+};
+
+#[test]
+fn every_conflict_of_the_array_grammar_has_an_ambiguous_counterexample() {
+    let grammar = make_array_grammar();
+    let conflicts = GrammarSolver::new(grammar.clone()).conflicts();
+    assert!(!conflicts.is_empty());
+    assert_eq!(classify(&grammar), GrammarClass::NotLr1);
+
+    let found = counterexamples(&grammar, &conflicts, &CounterexampleOptions::default());
+    assert_eq!(found.len(), conflicts.ids().count());
+    for example in &found {
+        assert!(example.is_ambiguous());
+        assert_eq!(example.tokens, input(&[Token::Num]));
+        assert!(example
+            .display(&grammar, &conflicts)
+            .to_string()
+            .contains("counterexample: Start Num Eof"));
+    }
+}
+
+#[test]
+fn explain_lists_every_table() {
+    let solver = GrammarSolver::new(make_statements_grammar());
+    let explanation = solver.explain().to_string();
+
+    for heading in [
+        "max lookahead",
+        "first sets",
+        "follow sets",
+        "seal actions",
+        "wrap sets",
+        "conflicts",
+        "recovery tables",
+    ] {
+        assert!(explanation.contains(heading), "missing {}", heading);
+    }
+}
+
+#[test]
+fn explanations_are_the_same_for_every_solver() {
+    for grammar in [
+        make_calc2_grammar(),
+        make_struct_fn_grammar(),
+        make_array_grammar(),
+        make_statements_grammar(),
+    ] {
+        // Each solver has its own hash maps, so building it again changes their order
+        let first = GrammarSolver::new(grammar.clone()).explain().to_string();
+        for _ in 0..3 {
+            let again = GrammarSolver::new(grammar.clone()).explain().to_string();
+            assert_eq!(again, first);
+        }
+    }
+}
+
+#[test]
+fn follow_sets_include_the_end_of_each_match() {
+    let solver = GrammarSolver::new(make_struct_fn_grammar());
+    let explanation = solver.explain().to_string();
+    let follow_sets = explanation
+        .split("follow sets")
+        .nth(1)
+        .and_then(|rest| rest.split("seal actions").next())
+        .unwrap();
+
+    assert!(follow_sets.contains("S -> Start Expr Eof .\n    none"));
+}
@@ -0,0 +1,11 @@
+//! Helpers shared by the integration tests.
+
+use msyntax::{interpreter::ITokenOrGroup, matches::Token};
+
+/// A whole input of the root rule, with the tokens between `Start` and `Eof`.
+pub fn input(body: &[Token]) -> Vec<ITokenOrGroup> {
+    let mut items = vec![ITokenOrGroup::Token(Token::Start)];
+    items.extend(body.iter().map(|&token| ITokenOrGroup::Token(token)));
+    items.push(ITokenOrGroup::Token(Token::Eof));
+    items
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use common::input;
+use msyntax::{
+    cst::{green::build_green_tree, red::SyntaxNode},
+    demos::*,
+    interpreter::{solve, ITokenOrGroup},
+    matches::{Rule, Token},
+    solver::GrammarSolver,
+};
+
+fn statements() -> Vec<ITokenOrGroup> {
+    input(&[
+        Token::Name,
+        Token::Eq,
+        Token::Num,
+        Token::Semi,
+        Token::Name,
+        Token::Eq,
+        Token::Name,
+        Token::Semi,
+    ])
+}
+
+#[test]
+fn syntax_nodes_know_their_parents_and_ranges() {
+    let grammar = make_statements_grammar();
+    let result = solve(&GrammarSolver::new(grammar), statements());
+
+    let root = SyntaxNode::new_root(build_green_tree(&result.value));
+    assert_eq!(root.rule(), Rule::S);
+    assert_eq!(root.text_range(), 0..10);
+
+    // The first statement is collapsed into the `Stmts` that only holds it
+    let stmts = root.children().next().unwrap();
+    assert_eq!(stmts.rule(), Rule::Stmts);
+    let stmt = stmts.children().next().unwrap();
+    assert_eq!(stmt.text_range(), 1..5);
+    assert_eq!(stmt.ancestors().last().unwrap(), root);
+    assert_eq!(stmt.next_sibling().unwrap().text_range(), 5..9);
+}
@@ -0,0 +1,267 @@
+mod common;
+
+use common::input;
+use msyntax::{
+    demos::*,
+    diagnostics::{collapse_expected, DiagnosticKind, ErrorCounts, ErrorOptions, Found},
+    interpreter::{solve, solve_with_options, ITokenOrGroup, Value},
+    matches::{Grammar, Rule, Symbol, Term, Token},
+    solver::GrammarSolver,
+};
+
+#[test]
+fn expected_symbols_dont_depend_on_hash_order() {
+    let grammar = make_calc2_grammar();
+    let tokens = input(&[Token::Num, Token::Num, Token::Plus]);
+
+    // Every solver gets hash maps with their own seeds, so the tables iterate differently
+    let messages = || {
+        let solver = GrammarSolver::new(grammar.clone());
+        solve(&solver, tokens.clone())
+            .diagnostics
+            .iter()
+            .map(|d| d.display(&grammar).to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let first = messages();
+    for _ in 0..20 {
+        assert_eq!(messages(), first);
+    }
+}
+
+fn messages(grammar: &Grammar, tokens: &[Token]) -> Vec<String> {
+    let solver = GrammarSolver::new(grammar.clone());
+    solve(&solver, input(tokens))
+        .diagnostics
+        .iter()
+        .map(|d| d.display(grammar).to_string())
+        .collect()
+}
+
+#[test]
+fn messages_use_the_names_from_the_grammar() {
+    let mut grammar = make_statements_grammar();
+
+    // The tokens an expression starts with are collapsed into the rule's name
+    assert_eq!(
+        messages(&grammar, &[Token::Name, Token::Eq, Token::Semi]),
+        ["expected expression, found `;` (at token 3)"]
+    );
+    assert_eq!(
+        messages(&grammar, &[Token::Semi]),
+        ["expected identifier or end of input, found `;` (at token 1)"]
+    );
+    assert_eq!(
+        messages(
+            &grammar,
+            &[Token::Name, Token::Eq, Token::Num, Token::Semi, Token::Num]
+        ),
+        ["expected statement or end of input, found number (at token 5)"]
+    );
+
+    // A description replaces the name only when it's the one thing expected
+    grammar.set_description(Rule::Expr, "an expression, such as `1` or `x`");
+    assert_eq!(
+        messages(&grammar, &[Token::Name, Token::Eq, Token::Semi]),
+        ["expected an expression, such as `1` or `x`, found `;` (at token 3)"]
+    );
+}
+
+#[test]
+fn input_after_the_end_is_trailing() {
+    let grammar = make_statements_grammar();
+    let solver = GrammarSolver::new(grammar.clone());
+
+    let mut tokens = input(&[Token::Name, Token::Eq, Token::Num, Token::Semi]);
+    tokens.push(ITokenOrGroup::Token(Token::Num));
+    let result = solve(&solver, tokens);
+
+    let [diagnostic] = result.diagnostics.as_slice() else {
+        panic!("Expected one diagnostic, found {:?}", result.diagnostics);
+    };
+    assert_eq!(
+        diagnostic.kind,
+        DiagnosticKind::Trailing {
+            found: Found::Token(Token::Num)
+        }
+    );
+    assert_eq!(
+        diagnostic.display(&grammar).to_string(),
+        "unexpected number after end of input (at token 6)"
+    );
+}
+
+#[test]
+fn mostly_covered_first_sets_collapse_into_their_rule() {
+    let mut grammar = make_statements_grammar();
+    grammar.add(Rule::Expr, vec![Term::Token(Token::Crate)]);
+    grammar.add(
+        Rule::Expr,
+        vec![Term::Token(Token::Minus), Term::Token(Token::Num)],
+    );
+    let solver = GrammarSolver::new(grammar);
+
+    // Three of the four tokens an expression starts with
+    let expected = [
+        Symbol::Token(Token::Num),
+        Symbol::Token(Token::Name),
+        Symbol::Token(Token::Minus),
+        Symbol::Token(Token::Semi),
+    ];
+    assert_eq!(
+        collapse_expected(&solver, &expected),
+        [Symbol::Rule(Rule::Expr), Symbol::Token(Token::Semi)]
+    );
+
+    // Two of them are too few
+    assert_eq!(
+        collapse_expected(&solver, &expected[1..]),
+        [
+            Symbol::Token(Token::Name),
+            Symbol::Token(Token::Minus),
+            Symbol::Token(Token::Semi),
+        ]
+    );
+}
+
+#[test]
+fn commit_points_keep_the_committed_prefix() {
+    let grammar = make_struct_fn_grammar();
+    let solver = GrammarSolver::new(grammar.clone());
+
+    // `fn` without a name is an invalid function, instead of an error at the `fn`
+    let result = solve(&solver, input(&[Token::Fn]));
+    let messages: Vec<_> = result
+        .diagnostics
+        .iter()
+        .map(|d| d.display(&grammar).to_string())
+        .collect();
+    assert_eq!(
+        messages,
+        ["invalid function: expected Name, found Eof (at token 2)"]
+    );
+
+    let Value::Rule(function) = &result.value.values[1] else {
+        panic!("Expected a function, found {:?}", result.value.values[1]);
+    };
+    assert_eq!(function.rule, Rule::Expr);
+    assert!(matches!(function.values[1], Value::Token(Token::Fn, _)));
+    assert!(matches!(function.values[2], Value::Error(_)));
+
+    // Tokens that don't fit are skipped, and the function resumes after them
+    let result = solve(&solver, input(&[Token::Fn, Token::Num, Token::Name]));
+    assert_eq!(result.diagnostics.len(), 1);
+    let Value::Rule(function) = &result.value.values[1] else {
+        panic!("Expected a function, found {:?}", result.value.values[1]);
+    };
+    assert!(matches!(function.values[2], Value::Token(Token::Name, _)));
+}
+
+/// Five statements without an expression, with an error every three tokens.
+fn five_errors() -> Vec<ITokenOrGroup> {
+    let statement = [Token::Name, Token::Eq, Token::Semi];
+    input(&statement.repeat(5))
+}
+
+#[test]
+fn errors_past_the_limit_are_only_counted() {
+    let solver = GrammarSolver::new(make_statements_grammar());
+    let parse = |options| solve_with_options(&solver, five_errors(), options);
+
+    let all = parse(ErrorOptions::default());
+    assert_eq!(
+        all.error_counts,
+        ErrorCounts {
+            reported: 5,
+            suppressed: 0,
+            over_limit: 0
+        }
+    );
+
+    let limited = parse(ErrorOptions {
+        max_errors: Some(2),
+        ..ErrorOptions::default()
+    });
+    assert_eq!(
+        limited.error_counts,
+        ErrorCounts {
+            reported: 2,
+            suppressed: 0,
+            over_limit: 3
+        }
+    );
+    assert_eq!(limited.diagnostics, all.diagnostics[..2]);
+    // Only the reporting is limited, not the parse
+    assert_eq!(limited.value, all.value);
+}
+
+#[test]
+fn errors_close_to_the_previous_one_are_cascades() {
+    let solver = GrammarSolver::new(make_statements_grammar());
+    let parse = |cascade_distance| {
+        let options = ErrorOptions {
+            cascade_distance,
+            ..ErrorOptions::default()
+        };
+        solve_with_options(&solver, five_errors(), options).error_counts
+    };
+
+    assert_eq!(parse(2).suppressed, 0);
+    assert_eq!(
+        parse(3),
+        ErrorCounts {
+            reported: 1,
+            suppressed: 4,
+            over_limit: 0
+        }
+    );
+
+    // Suppressed errors don't count towards the limit
+    let options = ErrorOptions {
+        max_errors: Some(1),
+        cascade_distance: 3,
+        ..ErrorOptions::default()
+    };
+    let counts = solve_with_options(&solver, five_errors(), options).error_counts;
+    assert_eq!(counts.over_limit, 0);
+}
+
+#[test]
+fn only_the_first_error_of_a_group_is_reported() {
+    let solver = GrammarSolver::new(make_calc_grammar());
+
+    // `(1 + * 1 + * 1) + (1 + * 1 + * 1)`, with two errors in each group
+    let group = ITokenOrGroup::Group(input(&[
+        Token::Num,
+        Token::Plus,
+        Token::Star,
+        Token::Num,
+        Token::Plus,
+        Token::Star,
+        Token::Num,
+    ]));
+    let tokens = vec![
+        ITokenOrGroup::Token(Token::Start),
+        group.clone(),
+        ITokenOrGroup::Token(Token::Plus),
+        group,
+        ITokenOrGroup::Token(Token::Eof),
+    ];
+
+    let options = ErrorOptions {
+        one_error_per_group: true,
+        ..ErrorOptions::default()
+    };
+    let result = solve_with_options(&solver, tokens, options);
+    assert_eq!(
+        result.error_counts,
+        ErrorCounts {
+            reported: 2,
+            suppressed: 2,
+            over_limit: 0
+        }
+    );
+    let positions: Vec<_> = result.diagnostics.iter().map(|d| d.token_index).collect();
+    assert_eq!(positions, [4, 14]);
+}
@@ -0,0 +1,73 @@
+use msyntax::{
+    demos::*,
+    differential::{
+        check_grammar, random_grammar, DiffOptions, MismatchKind, RandomGrammarOptions,
+    },
+    generate::Rng,
+    solver::GrammarSolver,
+};
+
+/// Random grammars from seed 0 that have sentences the interpreter rejects. It takes the
+/// first first set that matches, and these grammars need more lookahead than that to pick
+/// a match, so the solver reports conflicts for each of them.
+const KNOWN_FAILURES: [usize; 7] = [2, 4, 10, 11, 17, 18, 19];
+
+#[test]
+fn the_interpreter_agrees_with_the_reference_on_the_demos() {
+    let options = DiffOptions::default();
+    for grammar in [
+        make_calc_grammar(),
+        make_calc2_grammar(),
+        make_struct_fn_grammar(),
+        make_array_grammar(),
+        make_statements_grammar(),
+    ] {
+        let report = check_grammar(&grammar, &options);
+        assert!(report.is_ok(), "{}\n{}", report, grammar);
+    }
+}
+
+#[test]
+fn the_interpreter_agrees_with_the_reference_on_random_grammars() {
+    let options = DiffOptions::default();
+    let mut rng = Rng::new(0);
+    for i in 0..20 {
+        let grammar = random_grammar(&mut rng, &RandomGrammarOptions::default());
+        let report = check_grammar(&grammar, &options);
+
+        if !KNOWN_FAILURES.contains(&i) {
+            assert!(report.is_ok(), "random {}: {}\n{}", i, report, grammar);
+            continue;
+        }
+
+        // Known failures are still listed, so that fixing one shows up here
+        assert!(!report.is_ok(), "random {} passes now", i);
+        assert!(report.solver_panic.is_none());
+        assert!(!GrammarSolver::new(grammar).conflicts().is_empty());
+        for mismatch in &report.mismatches {
+            assert!(matches!(
+                mismatch.kind,
+                MismatchKind::Acceptance {
+                    interpreter: false,
+                    reference: true,
+                }
+            ));
+        }
+    }
+}
+
+#[test]
+fn reports_are_the_same_for_every_run() {
+    let options = DiffOptions::default();
+    // Random grammar 2 has ambiguous inputs and ties between first sets and wrap actions,
+    // which used to depend on the order of hash maps in the solver
+    let mut rng = Rng::new(0);
+    let grammar = (0..3)
+        .map(|_| random_grammar(&mut rng, &RandomGrammarOptions::default()))
+        .last()
+        .unwrap();
+    let first = check_grammar(&grammar, &options).to_string();
+    for _ in 0..3 {
+        assert_eq!(check_grammar(&grammar, &options).to_string(), first);
+    }
+}
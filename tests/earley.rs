@@ -0,0 +1,42 @@
+use msyntax::{
+    demos::*, earley::EarleyParser, generate::sentences, interpreter::solve, solver::GrammarSolver,
+};
+
+#[test]
+fn earley_agrees_with_the_interpreter_on_calc2() {
+    let grammar = make_calc2_grammar();
+    let solver = GrammarSolver::new(grammar.clone());
+    let earley = EarleyParser::new(grammar.clone());
+
+    let inputs = sentences(&grammar, 7, 20).items;
+    assert!(!inputs.is_empty());
+
+    for tokens in inputs {
+        let expected = solve(&solver, tokens.clone());
+        assert!(expected.diagnostics.is_empty());
+        assert_eq!(earley.parse(tokens), expected);
+    }
+}
+
+#[test]
+fn forests_contain_the_tree_of_the_interpreter() {
+    for grammar in [make_array_grammar(), make_statements_grammar()] {
+        let solver = GrammarSolver::new(grammar.clone());
+        let earley = EarleyParser::new(grammar.clone());
+
+        let mut ambiguous = 0;
+        for tokens in sentences(&grammar, 7, 20).items {
+            let expected = solve(&solver, tokens.clone()).value;
+            let forest = earley.parse_forest(tokens, None).forest;
+
+            // The first tree is only the interpreter's when there's no other choice
+            assert!(forest.trees(&grammar, 100).contains(&expected));
+            if forest.is_ambiguous() {
+                ambiguous += 1;
+            } else {
+                assert_eq!(forest.first_tree(&grammar), Some(expected));
+            }
+        }
+        assert!(ambiguous > 0);
+    }
+}
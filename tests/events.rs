@@ -0,0 +1,119 @@
+mod common;
+
+use std::cell::Cell;
+
+use common::input;
+use msyntax::{
+    demos::*,
+    diagnostics::ErrorOptions,
+    generate::sentences,
+    interpreter::{
+        events::{replay_events, solve_events, stream_events, Event},
+        solve, BufferedSource, ITokenOrGroup, ValueBuilder,
+    },
+    matches::Token,
+    solver::GrammarSolver,
+};
+
+fn statements() -> Vec<ITokenOrGroup> {
+    input(&[
+        Token::Name,
+        Token::Eq,
+        Token::Num,
+        Token::Semi,
+        Token::Name,
+        Token::Eq,
+        Token::Name,
+        Token::Semi,
+    ])
+}
+
+#[test]
+fn replayed_events_build_the_same_tree_as_solve() {
+    let grammar = make_statements_grammar();
+    let solver = GrammarSolver::new(grammar);
+    let expected = solve(&solver, statements());
+
+    let parse = solve_events(
+        &solver,
+        BufferedSource::new(statements()),
+        None,
+        ErrorOptions::default(),
+    );
+    assert_eq!(parse.diagnostics, expected.diagnostics);
+    let mut builder = ValueBuilder::new();
+    replay_events(parse.events, &mut builder);
+    assert_eq!(builder.finish(), expected.value);
+}
+
+#[test]
+fn streamed_events_build_the_same_tree_as_solve() {
+    for grammar in [
+        make_calc_grammar(),
+        make_calc2_grammar(),
+        make_struct_fn_grammar(),
+        make_array_grammar(),
+        make_statements_grammar(),
+    ] {
+        let solver = GrammarSolver::new(grammar.clone());
+
+        let mut inputs = sentences(&grammar, 7, 20).items;
+        // Errors make the interpreter abandon and collapse nodes
+        inputs.push(input(&[Token::Num, Token::Plus]));
+        inputs.push(input(&[Token::Name, Token::Eq, Token::Semi, Token::Num]));
+        inputs.push(input(&[Token::Fn, Token::Semi, Token::Semi]));
+
+        for tokens in inputs {
+            let expected = solve(&solver, tokens.clone());
+
+            let mut events = Vec::new();
+            let (diagnostics, _) = stream_events(
+                &solver,
+                BufferedSource::new(tokens),
+                None,
+                ErrorOptions::default(),
+                |event| events.push(event),
+            );
+            assert_eq!(diagnostics, expected.diagnostics);
+
+            let mut builder = ValueBuilder::new();
+            replay_events(events, &mut builder);
+            assert_eq!(builder.finish(), expected.value);
+        }
+    }
+}
+
+#[test]
+fn events_are_streamed_before_the_input_ends() {
+    let solver = GrammarSolver::new(make_statements_grammar());
+    let statement = [Token::Name, Token::Eq, Token::Num, Token::Semi];
+    let tokens = input(&statement.repeat(10));
+    let len = tokens.len();
+
+    // The number of tokens the interpreter read when each event was passed on
+    let read = Cell::new(0);
+    let source = tokens.into_iter().inspect(|_| read.set(read.get() + 1));
+    let mut read_at = Vec::new();
+    stream_events(
+        &solver,
+        BufferedSource::new(source),
+        None,
+        ErrorOptions::default(),
+        |event| read_at.push((event, read.get())),
+    );
+
+    // Every token comes out as soon as the interpreter has looked past it
+    let lookahead = solver.max_lookahead();
+    let mut tokens = 0;
+    for (event, read) in &read_at {
+        if let Event::Token(_, span) = event {
+            assert!(
+                *read <= span.tokens.end + lookahead,
+                "{:?} was held back",
+                event
+            );
+            tokens += 1;
+        }
+    }
+    assert_eq!(tokens, len);
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use common::input;
+use msyntax::{
+    demos::*,
+    format::{format, Doc, FormatOptions},
+    interpreter::solve,
+    matches::Token,
+    solver::GrammarSolver,
+    unparse::TextOptions,
+};
+
+fn calc_text() -> TextOptions<'static> {
+    let mut text = TextOptions::new();
+    text.set_text(Token::Start, "");
+    text.set_text(Token::Eof, "");
+    text.set_text(Token::Num, "1");
+    text.set_text(Token::Plus, "+");
+    text.set_text(Token::Star, "*");
+    text
+}
+
+#[test]
+fn groups_are_broken_when_they_dont_fit() {
+    let doc = Doc::group(Doc::Concat(vec![
+        Doc::text("f("),
+        Doc::nest(
+            4,
+            Doc::Concat(vec![Doc::soft_line(), Doc::text("argument")]),
+        ),
+        Doc::soft_line(),
+        Doc::text(")"),
+    ]));
+
+    assert_eq!(doc.render(80), "f(argument)");
+    assert_eq!(doc.render(8), "f(\n    argument\n)");
+}
+
+#[test]
+fn format_breaks_lines_where_the_grammar_says() {
+    let grammar = make_calc_grammar();
+    let result = solve(
+        &GrammarSolver::new(grammar.clone()),
+        input(&[Token::Num, Token::Plus, Token::Num, Token::Star, Token::Num]),
+    );
+
+    let mut options = FormatOptions {
+        text: calc_text(),
+        ..FormatOptions::default()
+    };
+    assert_eq!(format(&grammar, &result.value, &options), "1 + 1 * 1");
+
+    options.width = 6;
+    assert_eq!(format(&grammar, &result.value, &options), "1\n+ 1 * 1");
+}
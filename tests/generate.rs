@@ -0,0 +1,85 @@
+mod common;
+
+use common::input;
+use msyntax::{
+    demos::*,
+    generate::{sentences, GenerateOptions, Generator, Rng},
+    interpreter::{solve, ITokenOrGroup},
+    matches::{Rule, Term, Token},
+    solver::GrammarSolver,
+};
+
+#[test]
+fn generated_sentences_parse_without_errors() {
+    let grammar = make_calc2_grammar();
+    let solver = GrammarSolver::new(grammar.clone());
+    let generator = Generator::new(&grammar, GenerateOptions::default());
+
+    let mut rng = Rng::new(7);
+    for _ in 0..50 {
+        let tokens = generator.sentence(&mut rng).unwrap();
+        let len: usize = tokens.iter().map(|item| item.flat_len()).sum();
+        assert!(len <= generator.options().max_len);
+        assert!(solve(&solver, tokens).diagnostics.is_empty());
+    }
+}
+
+#[test]
+fn generating_is_deterministic_for_a_seed() {
+    let grammar = make_statements_grammar();
+    let generator = Generator::new(&grammar, GenerateOptions::default());
+
+    let generate = |seed| {
+        let mut rng = Rng::new(seed);
+        (0..20)
+            .map(|_| generator.sentence(&mut rng))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(generate(3), generate(3));
+}
+
+#[test]
+fn matches_with_no_weight_are_never_picked() {
+    let grammar = make_statements_grammar();
+    let mut options = GenerateOptions::default();
+    for &id in grammar.get_matches_from_rule(Rule::Expr) {
+        if grammar.get(id).terms == [Term::Token(Token::Name)] {
+            options.set_weight(id, 0.0);
+        }
+    }
+    let generator = Generator::new(&grammar, options);
+
+    let mut rng = Rng::new(1);
+    for _ in 0..50 {
+        let expr = generator.rule_sentence(Rule::Expr, &mut rng).unwrap();
+        assert_eq!(expr, [ITokenOrGroup::Token(Token::Num)]);
+    }
+}
+
+#[test]
+fn sentences_are_shortest_first() {
+    let grammar = make_array_grammar();
+    let found = sentences(&grammar, 4, 100);
+
+    assert!(!found.truncated);
+    assert_eq!(
+        found.items,
+        [
+            input(&[]),
+            input(&[Token::Num]),
+            input(&[Token::Num, Token::Num]),
+        ]
+    );
+}
+
+#[test]
+fn sentences_past_the_limit_are_reported() {
+    let grammar = make_calc2_grammar();
+    let all = sentences(&grammar, 5, 100);
+    assert!(!all.truncated);
+
+    let sample = sentences(&grammar, 5, 2);
+    assert!(sample.truncated);
+    assert!(sample.items.len() < all.items.len());
+    assert!(sample.items.iter().all(|item| all.items.contains(item)));
+}
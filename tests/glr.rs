@@ -0,0 +1,123 @@
+mod common;
+
+use common::input;
+use msyntax::{
+    glr::{filters::Filters, GlrParser},
+    interpreter::{RuleValue, Value},
+    matches::{Grammar, MatchId, Rule, Term, Token},
+};
+
+/// `S -> Start Expr Eof` with `Expr -> Expr + Expr | Expr * Expr | Num`, which is ambiguous
+/// without filters. Returns the ids of the addition and the multiplication too.
+fn ambiguous_calc_grammar() -> (Grammar, MatchId, MatchId) {
+    let mut grammar = Grammar::new();
+    grammar.add(
+        Rule::S,
+        vec![
+            Term::Token(Token::Start),
+            Term::Rule(Rule::Expr),
+            Term::Token(Token::Eof),
+        ],
+    );
+    let add = grammar.add(
+        Rule::Expr,
+        vec![
+            Term::Rule(Rule::Expr),
+            Term::Token(Token::Plus),
+            Term::Rule(Rule::Expr),
+        ],
+    );
+    let mul = grammar.add(
+        Rule::Expr,
+        vec![
+            Term::Rule(Rule::Expr),
+            Term::Token(Token::Star),
+            Term::Rule(Rule::Expr),
+        ],
+    );
+    grammar.add(Rule::Expr, vec![Term::Token(Token::Num)]);
+
+    (grammar, add, mul)
+}
+
+fn rule(value: &Value) -> &RuleValue {
+    match value {
+        Value::Rule(rule) => rule,
+        _ => panic!("Expected a rule, found {:?}", value),
+    }
+}
+
+#[test]
+fn glr_keeps_every_tree_of_an_ambiguous_input() {
+    let (grammar, _, _) = ambiguous_calc_grammar();
+    let parse = GlrParser::new(grammar).parse(input(&[
+        Token::Num,
+        Token::Plus,
+        Token::Num,
+        Token::Star,
+        Token::Num,
+    ]));
+
+    assert!(parse.diagnostics.is_empty());
+    assert!(parse.forest.is_ambiguous());
+    assert_eq!(parse.forest.count_trees(), 2);
+}
+
+#[test]
+fn filters_pick_the_tree_with_priorities_and_associativity() {
+    let (grammar, add, mul) = ambiguous_calc_grammar();
+    let parser = GlrParser::new(grammar.clone());
+
+    let mut filters = Filters::new();
+    filters.set_priority(mul, add);
+    filters.set_left_assoc(&grammar, add);
+    filters.set_left_assoc(&grammar, mul);
+
+    // 1 + 2 * 3 + 4 has five trees, and only ((1 + (2 * 3)) + 4) is left
+    let mut parse = parser.parse(input(&[
+        Token::Num,
+        Token::Plus,
+        Token::Num,
+        Token::Star,
+        Token::Num,
+        Token::Plus,
+        Token::Num,
+    ]));
+    assert_eq!(parse.forest.count_trees(), 5);
+    filters.apply(&grammar, &mut parse.forest);
+    assert_eq!(parse.forest.count_trees(), 1);
+
+    let tree = parse.forest.first_tree(&grammar).unwrap();
+    let expr = rule(&tree.values[1]);
+    assert_eq!(expr.match_id, add);
+    let lhs = rule(&expr.values[0]);
+    assert_eq!(lhs.match_id, add);
+    assert_eq!(rule(&lhs.values[2]).match_id, mul);
+}
+
+#[test]
+fn rejecting_a_match_removes_its_trees() {
+    let (grammar, add, mul) = ambiguous_calc_grammar();
+    let parser = GlrParser::new(grammar.clone());
+
+    let mut filters = Filters::new();
+    filters.reject(mul);
+    let mut parse = parser.parse(input(&[Token::Num, Token::Star, Token::Num]));
+    filters.apply(&grammar, &mut parse.forest);
+    assert_eq!(parse.forest.root(), None);
+
+    // Avoiding a match only removes it where there's another choice
+    let mut filters = Filters::new();
+    filters.avoid(add);
+    let mut parse = parser.parse(input(&[
+        Token::Num,
+        Token::Plus,
+        Token::Num,
+        Token::Star,
+        Token::Num,
+    ]));
+    filters.apply(&grammar, &mut parse.forest);
+    assert_eq!(parse.forest.count_trees(), 1);
+    let tree = parse.forest.first_tree(&grammar).unwrap();
+    assert_eq!(rule(&tree.values[1]).match_id, mul);
+}
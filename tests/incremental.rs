@@ -0,0 +1,119 @@
+mod common;
+
+use common::input;
+use msyntax::{
+    demos::*,
+    diagnostics::ErrorOptions,
+    interpreter::{
+        incremental::{IncrementalParse, TokenEdit},
+        solve_with_spans, ITokenOrGroup,
+    },
+    matches::Token,
+    solver::GrammarSolver,
+};
+
+fn group(body: &[Token]) -> ITokenOrGroup {
+    ITokenOrGroup::Group(input(body))
+}
+
+/// `(1 + 1) * (1) + (1 * 1)`, with a byte range of one byte per token.
+fn groups_input(middle: &[Token]) -> Vec<ITokenOrGroup> {
+    vec![
+        ITokenOrGroup::Token(Token::Start),
+        group(&[Token::Num, Token::Plus, Token::Num]),
+        ITokenOrGroup::Token(Token::Star),
+        group(middle),
+        ITokenOrGroup::Token(Token::Plus),
+        group(&[Token::Num, Token::Star, Token::Num]),
+        ITokenOrGroup::Token(Token::Eof),
+    ]
+}
+
+fn byte_ranges(tokens: &[ITokenOrGroup]) -> Vec<std::ops::Range<usize>> {
+    let len: usize = tokens.iter().map(|item| item.flat_len()).sum();
+    (0..len).map(|i| i..i + 1).collect()
+}
+
+#[test]
+fn reparsing_an_edit_gives_the_same_result_as_a_full_parse() {
+    let solver = GrammarSolver::new(make_calc_grammar());
+    let options = ErrorOptions::default();
+
+    let before = groups_input(&[Token::Num]);
+    let parse = IncrementalParse::new(
+        &solver,
+        before.clone(),
+        Some(&byte_ranges(&before)),
+        options,
+    )
+    .unwrap();
+    assert_eq!(parse.parsed_groups(), 3);
+    assert_eq!(parse.reused_groups(), 0);
+
+    // The middle group's tokens start at 8, and `1` becomes `1 + 1`, then `1 + * 1` which
+    // has an error, and back again
+    let edits = [
+        vec![Token::Num, Token::Plus, Token::Num],
+        vec![Token::Num, Token::Plus, Token::Star, Token::Num],
+        vec![Token::Num],
+    ];
+
+    let mut parse = parse;
+    let mut old_len = 1;
+    for middle in edits {
+        let after = groups_input(&middle);
+        let ranges = byte_ranges(&after);
+        let edit = TokenEdit {
+            range: 8..8 + old_len,
+            new_len: middle.len(),
+        };
+
+        let reparse = parse
+            .reparse(&solver, after.clone(), Some(&ranges), &edit, options)
+            .unwrap();
+        let full = solve_with_spans(&solver, after, &ranges, options).unwrap();
+        assert_eq!(reparse.result, full);
+
+        // Only the edited group is parsed again
+        assert_eq!(reparse.parsed_groups(), 1);
+        assert_eq!(reparse.reused_groups(), 2);
+
+        parse = reparse;
+        old_len = middle.len();
+    }
+}
+
+#[test]
+fn reused_groups_move_after_an_edit_before_them() {
+    let solver = GrammarSolver::new(make_calc_grammar());
+    let options = ErrorOptions::default();
+
+    let before = groups_input(&[Token::Num, Token::Plus]);
+    let parse = IncrementalParse::new(
+        &solver,
+        before.clone(),
+        Some(&byte_ranges(&before)),
+        options,
+    )
+    .unwrap();
+    assert_eq!(parse.result.diagnostics.len(), 1);
+
+    // `(1 + 1)` at the start becomes `1`, which moves the group with the error and the one
+    // after it three tokens back
+    let mut after = before.clone();
+    after.splice(1..2, [ITokenOrGroup::Token(Token::Num)]);
+    let ranges = byte_ranges(&after);
+    let edit = TokenEdit {
+        range: 1..6,
+        new_len: 1,
+    };
+
+    let reparse = parse
+        .reparse(&solver, after.clone(), Some(&ranges), &edit, options)
+        .unwrap();
+    let full = solve_with_spans(&solver, after, &ranges, options).unwrap();
+    assert_eq!(reparse.result, full);
+    assert_eq!(reparse.result.diagnostics[0].token_index, 6);
+    assert_eq!(reparse.parsed_groups(), 0);
+    assert_eq!(reparse.reused_groups(), 2);
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use common::input;
+use msyntax::{
+    demos::*,
+    generate::sentences,
+    interpreter::solve,
+    lr::{
+        table::{classify, GrammarClass, LrTable},
+        LrParser,
+    },
+    matches::Token,
+    solver::GrammarSolver,
+};
+
+#[test]
+fn lr_agrees_with_the_interpreter_on_calc2() {
+    let grammar = make_calc2_grammar();
+    let solver = GrammarSolver::new(grammar.clone());
+    let lr = LrParser::new(grammar.clone());
+
+    let inputs = sentences(&grammar, 7, 20).items;
+    assert!(!inputs.is_empty());
+
+    for tokens in inputs {
+        let expected = solve(&solver, tokens.clone());
+        assert!(expected.diagnostics.is_empty());
+        assert_eq!(lr.parse(tokens), expected);
+    }
+}
+
+#[test]
+fn lr_reports_errors_like_the_interpreter() {
+    let grammar = make_calc_grammar();
+    let tokens = input(&[Token::Num, Token::Plus]);
+
+    let expected = solve(&GrammarSolver::new(grammar.clone()), tokens.clone());
+    assert!(!expected.diagnostics.is_empty());
+    assert!(!LrParser::new(grammar).parse(tokens).diagnostics.is_empty());
+}
+
+#[test]
+fn calc_grammars_are_lalr_without_conflicts() {
+    for grammar in [
+        make_calc_grammar(),
+        make_calc2_grammar(),
+        make_struct_fn_grammar(),
+    ] {
+        assert!(GrammarSolver::new(grammar.clone()).conflicts().is_empty());
+        assert!(LrTable::lalr(&grammar).conflicts().is_empty());
+        assert_eq!(classify(&grammar), GrammarClass::Lalr1);
+    }
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use common::input;
+use msyntax::{
+    demos::*, generate::sentences, interpreter::solve, matches::Token, peg::PegParser,
+    solver::GrammarSolver,
+};
+
+#[test]
+fn peg_agrees_with_the_interpreter_on_calc2() {
+    let grammar = make_calc2_grammar();
+    let solver = GrammarSolver::new(grammar.clone());
+    let peg = PegParser::new(grammar.clone());
+
+    let inputs = sentences(&grammar, 7, 20).items;
+    assert!(!inputs.is_empty());
+
+    for tokens in inputs {
+        let expected = solve(&solver, tokens.clone());
+        assert!(expected.diagnostics.is_empty());
+        assert_eq!(peg.parse(tokens), expected);
+    }
+}
+
+#[test]
+fn peg_reports_errors_like_the_interpreter() {
+    let grammar = make_calc_grammar();
+    let tokens = input(&[Token::Num, Token::Plus]);
+
+    let expected = solve(&GrammarSolver::new(grammar.clone()), tokens.clone());
+    assert!(!expected.diagnostics.is_empty());
+    assert!(!PegParser::new(grammar).parse(tokens).diagnostics.is_empty());
+}
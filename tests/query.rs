@@ -0,0 +1,36 @@
+mod common;
+
+use common::input;
+use msyntax::{
+    demos::*,
+    interpreter::solve,
+    matches::Token,
+    query::{NodeRef, Query},
+    solver::GrammarSolver,
+};
+
+#[test]
+fn query_captures_the_fields_of_every_match() {
+    let grammar = make_calc2_grammar();
+    let result = solve(
+        &GrammarSolver::new(grammar.clone()),
+        input(&[Token::Num, Token::Plus, Token::Num, Token::Plus, Token::Num]),
+    );
+
+    let query = Query::new(&grammar, "(Add lhs:_ @lhs op:_ rhs:Num @rhs)").unwrap();
+    assert_eq!(query.capture_names(), ["lhs", "rhs"]);
+    let rhs = query.capture_index("rhs").unwrap();
+
+    let matches = query.matches(&result.value);
+    assert_eq!(matches.len(), 2);
+    for m in &matches {
+        let capture = m.captures.iter().find(|c| c.index == rhs).unwrap();
+        assert!(matches!(
+            capture.node,
+            NodeRef::Token(Token::Num, _) | NodeRef::Rule(_)
+        ));
+    }
+
+    assert!(Query::new(&grammar, "(Add lhs:").is_err());
+    assert!(Query::new(&grammar, "(NotARule)").is_err());
+}
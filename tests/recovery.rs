@@ -0,0 +1,112 @@
+mod common;
+
+use std::ops::Range;
+
+use common::input;
+use msyntax::{
+    demos::*,
+    diagnostics::DiagnosticKind,
+    generate::Rng,
+    interpreter::{solve, RuleValue, Value},
+    matches::{Grammar, Rule, Term, Token},
+    solver::GrammarSolver,
+};
+
+#[test]
+fn contexts_of_recovery_matches_dont_panic() {
+    let solver = GrammarSolver::new(make_statements_grammar());
+
+    let result = solve(&solver, input(&[Token::Num]));
+    assert_eq!(result.diagnostics.len(), 1);
+
+    // Random tokens between `Start` and `Eof`, which mostly go through recovery
+    let alphabet = [Token::Name, Token::Eq, Token::Num, Token::Semi];
+    let mut rng = Rng::new(0);
+    for _ in 0..500 {
+        let len = rng.below(8);
+        let body: Vec<Token> = (0..len).map(|_| *rng.pick(&alphabet)).collect();
+        let result = solve(&solver, input(&body));
+        assert_eq!(result.value.span.tokens, 0..len + 2);
+    }
+}
+
+#[test]
+fn rule_match_indexes_follow_the_visible_matches() {
+    let mut grammar = Grammar::new();
+    grammar.add(Rule::Expr, vec![Term::Token(Token::Num)]);
+    grammar.add_recovery(
+        Rule::Expr,
+        vec![Term::Token(Token::Plus)],
+        "Expected a number",
+    );
+    grammar.add(Rule::Expr, vec![Term::Token(Token::Name)]);
+
+    for grammar in [grammar.clone(), grammar.with_recovery_matches()] {
+        for (i, &id) in grammar.get_matches_from_rule(Rule::Expr).iter().enumerate() {
+            assert_eq!(grammar.get_rule_match_index(id), i);
+        }
+    }
+}
+
+/// The values that were parsed with a recovery match, outermost first.
+fn recovered(value: &RuleValue) -> Vec<&RuleValue> {
+    let mut found = Vec::new();
+    if value.is_error {
+        found.push(value);
+    }
+    for child in &value.values {
+        if let Value::Rule(rule) = child {
+            found.extend(recovered(rule));
+        }
+    }
+    found
+}
+
+#[test]
+fn recovery_matches_fire_after_a_rule_term() {
+    let grammar = make_statements_grammar();
+    let solver = GrammarSolver::new(grammar.clone());
+
+    // The mistake comes right after `Expr`, while `Stmt` is below it on the stack
+    let cases: [(&[Token], &str, Range<usize>); 3] = [
+        (
+            &[Token::Name, Token::Eq, Token::Num],
+            "missing semicolon after statement (at token 4)",
+            1..4,
+        ),
+        (
+            &[
+                Token::Name,
+                Token::Eq,
+                Token::Num,
+                Token::Name,
+                Token::Eq,
+                Token::Num,
+                Token::Semi,
+            ],
+            "missing semicolon after statement (at token 4)",
+            1..4,
+        ),
+        (
+            &[Token::Name, Token::Num, Token::Semi],
+            "missing `=` in assignment (at token 1)",
+            1..4,
+        ),
+    ];
+
+    for (body, message, tokens) in cases {
+        let result = solve(&solver, input(body));
+        assert_eq!(result.diagnostics.len(), 1, "{:?}", body);
+        let diagnostic = &result.diagnostics[0];
+        assert!(matches!(
+            diagnostic.kind,
+            DiagnosticKind::RecoveryMatch { .. }
+        ));
+        assert_eq!(diagnostic.display(&grammar).to_string(), message);
+
+        let recovered = recovered(&result.value);
+        assert_eq!(recovered.len(), 1);
+        assert!(grammar.is_recovery_match(recovered[0].match_id));
+        assert_eq!(recovered[0].span.tokens, tokens);
+    }
+}
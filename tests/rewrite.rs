@@ -0,0 +1,83 @@
+mod common;
+
+use common::input;
+use msyntax::{
+    demos::*,
+    interpreter::{solve, ITokenOrGroup},
+    matches::Token,
+    rewrite::{SearchReplace, Template},
+    solver::GrammarSolver,
+    unparse::unparse_tokens,
+};
+
+fn template(solver: &GrammarSolver, body: &[Token], names: &[&str]) -> Template {
+    Template::new(solver, input(body), names).unwrap()
+}
+
+fn flat(tokens: &[ITokenOrGroup]) -> Vec<Token> {
+    tokens
+        .iter()
+        .map(|item| match item {
+            ITokenOrGroup::Token(token) => *token,
+            ITokenOrGroup::Group(_) => panic!("Expected a token"),
+        })
+        .collect()
+}
+
+#[test]
+fn search_and_replace_swaps_the_operands() {
+    let grammar = make_calc2_grammar();
+    let holes = GrammarSolver::new(grammar.with_holes());
+
+    let pattern = template(
+        &holes,
+        &[Token::Hole, Token::Plus, Token::Hole],
+        &["x", "y"],
+    );
+    let replacement = template(
+        &holes,
+        &[Token::Hole, Token::Plus, Token::Hole],
+        &["y", "x"],
+    );
+    assert_eq!(pattern.names(), ["x", "y"]);
+    let rewrite = SearchReplace::new(pattern, replacement).unwrap();
+
+    let tokens = input(&[Token::Num, Token::Plus, Token::Num, Token::Star, Token::Num]);
+    let result = solve(&GrammarSolver::new(grammar.clone()), tokens);
+    assert_eq!(rewrite.pattern().find(&result.value).len(), 1);
+
+    let rewritten = rewrite.rewrite(&result.value);
+    assert_eq!(
+        unparse_tokens(&grammar, &rewritten),
+        flat(&input(&[
+            Token::Num,
+            Token::Star,
+            Token::Num,
+            Token::Plus,
+            Token::Num,
+        ]))
+    );
+}
+
+#[test]
+fn templates_need_a_grammar_with_holes_and_a_name_for_each_hole() {
+    let grammar = make_calc2_grammar();
+    let plain = GrammarSolver::new(grammar.clone());
+    assert!(Template::new(&plain, input(&[Token::Num]), &[]).is_err());
+
+    let holes = GrammarSolver::new(grammar.with_holes());
+    assert!(Template::new(&holes, input(&[Token::Hole]), &[]).is_err());
+
+    let pattern = template(&holes, &[Token::Hole], &["x"]);
+    let replacement = template(&holes, &[Token::Hole], &["y"]);
+    assert!(SearchReplace::new(pattern, replacement).is_err());
+}
+
+#[test]
+fn hole_tables_are_only_built_for_grammars_with_holes() {
+    let grammar = make_calc2_grammar();
+    assert!(GrammarSolver::new(grammar.clone()).hole_sets().is_none());
+    assert!(GrammarSolver::new(grammar.with_holes())
+        .hole_sets()
+        .is_some());
+}
@@ -0,0 +1,37 @@
+mod common;
+
+use common::input;
+use msyntax::{
+    demos::*,
+    interpreter::solve,
+    matches::Token,
+    serialize::{json, sexpr},
+    solver::GrammarSolver,
+};
+
+#[test]
+fn dumps_read_back_into_the_same_tree() {
+    let grammar = make_statements_grammar();
+    let result = solve(
+        &GrammarSolver::new(grammar.clone()),
+        input(&[
+            Token::Name,
+            Token::Eq,
+            Token::Num,
+            Token::Semi,
+            Token::Name,
+            Token::Eq,
+            Token::Name,
+            Token::Semi,
+        ]),
+    );
+
+    let dump = sexpr::to_sexpr(&result.value);
+    assert_eq!(sexpr::read_sexpr(&grammar, &dump), Ok(result.value.clone()));
+
+    let dump = json::to_json(&result.value);
+    assert_eq!(json::read_json(&grammar, &dump), Ok(result.value.clone()));
+    assert!(json::result_to_json(&result, &grammar).contains("\"diagnostics\""));
+
+    assert!(sexpr::read_sexpr(&grammar, "(Nope #0 0..1)").is_err());
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use common::input;
+use msyntax::{
+    demos::*,
+    diagnostics::ErrorOptions,
+    interpreter::{solve, solve_into, BufferedSource, ITokenOrGroup, ValueBuilder},
+    matches::Token,
+    solver::GrammarSolver,
+};
+
+fn statements() -> Vec<ITokenOrGroup> {
+    input(&[
+        Token::Name,
+        Token::Eq,
+        Token::Num,
+        Token::Semi,
+        Token::Name,
+        Token::Eq,
+        Token::Name,
+        Token::Semi,
+    ])
+}
+
+#[test]
+fn value_builders_build_the_same_tree_as_solve() {
+    let grammar = make_statements_grammar();
+    let solver = GrammarSolver::new(grammar);
+    let expected = solve(&solver, statements());
+
+    let mut builder = ValueBuilder::new();
+    let (diagnostics, _) = solve_into(
+        &solver,
+        BufferedSource::new(statements()),
+        None,
+        &mut builder,
+        ErrorOptions::default(),
+    );
+    assert_eq!(diagnostics, expected.diagnostics);
+    assert_eq!(builder.finish(), expected.value);
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use std::{cell::Cell, collections::VecDeque};
+
+use common::input;
+use msyntax::{
+    demos::*,
+    diagnostics::ErrorOptions,
+    generate::sentences,
+    interpreter::{solve, solve_source, ITokenOrGroup, TokenSource},
+    matches::Token,
+    solver::GrammarSolver,
+};
+
+/// A token source that holds the interpreter to the lookahead it asked for: it never
+/// buffers more items than that, and fails if one past them is peeked at.
+struct StrictSource {
+    items: VecDeque<ITokenOrGroup>,
+    buffer: VecDeque<ITokenOrGroup>,
+    lookahead: Option<usize>,
+    furthest_peek: Cell<Option<usize>>,
+}
+
+impl StrictSource {
+    fn new(items: Vec<ITokenOrGroup>) -> Self {
+        Self {
+            items: items.into(),
+            buffer: VecDeque::new(),
+            lookahead: None,
+            furthest_peek: Cell::new(None),
+        }
+    }
+
+    fn fill(&mut self) {
+        let lookahead = self.lookahead.expect("Filled before the lookahead was set");
+        while self.buffer.len() < lookahead {
+            match self.items.pop_front() {
+                Some(item) => self.buffer.push_back(item),
+                None => break,
+            }
+        }
+        assert!(self.buffer.len() <= lookahead);
+    }
+}
+
+impl TokenSource for StrictSource {
+    fn set_lookahead(&mut self, lookahead: usize) {
+        assert!(self.lookahead.is_none(), "The lookahead was set twice");
+        self.lookahead = Some(lookahead);
+        self.fill();
+    }
+
+    fn peek(&self, k: usize) -> Option<&ITokenOrGroup> {
+        let lookahead = self.lookahead.expect("Peeked before the lookahead was set");
+        assert!(
+            k < lookahead,
+            "Peeked {} ahead with a lookahead of {}",
+            k,
+            lookahead
+        );
+
+        let furthest = self.furthest_peek.get().map_or(k, |f| f.max(k));
+        self.furthest_peek.set(Some(furthest));
+        self.buffer.get(k)
+    }
+
+    fn next(&mut self) -> Option<ITokenOrGroup> {
+        let item = self.buffer.pop_front();
+        self.fill();
+        item
+    }
+}
+
+#[test]
+fn the_interpreter_stays_within_its_lookahead() {
+    for grammar in [
+        make_calc_grammar(),
+        make_calc2_grammar(),
+        make_struct_fn_grammar(),
+        make_array_grammar(),
+        make_statements_grammar(),
+    ] {
+        let solver = GrammarSolver::new(grammar.clone());
+        let mut furthest_peek = 0;
+
+        let mut inputs = sentences(&grammar, 7, 20).items;
+        // Inputs with errors, which make the interpreter look for recovery matches
+        inputs.push(input(&[Token::Num, Token::Plus]));
+        inputs.push(input(&[Token::Name, Token::Eq, Token::Num, Token::Name]));
+        inputs.push(input(&[Token::Fn, Token::Semi, Token::Semi]));
+
+        for tokens in inputs {
+            let mut source = StrictSource::new(tokens.clone());
+            let result = solve_source(&solver, &mut source, ErrorOptions::default());
+            assert_eq!(result, solve(&solver, tokens));
+
+            assert_eq!(source.lookahead, Some(solver.max_lookahead()));
+            assert!(source.items.is_empty() && source.buffer.is_empty());
+            furthest_peek = furthest_peek.max(source.furthest_peek.get().unwrap());
+        }
+
+        // The whole lookahead is needed somewhere, so the checks above aren't vacuous
+        assert_eq!(furthest_peek + 1, solver.max_lookahead());
+    }
+}
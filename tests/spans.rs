@@ -0,0 +1,63 @@
+mod common;
+
+use common::input;
+use msyntax::{
+    demos::*,
+    diagnostics::ErrorOptions,
+    earley::EarleyParser,
+    glr::GlrParser,
+    interpreter::{
+        incremental::IncrementalParse, solve_into, solve_with_spans, BufferedSource, ValueBuilder,
+    },
+    lr::LrParser,
+    matches::Token,
+    peg::PegParser,
+    solver::GrammarSolver,
+    span::ByteRangesError,
+};
+
+#[test]
+fn too_few_byte_ranges_are_an_error() {
+    let grammar = make_calc_grammar();
+    let solver = GrammarSolver::new(grammar.clone());
+    let tokens = input(&[Token::Num, Token::Plus, Token::Num]);
+    let byte_ranges = [0..0, 0..1, 2..3];
+    let error = ByteRangesError {
+        tokens: 5,
+        byte_ranges: 3,
+    };
+
+    let options = ErrorOptions::default();
+    assert_eq!(
+        solve_with_spans(&solver, tokens.clone(), &byte_ranges, options),
+        Err(error.clone())
+    );
+    assert_eq!(
+        IncrementalParse::new(&solver, tokens.clone(), Some(&byte_ranges), options).err(),
+        Some(error.clone())
+    );
+    let parse_with_spans = [
+        PegParser::new(grammar.clone()).parse_with_spans(tokens.clone(), &byte_ranges),
+        LrParser::new(grammar.clone()).parse_with_spans(tokens.clone(), &byte_ranges),
+        EarleyParser::new(grammar.clone()).parse_with_spans(tokens.clone(), &byte_ranges),
+    ];
+    for result in parse_with_spans {
+        assert_eq!(result, Err(error.clone()));
+    }
+    assert!(GlrParser::new(grammar)
+        .parse_with_spans(tokens.clone(), &byte_ranges)
+        .is_err());
+
+    // The length of a token source isn't known up front, so its spans just lack the bytes
+    let mut builder = ValueBuilder::new();
+    solve_into(
+        &solver,
+        BufferedSource::new(tokens),
+        Some(&byte_ranges),
+        &mut builder,
+        options,
+    );
+    let value = builder.finish();
+    assert_eq!(value.span.bytes, None);
+    assert_eq!(value.values[0].span().bytes, Some(0..0));
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use common::input;
+use msyntax::{
+    demos::*,
+    interpreter::{
+        solve,
+        visit::{fold, walk_value_mut, Fold, RuleCallbacks, VisitorMut},
+        ITokenOrGroup, RuleValue, Value,
+    },
+    matches::{Rule, Token},
+    solver::GrammarSolver,
+    span::Span,
+};
+
+/// Counts the tokens below each node.
+struct CountTokens;
+
+impl Fold for CountTokens {
+    type Output = usize;
+
+    fn fold_rule(&mut self, _: &RuleValue, children: Vec<usize>) -> usize {
+        children.into_iter().sum()
+    }
+
+    fn fold_token(&mut self, _: Token, _: &Span) -> usize {
+        1
+    }
+
+    fn fold_error(&mut self, _: &Span) -> usize {
+        0
+    }
+}
+
+/// Turns every `Name` token into a `Num`, and replaces every expression by an error
+/// covering the same tokens.
+struct ErrorOutExprs;
+
+impl VisitorMut for ErrorOutExprs {
+    fn visit_value_mut(&mut self, value: &mut Value) {
+        match value {
+            Value::Rule(node) if node.rule == Rule::Expr => {
+                *value = Value::Error(node.span.clone());
+            }
+            _ => walk_value_mut(self, value),
+        }
+    }
+
+    fn visit_token_mut(&mut self, token: &mut Token, _: &mut Span) {
+        if *token == Token::Name {
+            *token = Token::Num;
+        }
+    }
+}
+
+fn statements() -> Vec<ITokenOrGroup> {
+    input(&[
+        Token::Name,
+        Token::Eq,
+        Token::Num,
+        Token::Semi,
+        Token::Name,
+        Token::Eq,
+        Token::Name,
+        Token::Semi,
+    ])
+}
+
+#[test]
+fn visitors_see_every_node() {
+    let grammar = make_statements_grammar();
+    let result = solve(&GrammarSolver::new(grammar), statements());
+
+    let mut exprs = 0;
+    let mut tokens = Vec::new();
+    RuleCallbacks::new()
+        .on(Rule::Expr, |_| exprs += 1)
+        .on_token(|token, _| tokens.push(token))
+        .run(&result.value);
+    assert_eq!(exprs, 2);
+    assert_eq!(tokens.len(), 10);
+
+    assert_eq!(fold(&mut CountTokens, &result.value), 10);
+}
+
+#[test]
+fn mutable_visitors_change_and_replace_values() {
+    let grammar = make_statements_grammar();
+    let mut value = solve(&GrammarSolver::new(grammar), statements()).value;
+    let span = value.span.clone();
+    ErrorOutExprs.visit_rule_mut(&mut value);
+
+    let mut exprs = 0;
+    let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+    RuleCallbacks::new()
+        .on(Rule::Expr, |_| exprs += 1)
+        .on_token(|token, _| tokens.push(token))
+        .on_error(|span| errors.push(span.clone()))
+        .run(&value);
+    assert_eq!(exprs, 0);
+    assert_eq!(errors.len(), 2);
+    assert_eq!(
+        tokens,
+        [
+            Token::Start,
+            Token::Num,
+            Token::Eq,
+            Token::Semi,
+            Token::Num,
+            Token::Eq,
+            Token::Semi,
+            Token::Eof,
+        ]
+    );
+
+    // The errors take the place of the expressions, so spans above them don't change
+    assert_eq!(value.span, span);
+    assert_eq!(errors[0].tokens, 3..4);
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use common::input;
+use msyntax::{
+    demos::*,
+    diagnostics::ErrorOptions,
+    interpreter::{solve, solve_with_spans},
+    matches::Token,
+    solver::GrammarSolver,
+    unparse::{unparse, unparse_text, TextOptions},
+};
+
+fn calc_text() -> TextOptions<'static> {
+    let mut text = TextOptions::new();
+    text.set_text(Token::Start, "");
+    text.set_text(Token::Eof, "");
+    text.set_text(Token::Num, "1");
+    text.set_text(Token::Plus, "+");
+    text.set_text(Token::Star, "*");
+    text
+}
+
+#[test]
+fn unparse_gives_back_the_tokens_and_the_source() {
+    let grammar = make_calc_grammar();
+    let solver = GrammarSolver::new(grammar.clone());
+    let tokens = input(&[Token::Num, Token::Plus, Token::Num]);
+
+    let result = solve(&solver, tokens.clone());
+    assert_eq!(unparse(&grammar, &result.value), tokens);
+    assert_eq!(unparse_text(&grammar, &result.value, &calc_text()), "1 + 1");
+
+    let source = "12 + 345";
+    let byte_ranges = [0..0, 0..2, 3..4, 5..8, 8..8];
+    let result = solve_with_spans(&solver, tokens, &byte_ranges, ErrorOptions::default()).unwrap();
+    let mut text = TextOptions::new();
+    text.source = Some(source);
+    text.set_text(Token::Start, "");
+    text.set_text(Token::Eof, "");
+    assert_eq!(unparse_text(&grammar, &result.value, &text), source);
+}